@@ -0,0 +1,30 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::Particle;
+use rlsl_math::prelude::*;
+
+// Only reads from the buffer, so it can be called with a `BufferRead`. A
+// `store` in here would be a compile error.
+fn advance(particles: &RuntimeArray<Particle>, index: u32) -> Particle {
+    let particle = particles.get(index);
+    Particle {
+        position: particle.position + particle.velocity,
+        velocity: particle.velocity,
+        mass: particle.mass,
+    }
+}
+
+#[spirv(compute)]
+fn compute(
+    compute: Compute,
+    // Decorated with `NonWritable`, `input.data.store(..)` is a compile error
+    input: BufferRead<N0, N0, RuntimeArray<Particle>>,
+    // Decorated with `NonReadable`
+    output: BufferWrite<N1, N0, RuntimeArray<Particle>>,
+) {
+    let index = compute.global_invocation_index.x;
+    output.data.store(index, advance(&input.data, index));
+}
+
+fn main() {}
@@ -0,0 +1,29 @@
+#![feature(custom_attribute)]
+// Built for SPIR-V 1.0, where buffers live in the `Uniform` storage class and
+// the block is decorated with `BufferBlock`:
+// RUSTC=rlsl cargo rustc --bin buffer-header-spv1 -- --target-env spv1.0
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::{Particle, Particles};
+use rlsl_math::prelude::*;
+
+// `Particles` ends with a `RuntimeArray`, which is why it is used as the
+// block itself
+#[spirv(compute)]
+fn compute(compute: Compute, particles: Buffer<N0, N0, Particles>) {
+    let index = compute.global_invocation_index.x;
+    if index < particles.data.particles.len() {
+        let time = particles.data.time;
+        let particle = particles.data.particles.get(index);
+        particles.data.particles.store(
+            index,
+            Particle {
+                position: particle.position + particle.velocity * time,
+                velocity: particle.velocity,
+                mass: particle.mass,
+            },
+        );
+    }
+}
+
+fn main() {}
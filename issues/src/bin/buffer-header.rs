@@ -0,0 +1,26 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::{Particle, Particles};
+use rlsl_math::prelude::*;
+
+#[spirv(compute)]
+fn compute(compute: Compute, particles: Buffer<N0, N0, Particles>) {
+    let index = compute.global_invocation_index.x;
+    // The header has two fields, `len` is member 2 of the block.
+    if index < particles.data.particles.len() {
+        let time = particles.data.time;
+        let particle = particles.data.particles.get(index);
+        let velocity = particle.velocity + particles.data.gravity * time;
+        particles.data.particles.store(
+            index,
+            Particle {
+                position: particle.position + velocity * time,
+                velocity,
+                mass: particle.mass,
+            },
+        );
+    }
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+#![feature(custom_attribute)]
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+#[spirv(FragmentOutputs)]
+pub struct GBuffer {
+    pub albedo: Output<N0, Vec4<f32>>,
+    pub normal: Output<N1, Vec4<f32>>,
+    pub depth: FragDepth,
+}
+
+#[spirv(fragment)]
+fn fragment(frag: Fragment, uv: Input<N0, Vec2<f32>>) -> GBuffer {
+    let uv = *uv;
+    GBuffer {
+        albedo: Output::new(uv.extend2(0.0, 1.0)),
+        normal: Output::new(Vec4::new(0.0, 0.0, 1.0, 0.0)),
+        depth: FragDepth::new(frag.frag_coord.z),
+    }
+}
+
+fn main() {}
@@ -0,0 +1,24 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::Surface;
+use rlsl_math::prelude::*;
+
+#[spirv(fragment)]
+fn fragment(
+    frag: Fragment,
+    material_index: Input<N0, u32, Flat>,
+    materials: UniformArray<N0, N0, [Surface; 16]>,
+    lights: BufferArray<N1, N0, RuntimeArray<Vec4<f32>>>,
+    light_index: Uniform<N2, N0, u32>,
+) -> Output<N0, Vec4<f32>> {
+    // Constant index
+    let ambient = lights.get(0);
+    // Dynamic but uniform index, not decorated
+    let light = lights.get(*light_index);
+    // Different per instance, decorated with `NonUniformEXT`
+    let material = materials.get_non_uniform(*material_index);
+    Output::new(material.color * material.roughness + ambient + light)
+}
+
+fn main() {}
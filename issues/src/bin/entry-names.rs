@@ -0,0 +1,15 @@
+#![feature(custom_attribute)]
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+#[spirv(vertex, name = "main")]
+fn vertex(vertex: &mut Vertex, pos: Input<N0, Vec4<f32>>) {
+    vertex.position = *pos;
+}
+
+#[spirv(fragment, name = "main")]
+fn fragment(frag: Fragment, color: Input<N0, Vec4<f32>>) -> Output<N0, Vec4<f32>> {
+    Output::new(*color)
+}
+
+fn main() {}
@@ -0,0 +1,35 @@
+#![feature(custom_attribute)]
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+// Contains an integer, which is why the varying has to be `Flat` as a whole
+pub struct Instance {
+    pub color: Vec4<f32>,
+    pub id: u32,
+    pub layers: [u32; 2],
+}
+
+#[spirv(vertex)]
+fn vertex(
+    vertex: &mut Vertex,
+    pos: Input<N0, Vec2<f32>>,
+    id: Input<N1, u32>,
+) -> Output<N0, Instance, Flat> {
+    vertex.position = pos.extend2(0.0, 1.0);
+    Output::new(Instance {
+        color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+        id: *id,
+        layers: [*id, *id + 1],
+    })
+}
+
+#[spirv(fragment)]
+fn fragment(frag: Fragment, instance: Input<N0, Instance, Flat>) -> Output<N0, Vec4<f32>> {
+    if instance.id == instance.layers[0] {
+        Output::new(instance.color)
+    } else {
+        Output::new(Vec4::new(0.0, 0.0, 0.0, 1.0))
+    }
+}
+
+fn main() {}
@@ -0,0 +1,28 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::{Camera, Light, Material, Particle};
+use rlsl_math::prelude::*;
+
+#[spirv(compute)]
+fn compute(
+    compute: Compute,
+    camera: Uniform<N0, N0, Camera>,
+    material: Uniform<N1, N0, Material>,
+    lights: Buffer<N2, N0, RuntimeArray<Light>>,
+    particles: Buffer<N0, N1, RuntimeArray<Particle>>,
+) {
+    let index = compute.global_invocation_index.x;
+    let light = lights.data.get(index);
+    let particle = particles.data.get(index);
+    particles.data.store(
+        index,
+        Particle {
+            position: particle.position + light.position + camera.data.position,
+            velocity: particle.velocity + material.data.color,
+            mass: particle.mass + material.data.roughness,
+        },
+    );
+}
+
+fn main() {}
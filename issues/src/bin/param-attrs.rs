@@ -0,0 +1,21 @@
+#![feature(custom_attribute, attr_literals)]
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+#[spirv(fragment)]
+#[spirv(param(uv, location = 5))]
+#[spirv(param(tint, set = 1, binding = 7))]
+#[spirv(param(time, set = 2, binding = 12))]
+#[spirv(output(0, location = 9))]
+fn fragment(
+    frag: Fragment,
+    uv: Input<FromAttr, Vec2<f32>>,
+    tint: Uniform<FromAttr, FromAttr, Vec4<f32>>,
+    time: Uniform<FromAttr, FromAttr, f32>,
+) -> Output<FromAttr, Vec4<f32>> {
+    let s = time.sin().abs();
+    let color = Vec4::new(uv.x * s, uv.y * s, 0.0, 1.0);
+    Output::new(color * *tint)
+}
+
+fn main() {}
@@ -0,0 +1,43 @@
+#![feature(custom_attribute)]
+// SPIR-V does not allow recursion, rlsl reports every cycle in the call
+// graph together with the calls that form it. Only built with
+// `--features unsupported`.
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+// error: `factorial` is recursive, which is not supported by rlsl
+fn factorial(n: u32) -> u32 {
+    if n == 0 {
+        1
+    } else {
+        n * factorial(n - 1)
+    }
+}
+
+// error: `is_even` is recursive, which is not supported by rlsl
+fn is_even(n: u32) -> bool {
+    if n == 0 {
+        true
+    } else {
+        is_odd(n - 1)
+    }
+}
+
+fn is_odd(n: u32) -> bool {
+    if n == 0 {
+        false
+    } else {
+        is_even(n - 1)
+    }
+}
+
+#[spirv(compute)]
+fn compute(compute: Compute, buffer: Buffer<N0, N0, RuntimeArray<u32>>) {
+    let index = compute.global_invocation_index.x;
+    let value = buffer.data.get(index);
+    if is_even(value) {
+        buffer.data.store(index, factorial(value));
+    }
+}
+
+fn main() {}
@@ -0,0 +1,22 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::ScalarParticle;
+use rlsl_math::prelude::*;
+
+#[spirv(compute)]
+fn compute(compute: Compute, particles: Buffer<N0, N0, RuntimeArray<ScalarParticle>>) {
+    let index = compute.global_invocation_index.x;
+    let particle = particles.data.get(index);
+    let position = particle.position + particle.velocity;
+    particles.data.store(
+        index,
+        ScalarParticle {
+            position,
+            mass: particle.mass,
+            velocity: particle.velocity,
+        },
+    );
+}
+
+fn main() {}
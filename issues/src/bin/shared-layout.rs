@@ -0,0 +1,29 @@
+#![feature(custom_attribute)]
+extern crate issues;
+extern crate rlsl_math;
+use issues::blocks::Material;
+use rlsl_math::prelude::*;
+
+// `Material` is laid out with std140 in the uniform and with std430 in the
+// buffers, each block gets a type of its own.
+#[spirv(compute)]
+fn compute(
+    compute: Compute,
+    material: Uniform<N0, N0, Material>,
+    input: Buffer<N1, N0, RuntimeArray<Material>>,
+    output: Buffer<N2, N0, RuntimeArray<Material>>,
+) {
+    let index = compute.global_invocation_index.x;
+    let other = input.data.get(index);
+    output.data.store(
+        index,
+        Material {
+            color: other.color + material.data.color,
+            roughness: other.roughness * material.data.roughness,
+            weights: material.data.weights,
+            normal: other.normal,
+        },
+    );
+}
+
+fn main() {}
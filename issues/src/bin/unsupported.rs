@@ -0,0 +1,64 @@
+#![feature(custom_attribute)]
+// Every function in here is rejected by rlsl before codegen, all errors are
+// reported at once. Only built with `--features unsupported`.
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+trait Shade {
+    fn shade(&self) -> f32;
+}
+
+impl Shade for f32 {
+    fn shade(&self) -> f32 {
+        *self
+    }
+}
+
+// error: `f64` is not supported by rlsl
+fn double(value: f32) -> f32 {
+    let value: f64 = 2.0;
+    1.0
+}
+
+// error: A cast is not supported by rlsl
+fn cast(value: u32) -> f32 {
+    value as f32
+}
+
+// error: A slice is not supported by rlsl
+fn sum(values: &[f32]) -> f32 {
+    values[0] + values[1]
+}
+
+// error: A raw pointer is not supported by rlsl
+fn raw(value: &f32) -> f32 {
+    let ptr = value as *const f32;
+    *value
+}
+
+// error: `dyn Trait` is not supported by rlsl
+fn dynamic(value: &Shade) -> f32 {
+    value.shade()
+}
+
+// error: Calling a function pointer is not supported by rlsl
+fn call(f: fn(f32) -> f32, value: f32) -> f32 {
+    f(value)
+}
+
+// error: A function that returns a reference and can not be inlined is not
+// supported by rlsl
+#[inline(never)]
+fn first(values: &[f32; 2]) -> &f32 {
+    &values[0]
+}
+
+#[spirv(fragment)]
+fn fragment(frag: Fragment, uv: Input<N0, Vec2<f32>>) -> Output<N0, Vec4<f32>> {
+    let values = [uv.x, uv.y];
+    let a = double(uv.x) + cast(1) + sum(&values) + raw(&uv.x);
+    let b = dynamic(&uv.y) + call(double, uv.x) + *first(&values);
+    Output::new(Vec4::new(a, b, 0.0, 1.0))
+}
+
+fn main() {}
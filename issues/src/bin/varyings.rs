@@ -0,0 +1,31 @@
+#![feature(custom_attribute)]
+extern crate rlsl_math;
+use rlsl_math::prelude::*;
+
+pub struct Varyings {
+    pub normal: Vec3<f32>,
+    pub uv: Vec2<f32>,
+    pub color: Vec4<f32>,
+}
+
+#[spirv(vertex)]
+fn vertex(
+    vertex: &mut Vertex,
+    pos: Input<N0, Vec2<f32>>,
+    uv: Input<N1, Vec2<f32>>,
+) -> Output<N0, Varyings> {
+    vertex.position = pos.extend2(0.0, 1.0);
+    Output::new(Varyings {
+        normal: Vec3::new(0.0, 0.0, 1.0),
+        uv: uv.data,
+        color: Vec4::new(1.0, 0.0, 0.0, 1.0),
+    })
+}
+
+#[spirv(fragment)]
+fn fragment(frag: Fragment, varyings: Input<N0, Varyings>) -> Output<N0, Vec4<f32>> {
+    let uv = varyings.uv;
+    Output::new(varyings.color * uv.x)
+}
+
+fn main() {}
@@ -0,0 +1,67 @@
+//! Types that are used inside of `Uniform` and `Buffer` blocks. With the
+//! `host` feature they implement `Std140` or `Std430`, which is used to check
+//! that the host and the compiler agree on their layout. Every type in here
+//! is compared in `rlsl-test`, shaders should not declare block types of
+//! their own.
+#[cfg(feature = "host")]
+use rlsl_layout::{Std140, Std430};
+use rlsl_math::prelude::*;
+
+#[cfg_attr(feature = "host", derive(Std140))]
+pub struct Camera {
+    pub view: Mat4<f32>,
+    pub position: Vec3<f32>,
+    pub time: f32,
+    pub resolution: Vec2<f32>,
+}
+
+#[cfg_attr(feature = "host", derive(Std140))]
+pub struct Material {
+    pub color: Vec3<f32>,
+    pub roughness: f32,
+    pub weights: [f32; 3],
+    pub normal: Mat3<f32>,
+}
+
+#[cfg_attr(feature = "host", derive(Std430))]
+pub struct Light {
+    pub position: Vec3<f32>,
+    pub radius: f32,
+    pub color: Vec3<f32>,
+    pub falloff: Vec2<f32>,
+}
+
+#[cfg_attr(feature = "host", derive(Std430))]
+pub struct Particle {
+    pub position: Vec3<f32>,
+    pub velocity: Vec3<f32>,
+    pub mass: f32,
+}
+
+/// A buffer with a header, the data is used as the block itself
+#[cfg_attr(feature = "host", derive(Std430))]
+#[repr(C)]
+pub struct Particles {
+    pub time: f32,
+    pub gravity: Vec3<f32>,
+    pub particles: RuntimeArray<Particle>,
+}
+
+/// The element of a `UniformArray`
+#[cfg_attr(feature = "host", derive(Std140))]
+#[repr(C)]
+pub struct Surface {
+    pub color: Vec4<f32>,
+    pub roughness: f32,
+}
+
+/// Laid out with `VK_EXT_scalar_block_layout`. The derived `Std430` only
+/// describes its shape, the host uses `LayoutRules::Scalar`.
+#[cfg_attr(feature = "host", derive(Std430))]
+#[spirv(layout = "scalar")]
+#[repr(C)]
+pub struct ScalarParticle {
+    pub position: Vec3<f32>,
+    pub mass: f32,
+    pub velocity: Vec3<f32>,
+}
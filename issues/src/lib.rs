@@ -1,6 +1,9 @@
-#![feature(try_trait)]
+#![feature(try_trait, custom_attribute)]
 extern crate rlsl_math;
+#[cfg(feature = "host")]
+extern crate rlsl_layout;
 use rlsl_math::prelude::*;
+pub mod blocks;
 pub mod ray;
 pub fn square(_: u32, val: f32) -> f32 {
     val * val
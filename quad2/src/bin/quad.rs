@@ -59,7 +59,7 @@ enum Command {
 impl Opt {
     pub fn get_shader_path(&self) -> PathBuf {
         match self.compiler {
-            ShaderCompiler::Rlsl => PathBuf::from("./../.shaders/"),
+            ShaderCompiler::Rlsl => PathBuf::from("./../target/debug/shaders/"),
             ShaderCompiler::Glsl => PathBuf::from("./../issues/.shaders-glsl/"),
         }
     }
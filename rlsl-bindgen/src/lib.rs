@@ -0,0 +1,510 @@
+//! Generates host bindings from the `.reflect.json` that rlsl writes next to
+//! every module. The reflection is created from the signatures of the entry
+//! points, so the bindings can not drift from the shader crate.
+//!
+//! ```ignore
+//! // build.rs
+//! extern crate rlsl_bindgen;
+//! use std::path::Path;
+//!
+//! fn main() {
+//!     let out_dir = std::env::var("OUT_DIR").unwrap();
+//!     rlsl_bindgen::generate(
+//!         "../target/debug/shaders/circle.reflect.json",
+//!         Path::new(&out_dir).join("circle.rs"),
+//!     ).unwrap();
+//! }
+//! ```
+//!
+//! The generated module is included with
+//! `include!(concat!(env!("OUT_DIR"), "/circle.rs"));` and expects
+//! `rlsl_layout`, `rlsl_math` and `rlsl_reflect` as `extern crate`s in the
+//! root of the host crate.
+extern crate rlsl_reflect;
+
+use rlsl_reflect::{Descriptor, DescriptorCount, EntryPoint, Reflection, Type};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+/// Reads the reflection at `reflection` and writes the bindings to `out`.
+/// Also tells cargo to rerun the build script if the reflection changes.
+pub fn generate<P: AsRef<Path>, Q: AsRef<Path>>(reflection: P, out: Q) -> io::Result<()> {
+    use std::io::Write;
+    let reflection_path = reflection.as_ref();
+    println!("cargo:rerun-if-changed={}", reflection_path.display());
+    let reflection = Reflection::load(reflection_path)?;
+    let source = generate_source(&reflection)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+    File::create(out)?.write_all(source.as_bytes())
+}
+
+/// Returns the source of the bindings, or an error if the reflection contains
+/// types that can not be represented on the host.
+pub fn generate_source(reflection: &Reflection) -> Result<String, String> {
+    let mut generator = Generator::default();
+    let mut entry_points = String::new();
+    for entry in &reflection.entry_points {
+        let shared_name = reflection
+            .entry_points
+            .iter()
+            .filter(|other| other.name == entry.name)
+            .count()
+            > 1;
+        let module_name = if shared_name {
+            format!("{:?}_{}", entry.stage, entry.name).to_lowercase()
+        } else {
+            entry.name.clone()
+        };
+        entry_points.push_str(&generator.entry_point(&module_name, entry)?);
+    }
+
+    let mut out = String::new();
+    writeln!(out, "// Generated by rlsl-bindgen, do not edit.").unwrap();
+    writeln!(
+        out,
+        "pub const DEVICE_EXTENSIONS: &[&str] = &{:?};\n",
+        reflection.device_extensions
+    ).unwrap();
+    writeln!(out, "/// The types that are used inside of blocks").unwrap();
+    writeln!(out, "pub mod types {{").unwrap();
+    for host_struct in generator.structs.values() {
+        writeln!(out, "    #[repr(C)]").unwrap();
+        let derives: Vec<_> = host_struct
+            .layouts
+            .iter()
+            .map(|layout| format!("::rlsl_layout::{}", layout))
+            .collect();
+        writeln!(
+            out,
+            "    #[derive(Debug, Clone, Copy{}{})]",
+            if derives.is_empty() { "" } else { ", " },
+            derives.join(", ")
+        ).unwrap();
+        writeln!(out, "    pub struct {} {{", host_struct.name).unwrap();
+        for &(ref field, ref ty) in &host_struct.fields {
+            writeln!(out, "        pub {}: {},", field, ty).unwrap();
+        }
+        writeln!(out, "    }}\n").unwrap();
+    }
+    writeln!(out, "}}\n").unwrap();
+    out.push_str(&entry_points);
+    Ok(out)
+}
+
+#[derive(Default)]
+struct HostStruct {
+    /// The last segment of the path of the struct
+    name: String,
+    fields: Vec<(String, String)>,
+    /// `Std140` and or `Std430`
+    layouts: BTreeSet<&'static str>,
+}
+
+#[derive(Default)]
+struct Generator {
+    /// Keyed by the full path of the struct
+    structs: BTreeMap<String, HostStruct>,
+}
+
+fn layout_trait(layout: Option<&str>) -> Option<&'static str> {
+    match layout {
+        Some("std140") => Some("Std140"),
+        Some("std430") => Some("Std430"),
+        _ => None,
+    }
+}
+
+impl Generator {
+    /// Returns the host type of `ty`. Structs are added to `types` and
+    /// derive `layout`.
+    fn host_ty(&mut self, ty: &Type, layout: Option<&'static str>) -> Result<String, String> {
+        match *ty {
+            Type::Bool => Ok("u32".into()),
+            Type::Int { width, signed } => Ok(format!("{}{}", if signed { "i" } else { "u" }, width)),
+            Type::Float { width } => Ok(format!("f{}", width)),
+            Type::Vector {
+                ref component,
+                count,
+            } => Ok(format!(
+                "::rlsl_math::vector::Vec{}<{}>",
+                count,
+                self.host_ty(component, layout)?
+            )),
+            Type::Matrix {
+                ref column,
+                columns,
+            } => match **column {
+                Type::Vector {
+                    ref component,
+                    count,
+                } if count == columns => Ok(format!(
+                    "::rlsl_math::matrix::Mat{}<{}>",
+                    columns,
+                    self.host_ty(component, layout)?
+                )),
+                _ => Err(format!("Only square matrices are supported, found {:?}", ty)),
+            },
+            Type::Array {
+                ref element,
+                length: Some(length),
+                ..
+            } => Ok(format!("[{}; {}]", self.host_ty(element, layout)?, length)),
+            Type::Array { length: None, .. } => {
+                Err("A runtime array can only be the data of a buffer".into())
+            }
+            Type::Struct {
+                ref name,
+                ref members,
+                ..
+            } => {
+                let path = name
+                    .as_ref()
+                    .ok_or_else(|| format!("The struct {:?} has no name", ty))?;
+                let name = path.split("::").last().unwrap_or(path).to_string();
+                // Every struct is generated into `types` by its name
+                if let Some(other) = self
+                    .structs
+                    .iter()
+                    .find(|&(other, host_struct)| other != path && host_struct.name == name)
+                    .map(|(other, _)| other)
+                {
+                    return Err(format!(
+                        "`{}` and `{}` would both be generated as `types::{}`, rename one of them",
+                        other, path, name
+                    ));
+                }
+                let mut fields = Vec::new();
+                for (idx, member) in members.iter().enumerate() {
+                    let field = member
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| format!("field{}", idx));
+                    fields.push((field, self.host_ty(&member.ty, layout)?));
+                }
+                let host_struct = self
+                    .structs
+                    .entry(path.clone())
+                    .or_insert_with(HostStruct::default);
+                host_struct.name = name.clone();
+                host_struct.fields = fields;
+                host_struct.layouts.extend(layout);
+                Ok(name)
+            }
+            ref ty => Err(format!("{:?} can not be used on the host", ty)),
+        }
+    }
+
+    fn descriptor_set(
+        &mut self,
+        set: u32,
+        descriptors: &[(&str, &Descriptor)],
+    ) -> Result<String, String> {
+        let mut fields = String::new();
+        let mut bindings = String::new();
+        let mut methods = String::new();
+        for &(kind, descriptor) in descriptors {
+            let name = descriptor
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("binding{}", descriptor.binding));
+            let count = match descriptor.count {
+                DescriptorCount::One => "Some(1)".to_string(),
+                DescriptorCount::Array(len) => format!("Some({})", len),
+                DescriptorCount::Runtime => "None".to_string(),
+            };
+            writeln!(
+                bindings,
+                "            DescriptorBinding {{ name: {:?}, set: {}, binding: {}, count: {}, kind: DescriptorKind::{} }},",
+                name, set, descriptor.binding, count, kind
+            ).unwrap();
+            // Images have no data that the host could write
+            if kind == "Image" {
+                continue;
+            }
+            let layout = layout_trait(descriptor.layout.as_ref().map(String::as_str));
+            let (ty, bytes) = match (&descriptor.ty, layout) {
+                (
+                    &Type::Array {
+                        ref element,
+                        length: None,
+                        ..
+                    },
+                    Some(layout),
+                ) => (
+                    format!("Vec<{}>", self.host_ty(element, Some(layout))?),
+                    Some(format!(
+                        "::rlsl_layout::{}_slice_bytes(VALUE)",
+                        layout.to_lowercase()
+                    )),
+                ),
+                (ty, Some(layout)) => (
+                    self.host_ty(ty, Some(layout))?,
+                    Some(format!(
+                        "::rlsl_layout::{}::{}_bytes(VALUE)",
+                        layout,
+                        layout.to_lowercase()
+                    )),
+                ),
+                (ty, None) => (self.host_ty(ty, None)?, None),
+            };
+            writeln!(
+                fields,
+                "        /// Binding {}, {}",
+                descriptor.binding,
+                descriptor
+                    .layout
+                    .as_ref()
+                    .map(|layout| format!("{} layout", layout))
+                    .unwrap_or_else(|| "layout is not known".into())
+            ).unwrap();
+            // An array of descriptors has one buffer per element
+            let is_array = descriptor.count != DescriptorCount::One;
+            if is_array {
+                writeln!(fields, "        pub {}: Vec<{}>,", name, ty).unwrap();
+            } else {
+                writeln!(fields, "        pub {}: {},", name, ty).unwrap();
+            }
+            if let Some(bytes) = bytes {
+                writeln!(
+                    methods,
+                    "        /// The bytes of `{}` in the layout that the shader expects",
+                    name
+                ).unwrap();
+                if is_array {
+                    writeln!(methods, "        pub fn {}_bytes(&self) -> Vec<Vec<u8>> {{", name).unwrap();
+                    writeln!(
+                        methods,
+                        "            self.{}.iter().map(|value| {}).collect()",
+                        name,
+                        bytes.replace("VALUE", "value")
+                    ).unwrap();
+                } else {
+                    writeln!(methods, "        pub fn {}_bytes(&self) -> Vec<u8> {{", name).unwrap();
+                    writeln!(
+                        methods,
+                        "            {}",
+                        bytes.replace("VALUE", &format!("&self.{}", name))
+                    ).unwrap();
+                }
+                writeln!(methods, "        }}").unwrap();
+            }
+        }
+
+        let mut out = String::new();
+        writeln!(out, "    /// Descriptor set {}", set).unwrap();
+        writeln!(out, "    #[derive(Debug, Clone)]").unwrap();
+        writeln!(out, "    pub struct Set{} {{", set).unwrap();
+        out.push_str(&fields);
+        writeln!(out, "    }}\n").unwrap();
+        writeln!(out, "    impl Set{} {{", set).unwrap();
+        writeln!(out, "        pub const SET: u32 = {};", set).unwrap();
+        writeln!(out, "        pub const BINDINGS: &'static [DescriptorBinding] = &[").unwrap();
+        out.push_str(&bindings);
+        writeln!(out, "        ];").unwrap();
+        out.push_str(&methods);
+        writeln!(out, "    }}\n").unwrap();
+        Ok(out)
+    }
+
+    /// The vertex inputs are tightly packed into a `Vertex` struct
+    fn vertex_input(&mut self, entry: &EntryPoint) -> Result<String, String> {
+        let mut fields = String::new();
+        let mut attributes = String::new();
+        let mut offset = 0;
+        for input in entry.inputs.iter().filter(|input| input.location.is_some()) {
+            let location = input.location.expect("location");
+            let shares_name = entry
+                .inputs
+                .iter()
+                .filter(|other| other.name == input.name)
+                .count()
+                > 1;
+            let name = match input.name {
+                Some(ref name) if !shares_name => name.clone(),
+                Some(ref name) => format!("{}_{}", name, location),
+                None => format!("location{}", location),
+            };
+            let (scalar, count) = match input.ty {
+                Type::Vector {
+                    ref component,
+                    count,
+                } => (&**component, count),
+                ref ty => (ty, 1),
+            };
+            let (scalar_ty, suffix) = match *scalar {
+                Type::Float { width: 32 } => ("f32", "Sfloat"),
+                Type::Int {
+                    width: 32,
+                    signed: false,
+                } => ("u32", "Uint"),
+                Type::Int {
+                    width: 32,
+                    signed: true,
+                } => ("i32", "Sint"),
+                ref ty => return Err(format!("{:?} can not be a vertex input", ty)),
+            };
+            let channels = ["R32", "G32", "B32", "A32"][..count as usize].concat();
+            let ty = if count == 1 {
+                scalar_ty.to_string()
+            } else {
+                format!("[{}; {}]", scalar_ty, count)
+            };
+            writeln!(fields, "        pub {}: {},", name, ty).unwrap();
+            writeln!(
+                attributes,
+                "        VertexAttribute {{ name: {:?}, location: {}, format: Format::{}{}, offset: {} }},",
+                name, location, channels, suffix, offset
+            ).unwrap();
+            offset += 4 * count;
+        }
+
+        let mut out = String::new();
+        writeln!(out, "    #[repr(C)]").unwrap();
+        writeln!(out, "    #[derive(Debug, Clone, Copy)]").unwrap();
+        writeln!(out, "    pub struct Vertex {{").unwrap();
+        out.push_str(&fields);
+        writeln!(out, "    }}\n").unwrap();
+        writeln!(out, "    pub const VERTEX_STRIDE: u32 = {};", offset).unwrap();
+        writeln!(out, "    pub const VERTEX_ATTRIBUTES: &[VertexAttribute] = &[").unwrap();
+        out.push_str(&attributes);
+        writeln!(out, "    ];\n").unwrap();
+        Ok(out)
+    }
+
+    fn entry_point(&mut self, module_name: &str, entry: &EntryPoint) -> Result<String, String> {
+        let mut out = String::new();
+        writeln!(out, "pub mod {} {{", module_name).unwrap();
+        writeln!(out, "    #![allow(unused_imports)]").unwrap();
+        writeln!(out, "    use super::types::*;").unwrap();
+        writeln!(
+            out,
+            "    use ::rlsl_reflect::host::{{DescriptorBinding, DescriptorKind, Format, VertexAttribute}};\n"
+        ).unwrap();
+        writeln!(out, "    pub const NAME: &str = {:?};", entry.name).unwrap();
+        writeln!(
+            out,
+            "    pub const STAGE: ::rlsl_reflect::Stage = ::rlsl_reflect::Stage::{:?};",
+            entry.stage
+        ).unwrap();
+        if let Some(size) = entry.workgroup_size {
+            writeln!(out, "    pub const WORKGROUP_SIZE: [u32; 3] = {:?};", size).unwrap();
+        }
+        writeln!(out).unwrap();
+
+        if entry.stage == rlsl_reflect::Stage::Vertex {
+            out.push_str(&self.vertex_input(entry)?);
+        }
+
+        let mut sets: BTreeMap<u32, Vec<(&str, &Descriptor)>> = BTreeMap::new();
+        let descriptors = entry
+            .uniforms
+            .iter()
+            .map(|descriptor| ("UniformBuffer", descriptor))
+            .chain(entry.buffers.iter().map(|descriptor| ("StorageBuffer", descriptor)))
+            .chain(entry.images.iter().map(|descriptor| ("Image", descriptor)));
+        for (kind, descriptor) in descriptors {
+            sets.entry(descriptor.set)
+                .or_insert_with(Vec::new)
+                .push((kind, descriptor));
+        }
+        for (set, descriptors) in &mut sets {
+            descriptors.sort_by_key(|&(_, descriptor)| descriptor.binding);
+            out.push_str(&self.descriptor_set(*set, descriptors)?);
+        }
+
+        for push_constant in &entry.push_constants {
+            // Push constants use the same layout as buffers
+            let ty = self.host_ty(&push_constant.ty, Some("Std430"))?;
+            writeln!(out, "    pub type PushConstants = {};\n", ty).unwrap();
+        }
+        writeln!(out, "}}\n").unwrap();
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rlsl_reflect::{InterfaceVariable, Member, Stage};
+
+    #[test]
+    fn fragment_with_uniform() {
+        let mut vertex = EntryPoint::new("vertex".into(), Stage::Vertex);
+        vertex.inputs.push(InterfaceVariable {
+            name: Some("uv".into()),
+            location: Some(0),
+            builtin: None,
+            ty: Type::Vector {
+                component: Box::new(Type::Float { width: 32 }),
+                count: 2,
+            },
+        });
+        let mut fragment = EntryPoint::new("fragment".into(), Stage::Fragment);
+        fragment.uniforms.push(Descriptor {
+            name: Some("light".into()),
+            set: 1,
+            binding: 2,
+            layout: Some("std140".into()),
+            count: DescriptorCount::One,
+            ty: Type::Struct {
+                name: Some("issues::Light".into()),
+                size: Some(16),
+                members: vec![Member {
+                    name: Some("intensity".into()),
+                    offset: Some(0),
+                    size: Some(4),
+                    matrix_stride: None,
+                    ty: Type::Float { width: 32 },
+                }],
+            },
+        });
+        let reflection = Reflection {
+            entry_points: vec![vertex, fragment],
+            device_extensions: Vec::new(),
+        };
+        let source = generate_source(&reflection).expect("source");
+        assert!(source.contains("#[derive(Debug, Clone, Copy, ::rlsl_layout::Std140)]"));
+        assert!(source.contains("pub struct Light {"));
+        assert!(source.contains("pub uv: [f32; 2],"));
+        assert!(source.contains("format: Format::R32G32Sfloat, offset: 0"));
+        assert!(source.contains("pub struct Set1 {"));
+        assert!(source.contains("pub light: Light,"));
+        assert!(source.contains("set: 1, binding: 2, count: Some(1), kind: DescriptorKind::UniformBuffer"));
+        assert!(source.contains("::rlsl_layout::Std140::std140_bytes(&self.light)"));
+    }
+
+    #[test]
+    fn struct_name_collision() {
+        let light = |path: &str| Type::Struct {
+            name: Some(path.into()),
+            size: Some(4),
+            members: vec![Member {
+                name: Some("intensity".into()),
+                offset: Some(0),
+                size: Some(4),
+                matrix_stride: None,
+                ty: Type::Float { width: 32 },
+            }],
+        };
+        let mut compute = EntryPoint::new("compute".into(), Stage::Compute);
+        for (binding, path) in ["a::Light", "b::Light"].iter().enumerate() {
+            compute.uniforms.push(Descriptor {
+                name: None,
+                set: 0,
+                binding: binding as u32,
+                layout: Some("std140".into()),
+                count: DescriptorCount::One,
+                ty: light(path),
+            });
+        }
+        let reflection = Reflection {
+            entry_points: vec![compute],
+            device_extensions: Vec::new(),
+        };
+        let err = generate_source(&reflection).expect_err("collision");
+        assert!(err.contains("`a::Light` and `b::Light`"));
+    }
+}
@@ -0,0 +1,91 @@
+// Generated by rlsl-bindgen, do not edit.
+pub const DEVICE_EXTENSIONS: &[&str] = &[];
+
+/// The types that are used inside of blocks
+pub mod types {
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, ::rlsl_layout::Std140)]
+    pub struct Light {
+        pub intensity: f32,
+        pub weights: [f32; 2],
+    }
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy, ::rlsl_layout::Std430)]
+    pub struct Particle {
+        pub mass: f32,
+        pub id: u32,
+    }
+
+}
+
+pub mod vertex {
+    #![allow(unused_imports)]
+    use super::types::*;
+    use ::rlsl_reflect::host::{DescriptorBinding, DescriptorKind, Format, VertexAttribute};
+
+    pub const NAME: &str = "vertex";
+    pub const STAGE: ::rlsl_reflect::Stage = ::rlsl_reflect::Stage::Vertex;
+
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Vertex {
+        pub position: [f32; 3],
+        pub uv: [f32; 2],
+    }
+
+    pub const VERTEX_STRIDE: u32 = 20;
+    pub const VERTEX_ATTRIBUTES: &[VertexAttribute] = &[
+        VertexAttribute { name: "position", location: 0, format: Format::R32G32B32Sfloat, offset: 0 },
+        VertexAttribute { name: "uv", location: 1, format: Format::R32G32Sfloat, offset: 12 },
+    ];
+
+    /// Descriptor set 0
+    #[derive(Debug, Clone)]
+    pub struct Set0 {
+        /// Binding 0, std140 layout
+        pub light: Light,
+    }
+
+    impl Set0 {
+        pub const SET: u32 = 0;
+        pub const BINDINGS: &'static [DescriptorBinding] = &[
+            DescriptorBinding { name: "light", set: 0, binding: 0, count: Some(1), kind: DescriptorKind::UniformBuffer },
+        ];
+        /// The bytes of `light` in the layout that the shader expects
+        pub fn light_bytes(&self) -> Vec<u8> {
+            ::rlsl_layout::Std140::std140_bytes(&self.light)
+        }
+    }
+
+}
+
+pub mod compute {
+    #![allow(unused_imports)]
+    use super::types::*;
+    use ::rlsl_reflect::host::{DescriptorBinding, DescriptorKind, Format, VertexAttribute};
+
+    pub const NAME: &str = "compute";
+    pub const STAGE: ::rlsl_reflect::Stage = ::rlsl_reflect::Stage::Compute;
+    pub const WORKGROUP_SIZE: [u32; 3] = [64, 1, 1];
+
+    /// Descriptor set 0
+    #[derive(Debug, Clone)]
+    pub struct Set0 {
+        /// Binding 1, std430 layout
+        pub particles: Vec<Particle>,
+    }
+
+    impl Set0 {
+        pub const SET: u32 = 0;
+        pub const BINDINGS: &'static [DescriptorBinding] = &[
+            DescriptorBinding { name: "particles", set: 0, binding: 1, count: Some(1), kind: DescriptorKind::StorageBuffer },
+        ];
+        /// The bytes of `particles` in the layout that the shader expects
+        pub fn particles_bytes(&self) -> Vec<u8> {
+            ::rlsl_layout::std430_slice_bytes(&self.particles)
+        }
+    }
+
+}
+
@@ -0,0 +1,57 @@
+//! Compiles the bindings of `fixtures/shader.reflect.json`. The generated
+//! source is checked in as `fixtures/bindings.rs`, run the tests with
+//! `RLSL_BINDGEN_BLESS=1` to update it after a change to the generator.
+extern crate rlsl_bindgen;
+extern crate rlsl_layout;
+extern crate rlsl_reflect;
+
+// Not every constant is used by the tests
+#[allow(dead_code)]
+mod bindings {
+    include!("fixtures/bindings.rs");
+}
+
+use bindings::types::{Light, Particle};
+use rlsl_reflect::Reflection;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+
+#[test]
+fn bindings_are_up_to_date() {
+    let reflection = Reflection::load("tests/fixtures/shader.reflect.json").expect("reflection");
+    let source = rlsl_bindgen::generate_source(&reflection).expect("source");
+    if env::var("RLSL_BINDGEN_BLESS").is_ok() {
+        File::create("tests/fixtures/bindings.rs")
+            .and_then(|mut file| file.write_all(source.as_bytes()))
+            .expect("write bindings");
+        return;
+    }
+    assert!(
+        source == include_str!("fixtures/bindings.rs"),
+        "The bindings are out of date, run the tests with `RLSL_BINDGEN_BLESS=1`"
+    );
+}
+
+#[test]
+fn vertex() {
+    assert_eq!(bindings::vertex::VERTEX_STRIDE, 20);
+    assert_eq!(bindings::vertex::VERTEX_ATTRIBUTES[1].offset, 12);
+    let set = bindings::vertex::Set0 {
+        light: Light {
+            intensity: 1.0,
+            weights: [0.5, 0.25],
+        },
+    };
+    assert_eq!(set.light_bytes().len(), 48);
+}
+
+#[test]
+fn compute() {
+    assert_eq!(bindings::compute::WORKGROUP_SIZE, [64, 1, 1]);
+    let set = bindings::compute::Set0 {
+        particles: vec![Particle { mass: 1.0, id: 0 }; 3],
+    };
+    assert_eq!(set.particles_bytes().len(), 24);
+    assert_eq!(bindings::compute::Set0::BINDINGS[0].binding, 1);
+}
@@ -0,0 +1,114 @@
+//! `#[derive(Std140)]` and `#[derive(Std430)]` for structs. `PhantomData`
+//! fields are skipped, in the same way as the compiler skips them.
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use]
+extern crate quote;
+#[macro_use]
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use syn::{Data, DeriveInput, Fields, Ident, Index, Type};
+
+#[proc_macro_derive(Std140)]
+pub fn derive_std140(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    impl_layout(&input, "Std140", "std140_ty", "write_std140").into()
+}
+
+#[proc_macro_derive(Std430)]
+pub fn derive_std430(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    impl_layout(&input, "Std430", "std430_ty", "write_std430").into()
+}
+
+fn is_phantom_data(ty: &Type) -> bool {
+    match *ty {
+        Type::Path(ref path) => path
+            .path
+            .segments
+            .iter()
+            .last()
+            .map(|segment| segment.ident == "PhantomData")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn impl_layout(
+    input: &DeriveInput,
+    trait_name: &str,
+    ty_fn: &str,
+    write_fn: &str,
+) -> proc_macro2::TokenStream {
+    let trait_name = Ident::new(trait_name, Span::call_site());
+    let ty_fn = Ident::new(ty_fn, Span::call_site());
+    let write_fn = Ident::new(write_fn, Span::call_site());
+    let name = &input.ident;
+
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => panic!("`{}` can only be derived for structs", trait_name),
+    };
+    let (accessors, tys): (Vec<_>, Vec<_>) = match *fields {
+        Fields::Named(ref fields) => fields
+            .named
+            .iter()
+            .filter(|field| !is_phantom_data(&field.ty))
+            .map(|field| {
+                let ident = field.ident.as_ref().expect("ident");
+                (quote!(#ident), &field.ty)
+            }).unzip(),
+        Fields::Unnamed(ref fields) => fields
+            .unnamed
+            .iter()
+            .enumerate()
+            .filter(|&(_, field)| !is_phantom_data(&field.ty))
+            .map(|(idx, field)| {
+                let idx = Index::from(idx);
+                (quote!(#idx), &field.ty)
+            }).unzip(),
+        Fields::Unit => (Vec::new(), Vec::new()),
+    };
+    // quote can only repeat iterators, the calls for every field are built
+    // up front
+    let member_tys: Vec<_> = tys
+        .iter()
+        .map(|ty| quote!(<#ty as ::rlsl_layout::#trait_name>::#ty_fn()))
+        .collect();
+    let member_writes: Vec<_> = accessors
+        .iter()
+        .zip(&tys)
+        .enumerate()
+        .map(|(idx, (accessor, ty))| {
+            let idx = Index::from(idx);
+            quote! {
+                <#ty as ::rlsl_layout::#trait_name>::#write_fn(
+                    &self.#accessor,
+                    &members[#idx],
+                    &mut bytes[offsets[#idx]..],
+                );
+            }
+        }).collect();
+
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::rlsl_layout::#trait_name));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics ::rlsl_layout::#trait_name for #name #ty_generics #where_clause {
+            fn #ty_fn() -> ::rlsl_layout::LayoutTy {
+                ::rlsl_layout::LayoutTy::Struct(vec![#(#member_tys),*])
+            }
+
+            fn #write_fn(&self, layout: &::rlsl_layout::Layout, bytes: &mut [u8]) {
+                let offsets = layout.offsets();
+                let members = layout.members();
+                #(#member_writes)*
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Host side traits that write data with the same layout that the compiler
+//! uses for `Uniform` and `Buffer` blocks.
+use {Layout, LayoutKind, LayoutRules, LayoutTy};
+
+/// Writes `bits` as little endian
+pub fn write_u32(bits: u32, bytes: &mut [u8]) {
+    for (i, byte) in bytes[..4].iter_mut().enumerate() {
+        *byte = (bits >> (8 * i)) as u8;
+    }
+}
+
+/// Returns the size of a scalar, vectors and matrices can only contain scalars.
+pub fn scalar_size(ty: &LayoutTy) -> usize {
+    match *ty {
+        LayoutTy::Scalar(size) => size,
+        ref ty => panic!("Expected a scalar, found {:?}", ty),
+    }
+}
+
+/// The layout of the elements of an array
+pub fn array_elem(layout: &Layout) -> (&Layout, usize) {
+    match layout.kind {
+        LayoutKind::Array { ref elem, stride } => (elem, stride),
+        ref kind => panic!("Expected an array, found {:?}", kind),
+    }
+}
+
+macro_rules! layout_trait {
+    (
+        $(#[$attr:meta])*
+        trait $name:ident, $rules:expr, $ty_fn:ident, $write_fn:ident,
+        $layout_fn:ident, $bytes_fn:ident, $slice_fn:ident
+    ) => {
+        $(#[$attr])*
+        pub trait $name {
+            /// Describes the shape of `Self`
+            fn $ty_fn() -> LayoutTy;
+
+            /// Writes `self` into `bytes`. `layout` has to be the layout of
+            /// `Self`.
+            fn $write_fn(&self, layout: &Layout, bytes: &mut [u8]);
+
+            fn $layout_fn() -> Layout {
+                Layout::new(&Self::$ty_fn(), $rules)
+            }
+
+            /// Returns the bytes of `self`, including the padding
+            fn $bytes_fn(&self) -> Vec<u8> {
+                let layout = Self::$layout_fn();
+                let mut bytes = vec![0; layout.size];
+                self.$write_fn(&layout, &mut bytes);
+                bytes
+            }
+        }
+
+        /// Returns the bytes of a `RuntimeArray` that contains `items`
+        pub fn $slice_fn<T: $name>(items: &[T]) -> Vec<u8> {
+            let ty = LayoutTy::RuntimeArray(Box::new(T::$ty_fn()));
+            let layout = Layout::new(&ty, $rules);
+            let (elem, stride) = array_elem(&layout);
+            let mut bytes = vec![0; stride * items.len()];
+            for (idx, item) in items.iter().enumerate() {
+                item.$write_fn(elem, &mut bytes[idx * stride..]);
+            }
+            bytes
+        }
+
+        layout_scalar!($name, $ty_fn, $write_fn, f32, |value: &f32| value.to_bits());
+        layout_scalar!($name, $ty_fn, $write_fn, u32, |value: &u32| *value);
+        layout_scalar!($name, $ty_fn, $write_fn, i32, |value: &i32| *value as u32);
+        layout_array!(
+            $name, $ty_fn, $write_fn,
+            1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+            17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+        );
+    };
+}
+
+macro_rules! layout_scalar {
+    ($name:ident, $ty_fn:ident, $write_fn:ident, $ty:ty, $to_bits:expr) => {
+        impl $name for $ty {
+            fn $ty_fn() -> LayoutTy {
+                LayoutTy::Scalar(4)
+            }
+
+            fn $write_fn(&self, _: &Layout, bytes: &mut [u8]) {
+                write_u32($to_bits(self), bytes);
+            }
+        }
+    };
+}
+
+macro_rules! layout_array {
+    ($name:ident, $ty_fn:ident, $write_fn:ident, $($len:expr)*) => {
+        $(
+            impl<T: $name> $name for [T; $len] {
+                fn $ty_fn() -> LayoutTy {
+                    LayoutTy::Array {
+                        elem: Box::new(T::$ty_fn()),
+                        len: $len,
+                    }
+                }
+
+                fn $write_fn(&self, layout: &Layout, bytes: &mut [u8]) {
+                    let (elem, stride) = array_elem(layout);
+                    for (idx, item) in self.iter().enumerate() {
+                        item.$write_fn(elem, &mut bytes[idx * stride..]);
+                    }
+                }
+            }
+        )*
+    };
+}
+
+layout_trait!(
+    /// Types that can be used inside of a `Uniform`. Can be derived with
+    /// `#[derive(Std140)]`.
+    trait Std140, LayoutRules::Std140, std140_ty, write_std140,
+    std140_layout, std140_bytes, std140_slice_bytes
+);
+
+layout_trait!(
+    /// Types that can be used inside of a `Buffer`. Can be derived with
+    /// `#[derive(Std430)]`.
+    trait Std430, LayoutRules::Std430, std430_ty, write_std430,
+    std430_layout, std430_bytes, std430_slice_bytes
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(value: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 4];
+        write_u32(value, &mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn array_padding() {
+        let bytes = [1.0f32, 2.0].std140_bytes();
+        assert_eq!(bytes.len(), 32);
+        assert_eq!(&bytes[16..20], &bits(2.0f32.to_bits())[..]);
+        assert_eq!([1.0f32, 2.0].std430_bytes().len(), 8);
+    }
+
+    #[test]
+    fn slice() {
+        let bytes = std430_slice_bytes(&[[1u32; 3], [2u32; 3]]);
+        assert_eq!(bytes.len(), 24);
+        assert_eq!(&bytes[12..16], &bits(2)[..]);
+    }
+}
@@ -0,0 +1,477 @@
+//! Computes the memory layout of types inside `Uniform` and `Buffer` blocks.
+//! The rules are described in section 7.6.2.2 "Standard Uniform Block Layout"
+//! of the OpenGL specification. std430 follows std140, except that the
+//! alignment and stride of arrays and structs are not rounded up to the
+//! alignment of a `vec4`. The scalar layout from `VK_EXT_scalar_block_layout`
+//! aligns every type to the size of its scalars, which results in the same
+//! offsets as a `#[repr(C)]` struct on the host.
+//!
+//! The same code is used by the compiler to decorate blocks and by the host,
+//! through `#[derive(Std140, Std430)]`, to write data that matches them.
+#[cfg(feature = "derive")]
+extern crate rlsl_layout_derive;
+#[cfg(feature = "math")]
+extern crate rlsl_math;
+
+#[cfg(feature = "derive")]
+pub use rlsl_layout_derive::{Std140, Std430};
+
+pub mod block;
+#[cfg(feature = "math")]
+mod math;
+
+pub use block::{std140_slice_bytes, std430_slice_bytes, Std140, Std430};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum LayoutRules {
+    /// Used for `Uniform` blocks
+    Std140,
+    /// Used for `Buffer` blocks
+    Std430,
+    /// Opt in with `#[spirv(layout = "scalar")]`
+    Scalar,
+}
+
+impl LayoutRules {
+    pub fn from_str(s: &str) -> Option<LayoutRules> {
+        match s {
+            "std140" => Some(LayoutRules::Std140),
+            "std430" => Some(LayoutRules::Std430),
+            "scalar" => Some(LayoutRules::Scalar),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LayoutRules::Std140 => "std140",
+            LayoutRules::Std430 => "std430",
+            LayoutRules::Scalar => "scalar",
+        }
+    }
+
+    /// std140 rounds the alignment of arrays and structs up to the alignment
+    /// of a `vec4`.
+    pub fn round_align(self, align: usize) -> usize {
+        match self {
+            LayoutRules::Std140 => round_up(align, 16),
+            LayoutRules::Std430 | LayoutRules::Scalar => align,
+        }
+    }
+}
+
+pub fn round_up(value: usize, align: usize) -> usize {
+    if align == 0 {
+        return value;
+    }
+    (value + align - 1) / align * align
+}
+
+/// Describes the shape of a type, without any layout information.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LayoutTy {
+    /// A scalar with the size in bytes
+    Scalar(usize),
+    Vector {
+        scalar: usize,
+        dim: usize,
+    },
+    /// A column major matrix
+    Matrix {
+        scalar: usize,
+        rows: usize,
+        cols: usize,
+    },
+    Array {
+        elem: Box<LayoutTy>,
+        len: usize,
+    },
+    /// An array without a length, which can only appear as the last member of
+    /// a `Buffer`.
+    RuntimeArray(Box<LayoutTy>),
+    Struct(Vec<LayoutTy>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutKind {
+    Scalar,
+    Vector,
+    Matrix {
+        stride: usize,
+    },
+    Array {
+        elem: Box<Layout>,
+        stride: usize,
+    },
+    Struct {
+        members: Vec<Layout>,
+        offsets: Vec<usize>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    /// The size in bytes. A `RuntimeArray` has a size of 0.
+    pub size: usize,
+    pub align: usize,
+    pub kind: LayoutKind,
+}
+
+impl Layout {
+    pub fn new(ty: &LayoutTy, rules: LayoutRules) -> Layout {
+        match *ty {
+            LayoutTy::Scalar(size) => Layout {
+                size,
+                align: size,
+                kind: LayoutKind::Scalar,
+            },
+            LayoutTy::Vector { scalar, dim } => {
+                // A vec3 has the alignment of a vec4, but keeps its size.
+                let align = match (rules, dim) {
+                    (LayoutRules::Scalar, _) => scalar,
+                    (_, 2) => 2 * scalar,
+                    (_, 3) | (_, 4) => 4 * scalar,
+                    _ => panic!("Vector with {} components", dim),
+                };
+                Layout {
+                    size: dim * scalar,
+                    align,
+                    kind: LayoutKind::Vector,
+                }
+            }
+            LayoutTy::Matrix { scalar, rows, cols } => {
+                // A matrix is stored like an array of its columns
+                let column = LayoutTy::Vector { scalar, dim: rows };
+                let array = Layout::array(&column, Some(cols), rules);
+                let stride = array.array_stride().expect("stride");
+                Layout {
+                    size: array.size,
+                    align: array.align,
+                    kind: LayoutKind::Matrix { stride },
+                }
+            }
+            LayoutTy::Array { ref elem, len } => Layout::array(elem, Some(len), rules),
+            LayoutTy::RuntimeArray(ref elem) => Layout::array(elem, None, rules),
+            LayoutTy::Struct(ref members) => {
+                let members: Vec<_> = members
+                    .iter()
+                    .map(|member| Layout::new(member, rules))
+                    .collect();
+                let align = rules.round_align(
+                    members
+                        .iter()
+                        .map(|member| member.align)
+                        .max()
+                        .unwrap_or(0),
+                );
+                let mut offset = 0;
+                let offsets = members
+                    .iter()
+                    .map(|member| {
+                        let member_offset = round_up(offset, member.align);
+                        offset = member_offset + member.size;
+                        member_offset
+                    }).collect();
+                Layout {
+                    // A struct is padded at the end to a multiple of its alignment
+                    size: round_up(offset, align),
+                    align,
+                    kind: LayoutKind::Struct { members, offsets },
+                }
+            }
+        }
+    }
+
+    fn array(elem: &LayoutTy, len: Option<usize>, rules: LayoutRules) -> Layout {
+        let elem = Layout::new(elem, rules);
+        let align = rules.round_align(elem.align);
+        let stride = round_up(elem.size, align);
+        Layout {
+            size: stride * len.unwrap_or(0),
+            align,
+            kind: LayoutKind::Array {
+                elem: Box::new(elem),
+                stride,
+            },
+        }
+    }
+
+    /// The offsets of the members of a struct
+    pub fn offsets(&self) -> Vec<usize> {
+        match self.kind {
+            LayoutKind::Struct { ref offsets, .. } => offsets.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// The stride between two elements, if this is an array
+    pub fn array_stride(&self) -> Option<usize> {
+        match self.kind {
+            LayoutKind::Array { stride, .. } => Some(stride),
+            _ => None,
+        }
+    }
+
+    /// The layout of the elements, if this is an array
+    pub fn elem(&self) -> Option<&Layout> {
+        match self.kind {
+            LayoutKind::Array { ref elem, .. } => Some(elem),
+            _ => None,
+        }
+    }
+
+    /// The layouts of the members of a struct
+    pub fn members(&self) -> &[Layout] {
+        match self.kind {
+            LayoutKind::Struct { ref members, .. } => members,
+            _ => &[],
+        }
+    }
+
+    /// The stride between two columns, if this is a matrix or an array of
+    /// matrices.
+    pub fn matrix_stride(&self) -> Option<usize> {
+        match self.kind {
+            LayoutKind::Matrix { stride } => Some(stride),
+            LayoutKind::Array { ref elem, .. } => elem.matrix_stride(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LayoutRules::{Scalar, Std140, Std430};
+    use super::*;
+
+    fn float() -> LayoutTy {
+        LayoutTy::Scalar(4)
+    }
+    fn vec(dim: usize) -> LayoutTy {
+        LayoutTy::Vector { scalar: 4, dim }
+    }
+    fn mat(cols: usize) -> LayoutTy {
+        LayoutTy::Matrix {
+            scalar: 4,
+            rows: cols,
+            cols,
+        }
+    }
+    fn array(elem: LayoutTy, len: usize) -> LayoutTy {
+        LayoutTy::Array {
+            elem: Box::new(elem),
+            len,
+        }
+    }
+
+    /// (description, type, rules, offsets, size, align)
+    fn struct_cases() -> Vec<(&'static str, LayoutTy, LayoutRules, Vec<usize>, usize, usize)> {
+        let particle = LayoutTy::Struct(vec![vec(3), float()]);
+        let inner = LayoutTy::Struct(vec![float()]);
+        vec![
+            ("float, vec3", LayoutTy::Struct(vec![float(), vec(3)]), Std140, vec![0, 16], 32, 16),
+            ("float, vec3", LayoutTy::Struct(vec![float(), vec(3)]), Std430, vec![0, 16], 32, 16),
+            ("vec3, float", LayoutTy::Struct(vec![vec(3), float()]), Std140, vec![0, 12], 16, 16),
+            ("vec3, float", LayoutTy::Struct(vec![vec(3), float()]), Std430, vec![0, 12], 16, 16),
+            ("vec3, vec3", LayoutTy::Struct(vec![vec(3), vec(3)]), Std430, vec![0, 16], 32, 16),
+            (
+                "vec2, vec3, float",
+                LayoutTy::Struct(vec![vec(2), vec(3), float()]),
+                Std430,
+                vec![0, 16, 28],
+                32,
+                16,
+            ),
+            ("float, vec2", LayoutTy::Struct(vec![float(), vec(2)]), Std430, vec![0, 8], 16, 8),
+            ("float, vec2", LayoutTy::Struct(vec![float(), vec(2)]), Std140, vec![0, 8], 16, 16),
+            (
+                "float, float[2], vec2",
+                LayoutTy::Struct(vec![float(), array(float(), 2), vec(2)]),
+                Std140,
+                vec![0, 16, 48],
+                64,
+                16,
+            ),
+            (
+                "float, float[2], vec2",
+                LayoutTy::Struct(vec![float(), array(float(), 2), vec(2)]),
+                Std430,
+                vec![0, 4, 16],
+                24,
+                8,
+            ),
+            (
+                "float, Inner, float",
+                LayoutTy::Struct(vec![float(), inner.clone(), float()]),
+                Std140,
+                vec![0, 16, 32],
+                48,
+                16,
+            ),
+            (
+                "float, Inner, float",
+                LayoutTy::Struct(vec![float(), inner.clone(), float()]),
+                Std430,
+                vec![0, 4, 8],
+                12,
+                4,
+            ),
+            (
+                "float, mat4",
+                LayoutTy::Struct(vec![float(), mat(4)]),
+                Std430,
+                vec![0, 16],
+                80,
+                16,
+            ),
+            (
+                "float, mat2",
+                LayoutTy::Struct(vec![float(), mat(2)]),
+                Std140,
+                vec![0, 16],
+                48,
+                16,
+            ),
+            (
+                "float, mat2",
+                LayoutTy::Struct(vec![float(), mat(2)]),
+                Std430,
+                vec![0, 8],
+                24,
+                8,
+            ),
+            ("float, vec3", LayoutTy::Struct(vec![float(), vec(3)]), Scalar, vec![0, 4], 16, 4),
+            ("vec3, float", LayoutTy::Struct(vec![vec(3), float()]), Scalar, vec![0, 12], 16, 4),
+            ("float, vec2", LayoutTy::Struct(vec![float(), vec(2)]), Scalar, vec![0, 4], 12, 4),
+            (
+                "float, float[2], vec2",
+                LayoutTy::Struct(vec![float(), array(float(), 2), vec(2)]),
+                Scalar,
+                vec![0, 4, 12],
+                20,
+                4,
+            ),
+            (
+                "float, mat3, float",
+                LayoutTy::Struct(vec![float(), mat(3), float()]),
+                Scalar,
+                vec![0, 4, 40],
+                44,
+                4,
+            ),
+            (
+                "uint, Particle[]",
+                LayoutTy::Struct(vec![float(), LayoutTy::RuntimeArray(Box::new(particle))]),
+                Std430,
+                vec![0, 16],
+                16,
+                16,
+            ),
+        ]
+    }
+
+    /// (description, type, rules, array stride, size)
+    fn array_cases() -> Vec<(&'static str, LayoutTy, LayoutRules, usize, usize)> {
+        vec![
+            ("float[3]", array(float(), 3), Std140, 16, 48),
+            ("float[3]", array(float(), 3), Std430, 4, 12),
+            ("vec2[2]", array(vec(2), 2), Std140, 16, 32),
+            ("vec2[2]", array(vec(2), 2), Std430, 8, 16),
+            ("vec3[2]", array(vec(3), 2), Std140, 16, 32),
+            ("vec3[2]", array(vec(3), 2), Std430, 16, 32),
+            ("vec4[2]", array(vec(4), 2), Std430, 16, 32),
+            ("vec3[2]", array(vec(3), 2), Scalar, 12, 24),
+            ("vec3[]", LayoutTy::RuntimeArray(Box::new(vec(3))), Scalar, 12, 0),
+            ("vec3[]", LayoutTy::RuntimeArray(Box::new(vec(3))), Std430, 16, 0),
+            ("float[]", LayoutTy::RuntimeArray(Box::new(float())), Std430, 4, 0),
+            (
+                "{vec3, float}[2]",
+                array(LayoutTy::Struct(vec![vec(3), float()]), 2),
+                Std430,
+                16,
+                32,
+            ),
+            (
+                "{float}[2]",
+                array(LayoutTy::Struct(vec![float()]), 2),
+                Std430,
+                4,
+                8,
+            ),
+            (
+                "{float}[2]",
+                array(LayoutTy::Struct(vec![float()]), 2),
+                Std140,
+                16,
+                32,
+            ),
+        ]
+    }
+
+    /// (description, type, rules, matrix stride, size)
+    fn matrix_cases() -> Vec<(&'static str, LayoutTy, LayoutRules, usize, usize)> {
+        vec![
+            ("mat2", mat(2), Std140, 16, 32),
+            ("mat2", mat(2), Std430, 8, 16),
+            ("mat3", mat(3), Std140, 16, 48),
+            ("mat3", mat(3), Std430, 16, 48),
+            ("mat4", mat(4), Std140, 16, 64),
+            ("mat4", mat(4), Std430, 16, 64),
+            ("mat2[2]", array(mat(2), 2), Std430, 8, 32),
+            ("mat3", mat(3), Scalar, 12, 36),
+            ("mat4", mat(4), Scalar, 16, 64),
+        ]
+    }
+
+    #[test]
+    fn scalar_layout_vectors() {
+        for &(dim, size) in &[(2, 8), (3, 12), (4, 16)] {
+            let layout = Layout::new(&vec(dim), Scalar);
+            assert_eq!((layout.size, layout.align), (size, 4));
+        }
+    }
+
+    #[test]
+    fn scalar_and_vector() {
+        let cases = vec![
+            (float(), 4, 4),
+            (vec(2), 8, 8),
+            (vec(3), 12, 16),
+            (vec(4), 16, 16),
+        ];
+        for (ty, size, align) in cases {
+            for &rules in &[Std140, Std430] {
+                let layout = Layout::new(&ty, rules);
+                assert_eq!((layout.size, layout.align), (size, align), "{:?}", ty);
+            }
+        }
+    }
+
+    #[test]
+    fn structs() {
+        for (name, ty, rules, offsets, size, align) in struct_cases() {
+            let layout = Layout::new(&ty, rules);
+            assert_eq!(layout.offsets(), offsets, "offsets of {} {:?}", name, rules);
+            assert_eq!(layout.size, size, "size of {} {:?}", name, rules);
+            assert_eq!(layout.align, align, "align of {} {:?}", name, rules);
+        }
+    }
+
+    #[test]
+    fn arrays() {
+        for (name, ty, rules, stride, size) in array_cases() {
+            let layout = Layout::new(&ty, rules);
+            assert_eq!(layout.array_stride(), Some(stride), "stride of {} {:?}", name, rules);
+            assert_eq!(layout.size, size, "size of {} {:?}", name, rules);
+        }
+    }
+
+    #[test]
+    fn matrices() {
+        for (name, ty, rules, stride, size) in matrix_cases() {
+            let layout = Layout::new(&ty, rules);
+            assert_eq!(layout.matrix_stride(), Some(stride), "stride of {} {:?}", name, rules);
+            assert_eq!(layout.size, size, "size of {} {:?}", name, rules);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Implements `Std140` and `Std430` for the vectors, matrices and runtime
+//! arrays of `rlsl_math`.
+use block::{scalar_size, Std140, Std430};
+use rlsl_math::entry::RuntimeArray;
+use rlsl_math::matrix::{Mat2, Mat3, Mat4};
+use rlsl_math::vector::{Vec2, Vec3, Vec4};
+use {Layout, LayoutKind, LayoutTy};
+
+macro_rules! layout_math {
+    ($name:ident, $ty_fn:ident, $write_fn:ident, $layout_fn:ident) => {
+        layout_math!(@vec $name, $ty_fn, $write_fn, Vec2, 2, x y);
+        layout_math!(@vec $name, $ty_fn, $write_fn, Vec3, 3, x y z);
+        layout_math!(@vec $name, $ty_fn, $write_fn, Vec4, 4, x y z w);
+        layout_math!(@mat $name, $ty_fn, $write_fn, $layout_fn, Mat2, Vec2, 2, x y);
+        layout_math!(@mat $name, $ty_fn, $write_fn, $layout_fn, Mat3, Vec3, 3, x y z);
+        layout_math!(@mat $name, $ty_fn, $write_fn, $layout_fn, Mat4, Vec4, 4, x y z w);
+    };
+    (@vec $name:ident, $ty_fn:ident, $write_fn:ident, $vec:ident, $dim:expr, $($field:ident)*) => {
+        impl<T: $name> $name for $vec<T> {
+            fn $ty_fn() -> LayoutTy {
+                LayoutTy::Vector {
+                    scalar: scalar_size(&T::$ty_fn()),
+                    dim: $dim,
+                }
+            }
+
+            fn $write_fn(&self, _: &Layout, bytes: &mut [u8]) {
+                let scalar = scalar_size(&T::$ty_fn());
+                let scalar_layout = Layout::new(&T::$ty_fn(), ::LayoutRules::Std430);
+                let components = [$(&self.$field),*];
+                for (idx, component) in components.iter().enumerate() {
+                    component.$write_fn(&scalar_layout, &mut bytes[idx * scalar..]);
+                }
+            }
+        }
+    };
+    (
+        @mat $name:ident, $ty_fn:ident, $write_fn:ident, $layout_fn:ident,
+        $mat:ident, $vec:ident, $cols:expr, $($field:ident)*
+    ) => {
+        impl<T: $name> $name for $mat<T> {
+            fn $ty_fn() -> LayoutTy {
+                match $vec::<T>::$ty_fn() {
+                    LayoutTy::Vector { scalar, dim } => LayoutTy::Matrix {
+                        scalar,
+                        rows: dim,
+                        cols: $cols,
+                    },
+                    _ => unreachable!(),
+                }
+            }
+
+            fn $write_fn(&self, layout: &Layout, bytes: &mut [u8]) {
+                let stride = match layout.kind {
+                    LayoutKind::Matrix { stride } => stride,
+                    ref kind => panic!("Expected a matrix, found {:?}", kind),
+                };
+                let column = $vec::<T>::$layout_fn();
+                let columns = [$(&self.$field),*];
+                for (idx, col) in columns.iter().enumerate() {
+                    col.$write_fn(&column, &mut bytes[idx * stride..]);
+                }
+            }
+        }
+    };
+}
+
+layout_math!(Std140, std140_ty, write_std140, std140_layout);
+layout_math!(Std430, std430_ty, write_std430, std430_layout);
+
+/// A `RuntimeArray` can only be the last member of a buffer. It holds no
+/// data on the host, the elements are written with `std430_slice_bytes`
+/// after the header.
+impl<T: Std430> Std430 for RuntimeArray<T> {
+    fn std430_ty() -> LayoutTy {
+        LayoutTy::RuntimeArray(Box::new(T::std430_ty()))
+    }
+
+    fn write_std430(&self, _: &Layout, _: &mut [u8]) {}
+}
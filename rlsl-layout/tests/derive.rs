@@ -0,0 +1,73 @@
+//! Runs `#[derive(Std140, Std430)]` on structs and checks the layout and the
+//! bytes that they write.
+extern crate rlsl_layout;
+
+use rlsl_layout::{Std140, Std430};
+use std::marker::PhantomData;
+
+#[derive(Std140, Std430)]
+struct Light {
+    position: [f32; 3],
+    radius: f32,
+    weights: [u32; 2],
+    _marker: PhantomData<u32>,
+}
+
+#[derive(Std140, Std430)]
+struct Pair(u32, [f32; 2]);
+
+#[derive(Std140)]
+struct Wrapper<T> {
+    count: u32,
+    value: T,
+}
+
+fn light() -> Light {
+    Light {
+        position: [1.0, 2.0, 3.0],
+        radius: 4.0,
+        weights: [5, 6],
+        _marker: PhantomData,
+    }
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+    (0..4).fold(0, |bits, i| bits | (bytes[offset + i] as u32) << (8 * i))
+}
+
+#[test]
+fn std140() {
+    let layout = Light::std140_layout();
+    assert_eq!(layout.offsets(), vec![0, 48, 64]);
+    assert_eq!(layout.size, 96);
+    let bytes = light().std140_bytes();
+    assert_eq!(u32_at(&bytes, 16), 2.0f32.to_bits());
+    assert_eq!(u32_at(&bytes, 48), 4.0f32.to_bits());
+    assert_eq!(u32_at(&bytes, 80), 6);
+}
+
+#[test]
+fn std430() {
+    let layout = Light::std430_layout();
+    assert_eq!(layout.offsets(), vec![0, 12, 16]);
+    assert_eq!(layout.size, 24);
+    let bytes = light().std430_bytes();
+    assert_eq!(u32_at(&bytes, 8), 3.0f32.to_bits());
+    assert_eq!(u32_at(&bytes, 20), 6);
+}
+
+#[test]
+fn tuple_struct() {
+    assert_eq!(Pair::std430_layout().offsets(), vec![0, 4]);
+    let bytes = Pair(7, [1.0, 2.0]).std140_bytes();
+    assert_eq!(u32_at(&bytes, 0), 7);
+    assert_eq!(u32_at(&bytes, 32), 2.0f32.to_bits());
+}
+
+#[test]
+fn generic_struct() {
+    let layout = Wrapper::<Pair>::std140_layout();
+    assert_eq!(layout.offsets(), vec![0, 16]);
+    let bytes = Wrapper { count: 1, value: Pair(2, [3.0, 4.0]) }.std140_bytes();
+    assert_eq!(u32_at(&bytes, 16), 2);
+}
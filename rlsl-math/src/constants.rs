@@ -9,10 +9,34 @@ pub enum N1 {}
 #[spirv(Const2)]
 pub enum N2 {}
 
-#[spirv(Const2)]
+#[spirv(Const3)]
 pub enum N3 {}
 
+#[spirv(Const4)]
+pub enum N4 {}
+
+#[spirv(Const5)]
+pub enum N5 {}
+
+#[spirv(Const6)]
+pub enum N6 {}
+
+#[spirv(Const7)]
+pub enum N7 {}
+
+/// The location or binding is set with an attribute on the entry point,
+/// like `#[spirv(param(uv, location = 5))]`,
+/// `#[spirv(param(tex, set = 1, binding = 7))]` or
+/// `#[spirv(output(0, location = 9))]`.
+#[spirv(ConstFromAttr)]
+pub enum FromAttr {}
+
 impl Constant for N0 {}
 impl Constant for N1 {}
 impl Constant for N2 {}
 impl Constant for N3 {}
+impl Constant for N4 {}
+impl Constant for N5 {}
+impl Constant for N6 {}
+impl Constant for N7 {}
+impl Constant for FromAttr {}
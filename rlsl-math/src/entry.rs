@@ -29,10 +29,37 @@ pub struct Compute {
     pub global_invocation_index: Vec3<u32>,
 }
 
+/// Interpolation qualifier of an `Input` or `Output`. Integer varyings that
+/// are passed to a fragment shader have to be `Flat`. Only the outputs of a
+/// vertex shader and the inputs of a fragment shader can be interpolated.
+pub trait Interpolation {}
+
+#[spirv(Smooth)]
+pub enum Smooth {}
+
+#[spirv(Flat)]
+pub enum Flat {}
+
+#[spirv(NoPerspective)]
+pub enum NoPerspective {}
+
+#[spirv(Centroid)]
+pub enum Centroid {}
+
+#[spirv(Sample)]
+pub enum Sample {}
+
+impl Interpolation for Smooth {}
+impl Interpolation for Flat {}
+impl Interpolation for NoPerspective {}
+impl Interpolation for Centroid {}
+impl Interpolation for Sample {}
+
 #[spirv(Input)]
-pub struct Input<Location: Sized, T> {
+pub struct Input<Location: Sized, T, I: Interpolation = Smooth> {
     pub data: T,
     pub _location: PhantomData<Location>,
+    pub _interpolation: PhantomData<I>,
 }
 
 // impl<LInput, LOutput, T> From<Input<LInput, T>> for Output<LOutput, T> {
@@ -40,7 +67,7 @@ pub struct Input<Location: Sized, T> {
 //         Output::new(data: input.data)
 //     }
 // }
-impl<Location, T> Deref for Input<Location, T> {
+impl<Location, T, I: Interpolation> Deref for Input<Location, T, I> {
     type Target = T;
     #[inline(always)]
     fn deref(&self) -> &T {
@@ -49,12 +76,13 @@ impl<Location, T> Deref for Input<Location, T> {
 }
 
 #[spirv(Output)]
-pub struct Output<Location: Sized, T> {
+pub struct Output<Location: Sized, T, I: Interpolation = Smooth> {
     pub data: T,
     pub _location: PhantomData<Location>,
+    pub _interpolation: PhantomData<I>,
 }
 
-impl<Location, T> Deref for Output<Location, T> {
+impl<Location, T, I: Interpolation> Deref for Output<Location, T, I> {
     type Target = T;
     #[inline(always)]
     fn deref(&self) -> &T {
@@ -62,14 +90,40 @@ impl<Location, T> Deref for Output<Location, T> {
     }
 }
 
-impl<Location, T> Output<Location, T> {
-    pub fn new(data: T) -> Output<Location, T> {
+impl<Location, T, I: Interpolation> Output<Location, T, I> {
+    pub fn new(data: T) -> Output<Location, T, I> {
         Output {
             _location: PhantomData,
+            _interpolation: PhantomData,
             data,
         }
     }
 }
+/// Writes the depth of the fragment. Can be returned from a fragment shader
+/// as a member of a `#[spirv(FragmentOutputs)]` struct or of a tuple.
+#[spirv(FragDepth)]
+pub struct FragDepth {
+    pub depth: f32,
+}
+
+impl FragDepth {
+    pub fn new(depth: f32) -> FragDepth {
+        FragDepth { depth }
+    }
+}
+
+/// Writes the coverage mask of the fragment.
+#[spirv(SampleMask)]
+pub struct SampleMask {
+    pub mask: [u32; 1],
+}
+
+impl SampleMask {
+    pub fn new(mask: u32) -> SampleMask {
+        SampleMask { mask: [mask] }
+    }
+}
+
 #[spirv(RuntimeArray)]
 pub struct RuntimeArray<T> {
     pub _m: PhantomData<T>,
@@ -80,20 +134,26 @@ pub struct RuntimeArray<T> {
 fn runtime_array_get<T, R>(data: T, index: u32) -> R {
     unsafe { ::std::intrinsics::abort() }
 }
-#[inline(never)]
-#[spirv(runtime_array_store)]
-fn runtime_array_store<T, T1>(data: T, index: u32, value: T1) {
-    unsafe {
-        ::std::intrinsics::abort();
-    }
-}
 
 impl<T> RuntimeArray<T> {
     pub fn get(&self, index: u32) -> T {
         runtime_array_get(self, index)
     }
+    /// `store` is an intrinsic, so that the compiler can see which buffer is
+    /// written to.
+    #[inline(never)]
+    #[spirv(runtime_array_store)]
     pub fn store(&self, index: u32, value: T) {
-        runtime_array_store(self, index, value);
+        unsafe {
+            ::std::intrinsics::abort();
+        }
+    }
+    /// The number of elements, with `OpArrayLength`. Only works on a member
+    /// of a buffer, like `particles.data.len()`.
+    #[inline(never)]
+    #[spirv(runtime_array_len)]
+    pub fn len(&self) -> u32 {
+        unsafe { ::std::intrinsics::abort() }
     }
 }
 
@@ -130,3 +190,135 @@ where
     pub _location: PhantomData<Binding>,
     pub _binding: PhantomData<Set>,
 }
+
+/// A buffer that can only be read from, it is decorated with `NonWritable`.
+/// Writing to it is a compile error.
+#[spirv(BufferRead)]
+pub struct BufferRead<Binding, Set, T>
+where
+    Binding: Constant,
+    Set: Constant,
+{
+    pub data: T,
+    pub _location: PhantomData<Binding>,
+    pub _binding: PhantomData<Set>,
+}
+
+/// A buffer that can only be written to, it is decorated with `NonReadable`.
+#[spirv(BufferWrite)]
+pub struct BufferWrite<Binding, Set, T>
+where
+    Binding: Constant,
+    Set: Constant,
+{
+    pub data: T,
+    pub _location: PhantomData<Binding>,
+    pub _binding: PhantomData<Set>,
+}
+
+/// The data of a `UniformArray` or a `BufferArray`. Either `[T; N]` for a
+/// fixed number of descriptors or `RuntimeArray<T>` for an unsized, bindless
+/// style array.
+pub trait DescriptorArray {
+    type Elem;
+}
+
+impl<T> DescriptorArray for RuntimeArray<T> {
+    type Elem = T;
+}
+
+macro_rules! descriptor_array {
+    ($($len: expr)*) => {
+        $(
+            impl<T> DescriptorArray for [T; $len] {
+                type Elem = T;
+            }
+        )*
+    };
+}
+
+descriptor_array!(
+    1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16
+    17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32
+);
+
+/// An array of uniform blocks that share one binding, like
+/// `UniformArray<N0, N0, [Material; 16]>`. Every element is its own
+/// descriptor. The index of `get` has to be dynamically uniform, the same
+/// for every invocation of a draw or dispatch. An index that is different per
+/// invocation, like a material index of an instance, has to use
+/// `get_non_uniform`, which requires descriptor indexing.
+#[spirv(UniformArray)]
+pub struct UniformArray<Binding, Set, A>
+where
+    Binding: Constant,
+    Set: Constant,
+    A: DescriptorArray,
+{
+    pub data: A,
+    pub _location: PhantomData<Binding>,
+    pub _binding: PhantomData<Set>,
+}
+
+impl<Binding, Set, A> UniformArray<Binding, Set, A>
+where
+    Binding: Constant,
+    Set: Constant,
+    A: DescriptorArray,
+{
+    #[inline(never)]
+    #[spirv(descriptor_array_get)]
+    pub fn get(&self, index: u32) -> A::Elem {
+        unsafe { ::std::intrinsics::abort() }
+    }
+    #[inline(never)]
+    #[spirv(descriptor_array_get_non_uniform)]
+    pub fn get_non_uniform(&self, index: u32) -> A::Elem {
+        unsafe { ::std::intrinsics::abort() }
+    }
+}
+
+/// An array of storage buffers that share one binding, see `UniformArray`.
+#[spirv(BufferArray)]
+pub struct BufferArray<Binding, Set, A>
+where
+    Binding: Constant,
+    Set: Constant,
+    A: DescriptorArray,
+{
+    pub data: A,
+    pub _location: PhantomData<Binding>,
+    pub _binding: PhantomData<Set>,
+}
+
+impl<Binding, Set, A> BufferArray<Binding, Set, A>
+where
+    Binding: Constant,
+    Set: Constant,
+    A: DescriptorArray,
+{
+    #[inline(never)]
+    #[spirv(descriptor_array_get)]
+    pub fn get(&self, index: u32) -> A::Elem {
+        unsafe { ::std::intrinsics::abort() }
+    }
+    #[inline(never)]
+    #[spirv(descriptor_array_get_non_uniform)]
+    pub fn get_non_uniform(&self, index: u32) -> A::Elem {
+        unsafe { ::std::intrinsics::abort() }
+    }
+    #[inline(never)]
+    #[spirv(descriptor_array_store)]
+    pub fn store(&self, index: u32, value: A::Elem) {
+        unsafe {
+            ::std::intrinsics::abort();
+        }
+    }
+    #[inline(never)]
+    #[spirv(descriptor_array_store_non_uniform)]
+    pub fn store_non_uniform(&self, index: u32, value: A::Elem) {
+        unsafe {
+            ::std::intrinsics::abort();
+        }
+    }
+}
@@ -7,6 +7,7 @@ pub mod vector;
 pub mod constants;
 pub mod entry;
 pub mod intrinsics;
+pub mod matrix;
 pub mod num;
 pub mod random;
 pub mod range;
@@ -53,6 +54,7 @@ pub mod polynomial {
 pub mod prelude {
     pub use constants::*;
     pub use entry::*;
+    pub use matrix::*;
     pub use num::*;
     pub use random::*;
     pub use range::*;
@@ -0,0 +1,49 @@
+use vector::*;
+
+#[spirv(Mat2)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat2<T> {
+    pub x: Vec2<T>,
+    pub y: Vec2<T>,
+}
+
+impl<T> Mat2<T> {
+    #[inline]
+    pub fn new(x: Vec2<T>, y: Vec2<T>) -> Mat2<T> {
+        Mat2 { x, y }
+    }
+}
+
+#[spirv(Mat3)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat3<T> {
+    pub x: Vec3<T>,
+    pub y: Vec3<T>,
+    pub z: Vec3<T>,
+}
+
+impl<T> Mat3<T> {
+    #[inline]
+    pub fn new(x: Vec3<T>, y: Vec3<T>, z: Vec3<T>) -> Mat3<T> {
+        Mat3 { x, y, z }
+    }
+}
+
+#[spirv(Mat4)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4<T> {
+    pub x: Vec4<T>,
+    pub y: Vec4<T>,
+    pub z: Vec4<T>,
+    pub w: Vec4<T>,
+}
+
+impl<T> Mat4<T> {
+    #[inline]
+    pub fn new(x: Vec4<T>, y: Vec4<T>, z: Vec4<T>, w: Vec4<T>) -> Mat4<T> {
+        Mat4 { x, y, z, w }
+    }
+}
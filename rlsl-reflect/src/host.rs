@@ -0,0 +1,44 @@
+//! Types that are used by the host bindings that `rlsl-bindgen` generates.
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DescriptorKind {
+    UniformBuffer,
+    StorageBuffer,
+    Image,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct DescriptorBinding {
+    pub name: &'static str,
+    pub set: u32,
+    pub binding: u32,
+    /// The number of descriptors, `None` for a runtime-sized array
+    pub count: Option<u32>,
+    pub kind: DescriptorKind,
+}
+
+/// The format of a vertex attribute, named after the matching Vulkan format
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Format {
+    R32Sfloat,
+    R32G32Sfloat,
+    R32G32B32Sfloat,
+    R32G32B32A32Sfloat,
+    R32Uint,
+    R32G32Uint,
+    R32G32B32Uint,
+    R32G32B32A32Uint,
+    R32Sint,
+    R32G32Sint,
+    R32G32B32Sint,
+    R32G32B32A32Sint,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct VertexAttribute {
+    pub name: &'static str,
+    pub location: u32,
+    pub format: Format,
+    /// The offset inside of the generated `Vertex` struct
+    pub offset: u32,
+}
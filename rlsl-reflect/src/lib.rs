@@ -0,0 +1,249 @@
+//! Reflection data of a SPIR-V module. The compiler writes it as
+//! `.reflect.json` next to every module, so that the host can build
+//! descriptor set layouts and vertex input layouts automatically.
+//!
+//! `Reflection::load_spirv` creates the same reflection from any SPIR-V
+//! module, which also works for modules that were not compiled with rlsl.
+extern crate rspirv;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+extern crate spirv_headers as spirv;
+
+pub mod host;
+pub mod module;
+
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Reflection {
+    pub entry_points: Vec<EntryPoint>,
+    /// Vulkan device extensions that have to be enabled, for example
+    /// `VK_EXT_scalar_block_layout`
+    pub device_extensions: Vec<String>,
+}
+
+impl Reflection {
+    pub fn entry_point(&self, stage: Stage, name: &str) -> Option<&EntryPoint> {
+        self.entry_points
+            .iter()
+            .find(|entry| entry.stage == stage && entry.name == name)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Reflection is always valid json")
+    }
+
+    pub fn from_json(json: &str) -> serde_json::Result<Reflection> {
+        serde_json::from_str(json)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.write(file)
+    }
+
+    pub fn write<W: io::Write>(&self, w: W) -> io::Result<()> {
+        serde_json::to_writer_pretty(w, self).map_err(io::Error::from)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Reflection> {
+        let file = File::open(path)?;
+        serde_json::from_reader(file).map_err(io::Error::from)
+    }
+
+    pub fn from_module(module: &rspirv::mr::Module) -> Reflection {
+        module::reflect_module(module)
+    }
+
+    pub fn from_spirv(bytes: &[u8]) -> io::Result<Reflection> {
+        use rspirv::binary::Parser;
+        use rspirv::mr::Loader;
+        let mut loader = Loader::new();
+        Parser::new(bytes, &mut loader)
+            .parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", err)))?;
+        Ok(Reflection::from_module(&loader.module()))
+    }
+
+    /// Loads a `.spv` file
+    pub fn load_spirv<P: AsRef<Path>>(path: P) -> io::Result<Reflection> {
+        let bytes = fs::read(path)?;
+        Reflection::from_spirv(&bytes)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    Vertex,
+    TessellationControl,
+    TessellationEvaluation,
+    Geometry,
+    Fragment,
+    Compute,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: Stage,
+    /// Only set for compute shaders
+    pub workgroup_size: Option<[u32; 3]>,
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+    pub uniforms: Vec<Descriptor>,
+    pub buffers: Vec<Descriptor>,
+    pub images: Vec<Descriptor>,
+    pub push_constants: Vec<PushConstant>,
+    pub spec_constants: Vec<SpecConstant>,
+}
+
+impl EntryPoint {
+    pub fn new(name: String, stage: Stage) -> EntryPoint {
+        EntryPoint {
+            name,
+            stage,
+            workgroup_size: None,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            uniforms: Vec::new(),
+            buffers: Vec::new(),
+            images: Vec::new(),
+            push_constants: Vec::new(),
+            spec_constants: Vec::new(),
+        }
+    }
+
+    /// Every descriptor of the entry point, sorted by set and binding
+    pub fn descriptors(&self) -> Vec<&Descriptor> {
+        let mut descriptors: Vec<_> = self
+            .uniforms
+            .iter()
+            .chain(&self.buffers)
+            .chain(&self.images)
+            .collect();
+        descriptors.sort_by_key(|descriptor| (descriptor.set, descriptor.binding));
+        descriptors
+    }
+}
+
+/// An input or output of an entry point. Either `location` or `builtin` is
+/// set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InterfaceVariable {
+    pub name: Option<String>,
+    pub location: Option<u32>,
+    pub builtin: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Descriptor {
+    pub name: Option<String>,
+    pub set: u32,
+    pub binding: u32,
+    /// `std140`, `std430` or `scalar` for uniforms and buffers
+    pub layout: Option<String>,
+    /// The number of descriptors that are bound at `binding`. For arrays of
+    /// descriptors, `ty` is the type of a single element.
+    #[serde(default)]
+    pub count: DescriptorCount,
+    #[serde(rename = "type")]
+    pub ty: Type,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DescriptorCount {
+    One,
+    Array(u32),
+    /// A runtime-sized array that requires descriptor indexing
+    Runtime,
+}
+
+impl Default for DescriptorCount {
+    fn default() -> DescriptorCount {
+        DescriptorCount::One
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushConstant {
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpecConstant {
+    pub name: Option<String>,
+    pub id: u32,
+    #[serde(rename = "type")]
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Type {
+    Bool,
+    Int {
+        width: u32,
+        signed: bool,
+    },
+    Float {
+        width: u32,
+    },
+    Vector {
+        component: Box<Type>,
+        count: u32,
+    },
+    /// A column major matrix
+    Matrix {
+        column: Box<Type>,
+        columns: u32,
+    },
+    /// `length` is `None` for a runtime array. `stride` is only set inside of
+    /// blocks.
+    Array {
+        element: Box<Type>,
+        length: Option<u32>,
+        stride: Option<u32>,
+    },
+    /// `size` is only set inside of blocks
+    Struct {
+        name: Option<String>,
+        size: Option<u32>,
+        members: Vec<Member>,
+    },
+    Image {
+        dim: String,
+        depth: bool,
+        arrayed: bool,
+        multisampled: bool,
+    },
+    Sampler,
+    SampledImage {
+        image: Box<Type>,
+    },
+    /// A type that can not be described
+    Opaque {
+        name: String,
+    },
+}
+
+/// A member of a struct. `offset`, `size` and `matrix_stride` are only set
+/// inside of blocks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Member {
+    pub name: Option<String>,
+    pub offset: Option<u32>,
+    pub size: Option<u32>,
+    pub matrix_stride: Option<u32>,
+    #[serde(rename = "type")]
+    pub ty: Type,
+}
@@ -0,0 +1,542 @@
+//! Reflection of any SPIR-V module, for example a module that was compiled
+//! with rlsl or with glslang. Descriptors are assigned to an entry point if
+//! they are used by a function that can be reached from the entry point.
+use rspirv::mr::{Instruction, Module, Operand};
+use spirv::{self, Decoration, Op, StorageClass};
+use std::collections::{HashMap, HashSet};
+use {
+    Descriptor, DescriptorCount, EntryPoint, InterfaceVariable, Member, PushConstant, Reflection,
+    SpecConstant, Stage, Type,
+};
+
+fn id_ref(operand: &Operand) -> u32 {
+    match *operand {
+        Operand::IdRef(id) => id,
+        ref operand => panic!("Expected an id, found {:?}", operand),
+    }
+}
+
+fn literal(operand: &Operand) -> u32 {
+    match *operand {
+        Operand::LiteralInt32(value) => value,
+        ref operand => panic!("Expected a literal, found {:?}", operand),
+    }
+}
+
+fn stage(model: spirv::ExecutionModel) -> Option<Stage> {
+    use spirv::ExecutionModel::*;
+    match model {
+        Vertex => Some(Stage::Vertex),
+        TessellationControl => Some(Stage::TessellationControl),
+        TessellationEvaluation => Some(Stage::TessellationEvaluation),
+        Geometry => Some(Stage::Geometry),
+        Fragment => Some(Stage::Fragment),
+        GLCompute => Some(Stage::Compute),
+        _ => None,
+    }
+}
+
+struct ModuleInfo<'m> {
+    module: &'m Module,
+    names: HashMap<u32, &'m str>,
+    member_names: HashMap<(u32, u32), &'m str>,
+    decorations: HashMap<(u32, Decoration), &'m [Operand]>,
+    member_decorations: HashMap<(u32, u32, Decoration), &'m [Operand]>,
+    /// Types, constants and global variables
+    defs: HashMap<u32, &'m Instruction>,
+}
+
+impl<'m> ModuleInfo<'m> {
+    fn new(module: &'m Module) -> Self {
+        let mut info = ModuleInfo {
+            module,
+            names: HashMap::new(),
+            member_names: HashMap::new(),
+            decorations: HashMap::new(),
+            member_decorations: HashMap::new(),
+            defs: HashMap::new(),
+        };
+        for inst in &module.debugs {
+            let name = match inst.operands.last() {
+                Some(&Operand::LiteralString(ref name)) if !name.is_empty() => name,
+                _ => continue,
+            };
+            match inst.class.opcode {
+                Op::Name => {
+                    info.names.insert(id_ref(&inst.operands[0]), name);
+                }
+                Op::MemberName => {
+                    let key = (id_ref(&inst.operands[0]), literal(&inst.operands[1]));
+                    info.member_names.insert(key, name);
+                }
+                _ => (),
+            }
+        }
+        for inst in &module.annotations {
+            let operands = &inst.operands[..];
+            match inst.class.opcode {
+                Op::Decorate => {
+                    if let Operand::Decoration(decoration) = operands[1] {
+                        info.decorations
+                            .insert((id_ref(&operands[0]), decoration), &operands[2..]);
+                    }
+                }
+                Op::MemberDecorate => {
+                    if let Operand::Decoration(decoration) = operands[2] {
+                        let key = (id_ref(&operands[0]), literal(&operands[1]), decoration);
+                        info.member_decorations.insert(key, &operands[3..]);
+                    }
+                }
+                _ => (),
+            }
+        }
+        for inst in &module.types_global_values {
+            if let Some(id) = inst.result_id {
+                info.defs.insert(id, inst);
+            }
+        }
+        info
+    }
+
+    fn name(&self, id: u32) -> Option<String> {
+        self.names.get(&id).map(|name| name.to_string())
+    }
+
+    fn decoration_u32(&self, id: u32, decoration: Decoration) -> Option<u32> {
+        self.decorations
+            .get(&(id, decoration))
+            .and_then(|operands| operands.first())
+            .map(literal)
+    }
+
+    fn member_decoration_u32(&self, id: u32, member: u32, decoration: Decoration) -> Option<u32> {
+        self.member_decorations
+            .get(&(id, member, decoration))
+            .and_then(|operands| operands.first())
+            .map(literal)
+    }
+
+    fn builtin(operands: Option<&&[Operand]>) -> Option<String> {
+        match operands.and_then(|operands| operands.first()) {
+            Some(&Operand::BuiltIn(builtin)) => Some(format!("{:?}", builtin)),
+            _ => None,
+        }
+    }
+
+    /// The value of an `OpConstant`, `None` for specialization constants
+    fn constant_u32(&self, id: u32) -> Option<u32> {
+        let inst = self.defs.get(&id)?;
+        match inst.class.opcode {
+            Op::Constant => inst.operands.first().map(literal),
+            _ => None,
+        }
+    }
+
+    /// Every id that is referenced by a function that can be reached from
+    /// `function`.
+    fn used_ids(&self, function: u32) -> HashSet<u32> {
+        let functions: HashMap<_, _> = self
+            .module
+            .functions
+            .iter()
+            .filter_map(|f| f.def.as_ref().and_then(|def| def.result_id).map(|id| (id, f)))
+            .collect();
+        let mut stack = vec![function];
+        let mut visited = HashSet::new();
+        let mut used = HashSet::new();
+        while let Some(id) = stack.pop() {
+            if !visited.insert(id) {
+                continue;
+            }
+            let function = match functions.get(&id) {
+                Some(function) => function,
+                None => continue,
+            };
+            for inst in function.basic_blocks.iter().flat_map(|bb| &bb.instructions) {
+                for operand in &inst.operands {
+                    if let Operand::IdRef(id) = *operand {
+                        used.insert(id);
+                        if inst.class.opcode == Op::FunctionCall {
+                            stack.push(id);
+                        }
+                    }
+                }
+            }
+        }
+        used
+    }
+
+    fn ty(&self, id: u32) -> Type {
+        let inst = match self.defs.get(&id) {
+            Some(inst) => inst,
+            None => {
+                return Type::Opaque {
+                    name: format!("%{}", id),
+                }
+            }
+        };
+        let operands = &inst.operands;
+        match inst.class.opcode {
+            Op::TypeBool => Type::Bool,
+            Op::TypeInt => Type::Int {
+                width: literal(&operands[0]),
+                signed: literal(&operands[1]) != 0,
+            },
+            Op::TypeFloat => Type::Float {
+                width: literal(&operands[0]),
+            },
+            Op::TypeVector => Type::Vector {
+                component: Box::new(self.ty(id_ref(&operands[0]))),
+                count: literal(&operands[1]),
+            },
+            Op::TypeMatrix => Type::Matrix {
+                column: Box::new(self.ty(id_ref(&operands[0]))),
+                columns: literal(&operands[1]),
+            },
+            Op::TypeArray => Type::Array {
+                element: Box::new(self.ty(id_ref(&operands[0]))),
+                length: self.constant_u32(id_ref(&operands[1])),
+                stride: self.decoration_u32(id, Decoration::ArrayStride),
+            },
+            Op::TypeRuntimeArray => Type::Array {
+                element: Box::new(self.ty(id_ref(&operands[0]))),
+                length: None,
+                stride: self.decoration_u32(id, Decoration::ArrayStride),
+            },
+            Op::TypeStruct => {
+                let members: Vec<_> = operands
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, operand)| {
+                        let idx = idx as u32;
+                        let member_ty = id_ref(operand);
+                        let offset = self.member_decoration_u32(id, idx, Decoration::Offset);
+                        let matrix_stride =
+                            self.member_decoration_u32(id, idx, Decoration::MatrixStride);
+                        Member {
+                            name: self.member_names.get(&(id, idx)).map(|name| name.to_string()),
+                            offset,
+                            size: offset.and_then(|_| self.size(member_ty, matrix_stride)),
+                            matrix_stride,
+                            ty: self.ty(member_ty),
+                        }
+                    }).collect();
+                Type::Struct {
+                    name: self.name(id),
+                    size: self.struct_size(&members),
+                    members,
+                }
+            }
+            Op::TypeImage => Type::Image {
+                dim: match operands[1] {
+                    Operand::Dim(dim) => format!("{:?}", dim),
+                    ref operand => panic!("Expected a dim, found {:?}", operand),
+                },
+                depth: literal(&operands[2]) == 1,
+                arrayed: literal(&operands[3]) != 0,
+                multisampled: literal(&operands[4]) != 0,
+            },
+            Op::TypeSampler => Type::Sampler,
+            Op::TypeSampledImage => Type::SampledImage {
+                image: Box::new(self.ty(id_ref(&operands[0]))),
+            },
+            Op::TypePointer => self.ty(id_ref(&operands[1])),
+            opcode => Type::Opaque {
+                name: format!("{:?}", opcode),
+            },
+        }
+    }
+
+    /// The size of a type inside of a block. SPIR-V does not know about the
+    /// alignment of a struct, so the size of a struct ends with its last
+    /// member.
+    fn size(&self, id: u32, matrix_stride: Option<u32>) -> Option<u32> {
+        let inst = self.defs.get(&id)?;
+        let operands = &inst.operands;
+        match inst.class.opcode {
+            Op::TypeInt | Op::TypeFloat => Some(literal(&operands[0]) / 8),
+            Op::TypeVector => {
+                self.size(id_ref(&operands[0]), None).map(|size| size * literal(&operands[1]))
+            }
+            Op::TypeMatrix => matrix_stride.map(|stride| stride * literal(&operands[1])),
+            Op::TypeArray => {
+                let len = self.constant_u32(id_ref(&operands[1]))?;
+                self.decoration_u32(id, Decoration::ArrayStride)
+                    .map(|stride| stride * len)
+            }
+            Op::TypeRuntimeArray => Some(0),
+            Op::TypeStruct => match self.ty(id) {
+                Type::Struct { size, .. } => size,
+                _ => unreachable!(),
+            },
+            _ => None,
+        }
+    }
+
+    fn struct_size(&self, members: &[Member]) -> Option<u32> {
+        members.iter().fold(Some(0), |size, member| {
+            let end = member.offset? + member.size?;
+            size.map(|size| size.max(end))
+        })
+    }
+
+    fn pointee(&self, var: &Instruction) -> u32 {
+        let pointer = var.result_type.expect("Variable without a type");
+        id_ref(&self.defs[&pointer].operands[1])
+    }
+
+    /// Inputs and outputs can either be decorated directly, or be a block with
+    /// decorated members like `gl_PerVertex` or the blocks that rlsl creates.
+    fn interface(&self, var: &Instruction) -> Vec<InterfaceVariable> {
+        let id = var.result_id.expect("Variable without an id");
+        let pointee = self.pointee(var);
+        let location = self.decoration_u32(id, Decoration::Location);
+        let builtin = Self::builtin(self.decorations.get(&(id, Decoration::BuiltIn)));
+        if location.is_some() || builtin.is_some() {
+            return vec![InterfaceVariable {
+                name: self.name(id),
+                location,
+                builtin,
+                ty: self.ty(pointee),
+            }];
+        }
+        let members = match self.defs.get(&pointee) {
+            Some(inst) if inst.class.opcode == Op::TypeStruct => &inst.operands,
+            _ => return Vec::new(),
+        };
+        let decorated: Vec<_> = members
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, operand)| {
+                let idx = idx as u32;
+                let location = self.member_decoration_u32(pointee, idx, Decoration::Location);
+                let builtin = Self::builtin(
+                    self.member_decorations
+                        .get(&(pointee, idx, Decoration::BuiltIn)),
+                );
+                if location.is_none() && builtin.is_none() {
+                    return None;
+                }
+                let member_name = self
+                    .member_names
+                    .get(&(pointee, idx))
+                    .map(|name| name.to_string());
+                Some((member_name, location, builtin, self.ty(id_ref(operand))))
+            }).collect();
+        let single = decorated.len() == 1;
+        decorated
+            .into_iter()
+            .map(|(member_name, location, builtin, ty)| InterfaceVariable {
+                name: if single {
+                    self.name(id).or(member_name)
+                } else {
+                    member_name
+                },
+                location,
+                builtin,
+                ty,
+            }).collect()
+    }
+
+    /// Arrays of descriptors are arrays without an `ArrayStride`, because they
+    /// are not laid out in memory.
+    fn descriptor(&self, var: &Instruction) -> Descriptor {
+        let id = var.result_id.expect("Variable without an id");
+        let pointee = self.pointee(var);
+        let (count, ty) = match (self.defs[&pointee].class.opcode, self.ty(pointee)) {
+            (
+                Op::TypeArray,
+                Type::Array {
+                    element,
+                    length: Some(length),
+                    stride: None,
+                },
+            ) => (DescriptorCount::Array(length), *element),
+            (
+                Op::TypeRuntimeArray,
+                Type::Array {
+                    element,
+                    stride: None,
+                    ..
+                },
+            ) => (DescriptorCount::Runtime, *element),
+            (_, ty) => (DescriptorCount::One, ty),
+        };
+        Descriptor {
+            name: self.name(id),
+            set: self.decoration_u32(id, Decoration::DescriptorSet).unwrap_or(0),
+            binding: self.decoration_u32(id, Decoration::Binding).unwrap_or(0),
+            layout: None,
+            count,
+            ty,
+        }
+    }
+
+    fn spec_constants(&self) -> Vec<SpecConstant> {
+        self.module
+            .types_global_values
+            .iter()
+            .filter(|inst| match inst.class.opcode {
+                Op::SpecConstant | Op::SpecConstantTrue | Op::SpecConstantFalse => true,
+                _ => false,
+            }).filter_map(|inst| {
+                let id = inst.result_id?;
+                Some(SpecConstant {
+                    name: self.name(id),
+                    id: self.decoration_u32(id, Decoration::SpecId)?,
+                    ty: self.ty(inst.result_type?),
+                })
+            }).collect()
+    }
+
+    fn entry_point(&self, inst: &Instruction) -> Option<EntryPoint> {
+        let stage = match inst.operands[0] {
+            Operand::ExecutionModel(model) => stage(model)?,
+            _ => return None,
+        };
+        let function = id_ref(&inst.operands[1]);
+        let name = match inst.operands[2] {
+            Operand::LiteralString(ref name) => name.clone(),
+            _ => return None,
+        };
+        let mut entry = EntryPoint::new(name, stage);
+        entry.workgroup_size = self
+            .module
+            .execution_modes
+            .iter()
+            .filter(|mode| id_ref(&mode.operands[0]) == function)
+            .filter_map(|mode| match mode.operands[..] {
+                [
+                    _,
+                    Operand::ExecutionMode(spirv::ExecutionMode::LocalSize),
+                    Operand::LiteralInt32(x),
+                    Operand::LiteralInt32(y),
+                    Operand::LiteralInt32(z),
+                ] => Some([x, y, z]),
+                _ => None,
+            }).next();
+
+        let mut used = self.used_ids(function);
+        used.extend(inst.operands[3..].iter().map(id_ref));
+        let variables = self
+            .module
+            .types_global_values
+            .iter()
+            .filter(|inst| inst.class.opcode == Op::Variable)
+            .filter(|inst| inst.result_id.map_or(false, |id| used.contains(&id)));
+        for var in variables {
+            let storage_class = match var.operands[0] {
+                Operand::StorageClass(storage_class) => storage_class,
+                _ => continue,
+            };
+            match storage_class {
+                StorageClass::Input => entry.inputs.extend(self.interface(var)),
+                StorageClass::Output => entry.outputs.extend(self.interface(var)),
+                StorageClass::Uniform => {
+                    let block = self.pointee(var);
+                    // Older modules use `Uniform` with `BufferBlock` for buffers
+                    if self.decorations.contains_key(&(block, Decoration::BufferBlock)) {
+                        entry.buffers.push(self.descriptor(var));
+                    } else {
+                        entry.uniforms.push(self.descriptor(var));
+                    }
+                }
+                StorageClass::StorageBuffer => entry.buffers.push(self.descriptor(var)),
+                StorageClass::UniformConstant => entry.images.push(self.descriptor(var)),
+                StorageClass::PushConstant => entry.push_constants.push(PushConstant {
+                    name: self.name(var.result_id.expect("id")),
+                    ty: self.ty(self.pointee(var)),
+                }),
+                _ => (),
+            }
+        }
+        entry.inputs.sort_by_key(|var| var.location);
+        entry.outputs.sort_by_key(|var| var.location);
+        entry.spec_constants = self.spec_constants();
+        Some(entry)
+    }
+}
+
+pub fn reflect_module(module: &Module) -> Reflection {
+    let info = ModuleInfo::new(module);
+    // rlsl notes the required device extensions with `OpSourceExtension`
+    let device_extensions = module
+        .debugs
+        .iter()
+        .filter(|inst| inst.class.opcode == Op::SourceExtension)
+        .filter_map(|inst| match inst.operands.first() {
+            Some(&Operand::LiteralString(ref name)) if name.starts_with("VK_") => Some(name.clone()),
+            _ => None,
+        }).collect();
+    Reflection {
+        entry_points: module
+            .entry_points
+            .iter()
+            .filter_map(|inst| info.entry_point(inst))
+            .collect(),
+        device_extensions,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspirv::mr::Builder;
+
+    #[test]
+    fn vertex_with_uniform() {
+        let mut b = Builder::new();
+        b.capability(spirv::Capability::Shader);
+        b.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
+        let void = b.type_void();
+        let float = b.type_float(32);
+        let vec4 = b.type_vector(float, 4);
+
+        let input_ptr = b.type_pointer(None, StorageClass::Input, vec4);
+        let position = b.variable(input_ptr, None, StorageClass::Input, None);
+        b.name(position, "position");
+        b.decorate(position, Decoration::Location, &[Operand::LiteralInt32(2)]);
+
+        let block = b.type_struct(&[float, vec4]);
+        b.decorate(block, Decoration::Block, &[]);
+        b.member_decorate(block, 0, Decoration::Offset, &[Operand::LiteralInt32(0)]);
+        b.member_decorate(block, 1, Decoration::Offset, &[Operand::LiteralInt32(16)]);
+        let uniform_ptr = b.type_pointer(None, StorageClass::Uniform, block);
+        let uniform = b.variable(uniform_ptr, None, StorageClass::Uniform, None);
+        b.decorate(uniform, Decoration::DescriptorSet, &[Operand::LiteralInt32(1)]);
+        b.decorate(uniform, Decoration::Binding, &[Operand::LiteralInt32(3)]);
+        // Not used by the entry point
+        b.variable(uniform_ptr, None, StorageClass::Uniform, None);
+
+        let fn_ty = b.type_function(void, &[]);
+        let main = b
+            .begin_function(void, None, spirv::FunctionControl::empty(), fn_ty)
+            .unwrap();
+        b.begin_basic_block(None).unwrap();
+        b.load(block, None, uniform, None, &[]).unwrap();
+        b.ret().unwrap();
+        b.end_function().unwrap();
+        b.entry_point(spirv::ExecutionModel::Vertex, main, "main", &[position]);
+
+        let reflection = reflect_module(&b.module());
+        assert_eq!(reflection.entry_points.len(), 1);
+        let entry = &reflection.entry_points[0];
+        assert_eq!(entry.stage, Stage::Vertex);
+        assert_eq!(entry.inputs.len(), 1);
+        assert_eq!(entry.inputs[0].name, Some("position".to_string()));
+        assert_eq!(entry.inputs[0].location, Some(2));
+        assert_eq!(entry.uniforms.len(), 1);
+        let descriptor = &entry.uniforms[0];
+        assert_eq!((descriptor.set, descriptor.binding), (1, 3));
+        match descriptor.ty {
+            Type::Struct {
+                size, ref members, ..
+            } => {
+                assert_eq!(size, Some(32));
+                let offsets: Vec<_> = members.iter().map(|member| member.offset).collect();
+                assert_eq!(offsets, vec![Some(0), Some(16)]);
+            }
+            ref ty => panic!("Expected a struct, found {:?}", ty),
+        }
+    }
+}
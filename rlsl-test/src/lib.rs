@@ -4,6 +4,10 @@ extern crate quickcheck;
 //extern crate glsl_to_spirv;
 extern crate gfx_hal as hal;
 extern crate issues;
+extern crate rlsl_layout;
+extern crate rlsl_validate;
+extern crate rspirv;
+extern crate spirv_headers as spirv;
 
 use hal::{buffer, command, memory, pool, pso, queue};
 use hal::{Backend, Compute, DescriptorPool, Device, Instance, PhysicalDevice, QueueFamily};
@@ -44,6 +48,7 @@ where
     use std::io::Read;
     let mut data = Vec::new();
     spirv_file.read_to_end(&mut data).expect("read");
+    validate(path.as_ref(), &data);
 
     let shader = device.create_shader_module(&data).unwrap();
     let (pipeline_layout, pipeline, set_layout, mut desc_pool) = {
@@ -260,6 +265,17 @@ fn create_buffer<B: Backend>(
     }
 }
 
+/// Panics with the path and every error of the validator if the module is
+/// invalid
+pub fn validate(path: &Path, bytes: &[u8]) {
+    let module = rspirv::mr::load_bytes(bytes)
+        .unwrap_or_else(|err| panic!("{} is not a module: {:?}", path.display(), err));
+    if let Err(errors) = rlsl_validate::validate(&module) {
+        let errors: Vec<_> = errors.iter().map(|err| err.to_string()).collect();
+        panic!("{} is invalid:\n{}", path.display(), errors.join("\n"));
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use compute;
@@ -267,41 +283,41 @@ mod tests {
     use quickcheck::TestResult;
     quickcheck! {
         fn compute_u32_add(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/u32-add.spv", issues::u32_add)
+            compute("compute", input, "../target/debug/shaders/u32-add.spv", issues::u32_add)
         }
 
         fn compute_square(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/square.spv", issues::square)
+            compute("compute", input, "../target/debug/shaders/square.spv", issues::square)
         }
 
         fn compute_questionmark_option(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/questionmark-option.spv", issues::questionmark_option)
+            compute("compute", input, "../target/debug/shaders/questionmark-option.spv", issues::questionmark_option)
         }
 
         fn compute_reference(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/reference.spv", issues::reference)
+            compute("compute", input, "../target/debug/shaders/reference.spv", issues::reference)
         }
 
         fn compute_ok_or(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/ok_or.spv", issues::ok_or)
+            compute("compute", input, "../target/debug/shaders/ok_or.spv", issues::ok_or)
         }
         fn compute_match_enum(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/match_enum.spv", issues::match_enum)
+            compute("compute", input, "../target/debug/shaders/match_enum.spv", issues::match_enum)
         }
         fn compute_match_result(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/match_result.spv", issues::match_result)
+            compute("compute", input, "../target/debug/shaders/match_result.spv", issues::match_result)
         }
 
         fn compute_option(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/option.spv", issues::option)
+            compute("compute", input, "../target/debug/shaders/option.spv", issues::option)
         }
 
         fn compute_simple_loop(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/simple_loop.spv", issues::simple_loop)
+            compute("compute", input, "../target/debug/shaders/simple_loop.spv", issues::simple_loop)
         }
 
         fn compute_single_branch(input: Vec<f32>) -> TestResult {
-            compute("compute", input, "../.shaders/single-branch.spv", issues::single_branch)
+            compute("compute", input, "../target/debug/shaders/single-branch.spv", issues::single_branch)
         }
 
         fn compute_single_branch_glsl(input: Vec<f32>) -> TestResult {
@@ -309,4 +325,107 @@ mod tests {
         }
     }
 
+    /// Returns the `Offset` decorations of the struct with the name `name`.
+    /// A type that is used with several layouts has a copy per layout, which
+    /// would make the name ambiguous.
+    fn spirv_offsets(module: &rspirv::mr::Module, name: &str) -> Vec<u32> {
+        use rspirv::mr::Operand;
+        use spirv;
+        let ids: Vec<_> = module
+            .debugs
+            .iter()
+            .filter(|inst| inst.class.opcode == spirv::Op::Name)
+            .filter(|inst| inst.operands[1] == Operand::LiteralString(name.into()))
+            .filter_map(|inst| match inst.operands[0] {
+                Operand::IdRef(id) => Some(id),
+                _ => None,
+            }).collect();
+        assert_eq!(ids.len(), 1, "Expected exactly one struct named `{}`", name);
+        let id = ids[0];
+        let mut offsets: Vec<(u32, u32)> = module
+            .annotations
+            .iter()
+            .filter(|inst| inst.class.opcode == spirv::Op::MemberDecorate)
+            .filter_map(|inst| match inst.operands[..] {
+                [
+                    Operand::IdRef(target),
+                    Operand::LiteralInt32(member),
+                    Operand::Decoration(spirv::Decoration::Offset),
+                    Operand::LiteralInt32(offset),
+                ] if target == id => Some((member, offset)),
+                _ => None,
+            }).collect();
+        offsets.sort();
+        offsets.into_iter().map(|(_, offset)| offset).collect()
+    }
+
+    fn host_offsets(layout: rlsl_layout::Layout) -> Vec<u32> {
+        layout.offsets().into_iter().map(|offset| offset as u32).collect()
+    }
+
+    /// Every type of `issues::blocks` together with a module that uses it
+    #[test]
+    fn layouts_match_compiler() {
+        use issues::blocks::*;
+        use rlsl_layout::{Layout, LayoutRules, Std140, Std430};
+        let cases = vec![
+            ("layouts", "Camera", Camera::std140_layout()),
+            ("layouts", "Material", Material::std140_layout()),
+            ("layouts", "Light", Light::std430_layout()),
+            ("layouts", "Particle", Particle::std430_layout()),
+            ("buffer-header", "Particles", Particles::std430_layout()),
+            ("descriptor-arrays", "Surface", Surface::std140_layout()),
+            (
+                "scalar-layout",
+                "ScalarParticle",
+                Layout::new(&ScalarParticle::std430_ty(), LayoutRules::Scalar),
+            ),
+        ];
+        for (shader, name, layout) in cases {
+            let path = format!("../target/debug/shaders/{}.spv", shader);
+            let bytes = ::std::fs::read(&path).expect(&path);
+            let module = rspirv::mr::load_bytes(bytes).expect("Invalid module");
+            assert_eq!(
+                spirv_offsets(&module, &format!("issues::blocks::{}", name)),
+                host_offsets(layout),
+                "`{}` in {}",
+                name,
+                path
+            );
+        }
+    }
+
+    /// `Particles` has two fields in front of the `RuntimeArray`
+    #[test]
+    fn array_length_member() {
+        use rspirv::mr::Operand;
+        use spirv;
+        let path = "../target/debug/shaders/buffer-header.spv";
+        let bytes = ::std::fs::read(path).expect(path);
+        let module = rspirv::mr::load_bytes(bytes).expect("Invalid module");
+        let members: Vec<_> = module
+            .functions
+            .iter()
+            .flat_map(|function| function.basic_blocks.iter())
+            .flat_map(|block| block.instructions.iter())
+            .filter(|inst| inst.class.opcode == spirv::Op::ArrayLength)
+            .map(|inst| inst.operands[1].clone())
+            .collect();
+        assert_eq!(members, vec![Operand::LiteralInt32(2)]);
+    }
+
+    #[test]
+    fn validate_shaders() {
+        use std::fs;
+        let mut count = 0;
+        for entry in fs::read_dir("../target/debug/shaders").expect("shaders") {
+            let path = entry.expect("entry").path();
+            if path.extension().map_or(false, |ext| ext == "spv") {
+                let bytes = fs::read(&path).expect("read");
+                ::validate(&path, &bytes);
+                count += 1;
+            }
+        }
+        assert!(count > 0, "No modules found, build the shaders with rlsl first");
+    }
 }
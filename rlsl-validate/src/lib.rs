@@ -0,0 +1,999 @@
+//! A structural validator for the modules that rlsl generates. It is not a
+//! replacement for `spirv-val`, but it needs neither external tools nor a GPU
+//! and checks the rules that codegen is most likely to break:
+//!
+//! * every id is defined once, every use refers to a definition that
+//!   dominates it, and the types of common instructions match
+//! * every conditional branch is preceded by `OpSelectionMerge` or
+//!   `OpLoopMerge`, unless it breaks out of a loop or continues it
+//! * a header dominates its merge block and its continue target
+//! * every block in the `Uniform`, `StorageBuffer` and `PushConstant`
+//!   storage classes has an explicit layout
+extern crate rspirv;
+extern crate spirv_headers as spirv;
+
+use rspirv::mr::{BasicBlock, Function, Instruction, Module, Operand};
+use spirv::{Decoration, Op, StorageClass};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    /// The name of the function that contains the error, if there is one
+    pub function: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref function) => write!(f, "in `{}`: {}", function, self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Validates `module` and returns every error that was found
+pub fn validate(module: &Module) -> Result<(), Vec<Error>> {
+    let mut validator = Validator::new(module);
+    validator.check_ids();
+    validator.check_layouts();
+    for function in &module.functions {
+        FunctionValidator::new(&mut validator, function).check();
+    }
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(validator.errors)
+    }
+}
+
+fn id_refs<'i>(inst: &'i Instruction) -> impl Iterator<Item = u32> + 'i {
+    inst.operands.iter().filter_map(|operand| match *operand {
+        Operand::IdRef(id) => Some(id),
+        _ => None,
+    })
+}
+
+fn id_ref(operand: &Operand) -> Option<u32> {
+    match *operand {
+        Operand::IdRef(id) => Some(id),
+        _ => None,
+    }
+}
+
+fn literal(operand: &Operand) -> Option<u32> {
+    match *operand {
+        Operand::LiteralInt32(value) => Some(value),
+        _ => None,
+    }
+}
+
+fn is_type(opcode: Op) -> bool {
+    match opcode {
+        Op::TypeVoid
+        | Op::TypeBool
+        | Op::TypeInt
+        | Op::TypeFloat
+        | Op::TypeVector
+        | Op::TypeMatrix
+        | Op::TypeImage
+        | Op::TypeSampler
+        | Op::TypeSampledImage
+        | Op::TypeArray
+        | Op::TypeRuntimeArray
+        | Op::TypeStruct
+        | Op::TypeOpaque
+        | Op::TypePointer
+        | Op::TypeFunction => true,
+        _ => false,
+    }
+}
+
+/// Instructions whose operands have the same type as their result
+fn is_arithmetic(opcode: Op) -> bool {
+    match opcode {
+        Op::IAdd
+        | Op::ISub
+        | Op::IMul
+        | Op::UDiv
+        | Op::SDiv
+        | Op::UMod
+        | Op::SRem
+        | Op::SMod
+        | Op::FAdd
+        | Op::FSub
+        | Op::FMul
+        | Op::FDiv
+        | Op::FRem
+        | Op::FMod
+        | Op::BitwiseAnd
+        | Op::BitwiseOr
+        | Op::BitwiseXor
+        | Op::LogicalAnd
+        | Op::LogicalOr => true,
+        _ => false,
+    }
+}
+
+/// Comparisons of two operands with the same type
+fn is_comparison(opcode: Op) -> bool {
+    match opcode {
+        Op::IEqual
+        | Op::INotEqual
+        | Op::UGreaterThan
+        | Op::SGreaterThan
+        | Op::UGreaterThanEqual
+        | Op::SGreaterThanEqual
+        | Op::ULessThan
+        | Op::SLessThan
+        | Op::ULessThanEqual
+        | Op::SLessThanEqual
+        | Op::FOrdEqual
+        | Op::FOrdNotEqual
+        | Op::FOrdLessThan
+        | Op::FOrdGreaterThan
+        | Op::FOrdLessThanEqual
+        | Op::FOrdGreaterThanEqual
+        | Op::LogicalEqual
+        | Op::LogicalNotEqual => true,
+        _ => false,
+    }
+}
+
+struct Validator<'m> {
+    module: &'m Module,
+    names: HashMap<u32, &'m str>,
+    decorations: HashSet<(u32, Decoration)>,
+    member_decorations: HashSet<(u32, u32, Decoration)>,
+    /// Types, constants and global variables
+    defs: HashMap<u32, &'m Instruction>,
+    /// The type of every id that has one
+    types: HashMap<u32, u32>,
+    functions: HashMap<u32, &'m Function>,
+    /// Every id that is defined in the module
+    ids: HashSet<u32>,
+    errors: Vec<Error>,
+}
+
+impl<'m> Validator<'m> {
+    fn new(module: &'m Module) -> Self {
+        let mut validator = Validator {
+            module,
+            names: HashMap::new(),
+            decorations: HashSet::new(),
+            member_decorations: HashSet::new(),
+            defs: HashMap::new(),
+            types: HashMap::new(),
+            functions: HashMap::new(),
+            ids: HashSet::new(),
+            errors: Vec::new(),
+        };
+        for inst in &module.debugs {
+            if let (Op::Name, Some(&Operand::LiteralString(ref name))) =
+                (inst.class.opcode, inst.operands.get(1))
+            {
+                if let Some(id) = id_ref(&inst.operands[0]) {
+                    validator.names.insert(id, name);
+                }
+            }
+        }
+        for inst in &module.annotations {
+            let operands = &inst.operands[..];
+            match (inst.class.opcode, operands.get(1), operands.get(2)) {
+                (Op::Decorate, Some(&Operand::Decoration(decoration)), _) => {
+                    if let Some(id) = id_ref(&operands[0]) {
+                        validator.decorations.insert((id, decoration));
+                    }
+                }
+                (Op::MemberDecorate, Some(member), Some(&Operand::Decoration(decoration))) => {
+                    if let (Some(id), Some(member)) = (id_ref(&operands[0]), literal(member)) {
+                        validator
+                            .member_decorations
+                            .insert((id, member, decoration));
+                    }
+                }
+                _ => (),
+            }
+        }
+        for function in &module.functions {
+            if let Some(id) = function.def.as_ref().and_then(|def| def.result_id) {
+                validator.functions.insert(id, function);
+            }
+        }
+        validator
+    }
+
+    fn all_instructions(&self) -> impl Iterator<Item = &'m Instruction> {
+        let module = self.module;
+        module
+            .ext_inst_imports
+            .iter()
+            .chain(&module.types_global_values)
+            .chain(module.functions.iter().flat_map(|function| {
+                function
+                    .def
+                    .iter()
+                    .chain(&function.parameters)
+                    .chain(function.basic_blocks.iter().flat_map(|block| {
+                        block.label.iter().chain(&block.instructions)
+                    })).chain(&function.end)
+            }))
+    }
+
+    fn error(&mut self, function: Option<u32>, message: String) {
+        let function = function.map(|id| self.name(id));
+        self.errors.push(Error { function, message });
+    }
+
+    fn name(&self, id: u32) -> String {
+        self.names
+            .get(&id)
+            .map_or_else(|| format!("%{}", id), |name| name.to_string())
+    }
+
+    fn def(&self, id: u32, opcode: Op) -> Option<&'m Instruction> {
+        match self.defs.get(&id) {
+            Some(&inst) if inst.class.opcode == opcode => Some(inst),
+            _ => None,
+        }
+    }
+
+    /// The storage class and the pointee of a pointer type
+    fn pointer(&self, ty: u32) -> Option<(StorageClass, u32)> {
+        let pointer = self.def(ty, Op::TypePointer)?;
+        match (&pointer.operands[0], &pointer.operands[1]) {
+            (&Operand::StorageClass(storage_class), &Operand::IdRef(pointee)) => {
+                Some((storage_class, pointee))
+            }
+            _ => None,
+        }
+    }
+
+    /// Types are compared structurally, a type can be declared more than once
+    fn same_type(&self, a: u32, b: u32) -> bool {
+        if a == b {
+            return true;
+        }
+        match (self.defs.get(&a), self.defs.get(&b)) {
+            (Some(a), Some(b)) => {
+                a.class.opcode == b.class.opcode
+                    && a.operands.len() == b.operands.len()
+                    && a.operands.iter().zip(&b.operands).all(|pair| match pair {
+                        (&Operand::IdRef(a), &Operand::IdRef(b)) => self.same_type(a, b),
+                        (a, b) => a == b,
+                    })
+            }
+            _ => false,
+        }
+    }
+
+    fn is_bool(&self, ty: u32) -> bool {
+        match self.defs.get(&ty) {
+            Some(inst) if inst.class.opcode == Op::TypeBool => true,
+            Some(inst) if inst.class.opcode == Op::TypeVector => {
+                id_ref(&inst.operands[0]).map_or(false, |elem| self.is_bool(elem))
+            }
+            _ => false,
+        }
+    }
+
+    /// Every id is defined once, every used id is defined somewhere and
+    /// every result type is a type.
+    fn check_ids(&mut self) {
+        let mut duplicates = Vec::new();
+        for inst in self.all_instructions() {
+            if let Some(id) = inst.result_id {
+                if !self.ids.insert(id) {
+                    duplicates.push(id);
+                }
+                if let Some(ty) = inst.result_type {
+                    self.types.insert(id, ty);
+                }
+            }
+        }
+        let module = self.module;
+        for inst in &module.types_global_values {
+            if let Some(id) = inst.result_id {
+                self.defs.insert(id, inst);
+            }
+        }
+        for id in duplicates {
+            self.error(None, format!("%{} is defined more than once", id));
+        }
+        let global_insts = module
+            .entry_points
+            .iter()
+            .chain(&module.execution_modes)
+            .chain(&module.debugs)
+            .chain(&module.annotations)
+            .chain(&module.types_global_values);
+        let mut undefined = Vec::new();
+        for inst in global_insts {
+            for id in id_refs(inst) {
+                if !self.ids.contains(&id) {
+                    undefined.push((id, inst.class.opname));
+                }
+            }
+        }
+        let mut not_a_type = Vec::new();
+        for inst in self.all_instructions() {
+            if let Some(ty) = inst.result_type {
+                let is_type = self.defs.get(&ty).map_or(false, |def| is_type(def.class.opcode));
+                if !is_type {
+                    not_a_type.push((ty, inst.class.opname));
+                }
+            }
+        }
+        for (id, opname) in undefined {
+            self.error(None, format!("Op{} uses %{}, which is not defined", opname, id));
+        }
+        for (id, opname) in not_a_type {
+            self.error(None, format!("The result type %{} of Op{} is not a type", id, opname));
+        }
+    }
+
+    /// Blocks in memory that is shared with the host need an explicit layout
+    fn check_layouts(&mut self) {
+        let module = self.module;
+        let mut blocks = Vec::new();
+        for inst in &module.types_global_values {
+            if inst.class.opcode != Op::Variable {
+                continue;
+            }
+            let (storage_class, pointee) = match inst.result_type.and_then(|ty| self.pointer(ty)) {
+                Some(pointer) => pointer,
+                None => continue,
+            };
+            match storage_class {
+                StorageClass::Uniform | StorageClass::StorageBuffer | StorageClass::PushConstant => {
+                    blocks.push((inst.result_id, storage_class, pointee))
+                }
+                _ => (),
+            }
+        }
+        let mut checked = HashSet::new();
+        for (var, storage_class, ty) in blocks {
+            // Arrays of descriptors
+            let mut block = ty;
+            while let Some(array) = self
+                .def(block, Op::TypeArray)
+                .or_else(|| self.def(block, Op::TypeRuntimeArray))
+            {
+                match id_ref(&array.operands[0]) {
+                    Some(elem) => block = elem,
+                    None => break,
+                }
+            }
+            let decorated = self.decorations.contains(&(block, Decoration::Block))
+                || self.decorations.contains(&(block, Decoration::BufferBlock));
+            if self.def(block, Op::TypeStruct).is_none() || !decorated {
+                let var = var.map_or_else(String::new, |var| self.name(var));
+                self.error(
+                    None,
+                    format!(
+                        "The variable `{}` in the {:?} storage class is not a block",
+                        var, storage_class
+                    ),
+                );
+                continue;
+            }
+            self.check_explicit_layout(block, &mut checked);
+        }
+    }
+
+    /// Every member of a struct has an `Offset`, every array an `ArrayStride`
+    /// and every matrix a `MatrixStride`.
+    fn check_explicit_layout(&mut self, ty: u32, checked: &mut HashSet<u32>) {
+        if !checked.insert(ty) {
+            return;
+        }
+        let inst = match self.defs.get(&ty) {
+            Some(&inst) => inst,
+            None => return,
+        };
+        match inst.class.opcode {
+            Op::TypeStruct => {
+                for (member, operand) in inst.operands.iter().enumerate() {
+                    let member = member as u32;
+                    let member_ty = match id_ref(operand) {
+                        Some(member_ty) => member_ty,
+                        None => continue,
+                    };
+                    if !self.member_decorations.contains(&(ty, member, Decoration::Offset)) {
+                        let message =
+                            format!("Member {} of `{}` has no Offset", member, self.name(ty));
+                        self.error(None, message);
+                    }
+                    let is_matrix = self.def(member_ty, Op::TypeMatrix).is_some();
+                    let stride = self
+                        .member_decorations
+                        .contains(&(ty, member, Decoration::MatrixStride));
+                    if is_matrix && !stride {
+                        let message =
+                            format!("Member {} of `{}` has no MatrixStride", member, self.name(ty));
+                        self.error(None, message);
+                    }
+                    self.check_explicit_layout(member_ty, checked);
+                }
+            }
+            Op::TypeArray | Op::TypeRuntimeArray => {
+                if !self.decorations.contains(&(ty, Decoration::ArrayStride)) {
+                    let message = format!("The array %{} has no ArrayStride", ty);
+                    self.error(None, message);
+                }
+                if let Some(elem) = id_ref(&inst.operands[0]) {
+                    self.check_explicit_layout(elem, checked);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Validates the body of a function
+struct FunctionValidator<'v, 'm: 'v> {
+    validator: &'v mut Validator<'m>,
+    function: &'m Function,
+    id: Option<u32>,
+    /// The index of the block of every label
+    labels: HashMap<u32, usize>,
+    /// Where the results of the function are defined, `(block, instruction)`
+    defs: HashMap<u32, (usize, usize)>,
+    params: HashSet<u32>,
+    /// The immediate dominator of every reachable block
+    idoms: Vec<Option<usize>>,
+}
+
+impl<'v, 'm> FunctionValidator<'v, 'm> {
+    fn new(validator: &'v mut Validator<'m>, function: &'m Function) -> Self {
+        let labels: HashMap<_, _> = function
+            .basic_blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, block)| block.label.as_ref()?.result_id.map(|id| (id, idx)))
+            .collect();
+        let mut defs = HashMap::new();
+        for (block_idx, block) in function.basic_blocks.iter().enumerate() {
+            for (inst_idx, inst) in block.instructions.iter().enumerate() {
+                if let Some(id) = inst.result_id {
+                    defs.insert(id, (block_idx, inst_idx));
+                }
+            }
+        }
+        let params = function
+            .parameters
+            .iter()
+            .filter_map(|param| param.result_id)
+            .collect();
+        let mut function_validator = FunctionValidator {
+            id: function.def.as_ref().and_then(|def| def.result_id),
+            validator,
+            function,
+            labels,
+            defs,
+            params,
+            idoms: Vec::new(),
+        };
+        function_validator.idoms = function_validator.dominators();
+        function_validator
+    }
+
+    fn error(&mut self, message: String) {
+        let id = self.id;
+        self.validator.error(id, message);
+    }
+
+    fn successors(&self, block: &BasicBlock) -> Vec<usize> {
+        let terminator = match block.instructions.last() {
+            Some(terminator) => terminator,
+            None => return Vec::new(),
+        };
+        match terminator.class.opcode {
+            Op::Branch | Op::BranchConditional | Op::Switch => id_refs(terminator)
+                .filter_map(|id| self.labels.get(&id).cloned())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Computes the immediate dominators with the algorithm of Cooper,
+    /// Harvey and Kennedy. Unreachable blocks have no dominator.
+    fn dominators(&self) -> Vec<Option<usize>> {
+        let blocks = &self.function.basic_blocks;
+        let successors: Vec<_> = blocks.iter().map(|block| self.successors(block)).collect();
+        let mut predecessors = vec![Vec::new(); blocks.len()];
+        for (block, successors) in successors.iter().enumerate() {
+            for &successor in successors {
+                predecessors[successor].push(block);
+            }
+        }
+        // Reverse post order from the entry block
+        let mut post_order = Vec::new();
+        let mut visited = vec![false; blocks.len()];
+        let mut stack = Vec::new();
+        if !blocks.is_empty() {
+            visited[0] = true;
+            stack.push((0, 0));
+        }
+        while let Some((block, next)) = stack.pop() {
+            if let Some(&successor) = successors[block].get(next) {
+                stack.push((block, next + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, 0));
+                }
+            } else {
+                post_order.push(block);
+            }
+        }
+        let mut order = vec![0; blocks.len()];
+        for (idx, &block) in post_order.iter().enumerate() {
+            order[block] = idx;
+        }
+        let mut idoms = vec![None; blocks.len()];
+        if blocks.is_empty() {
+            return idoms;
+        }
+        idoms[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in post_order.iter().rev().skip(1) {
+                let mut new_idom = None;
+                for &pred in &predecessors[block] {
+                    if idoms[pred].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(other) => {
+                            let (mut a, mut b) = (pred, other);
+                            while a != b {
+                                while order[a] < order[b] {
+                                    a = idoms[a].expect("processed");
+                                }
+                                while order[b] < order[a] {
+                                    b = idoms[b].expect("processed");
+                                }
+                            }
+                            a
+                        }
+                    });
+                }
+                if new_idom.is_some() && idoms[block] != new_idom {
+                    idoms[block] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idoms
+    }
+
+    fn is_reachable(&self, block: usize) -> bool {
+        self.idoms[block].is_some()
+    }
+
+    fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.idoms[b] {
+                Some(idom) if idom != b => b = idom,
+                _ => return false,
+            }
+        }
+    }
+
+    fn check(&mut self) {
+        self.check_uses();
+        self.check_types();
+        self.check_structured_control_flow();
+    }
+
+    /// Every id that is used is defined by an instruction that dominates the
+    /// use.
+    fn check_uses(&mut self) {
+        let function = self.function;
+        let mut errors = Vec::new();
+        for (block_idx, block) in function.basic_blocks.iter().enumerate() {
+            if !self.is_reachable(block_idx) {
+                continue;
+            }
+            for (inst_idx, inst) in block.instructions.iter().enumerate() {
+                let opname = inst.class.opname;
+                if inst.class.opcode == Op::Phi {
+                    // The value has to dominate the end of the parent block
+                    for pair in inst.operands.chunks(2) {
+                        let value = pair.first().and_then(id_ref);
+                        let parent = pair.get(1).and_then(id_ref);
+                        let parent = parent.and_then(|parent| self.labels.get(&parent).cloned());
+                        if let (Some(value), Some(parent)) = (value, parent) {
+                            if let Some(&(def_block, _)) = self.defs.get(&value) {
+                                let reachable = self.is_reachable(parent);
+                                if reachable && !self.dominates(def_block, parent) {
+                                    errors.push(format!(
+                                        "OpPhi uses %{}, which does not dominate its parent block",
+                                        value
+                                    ));
+                                }
+                            }
+                        }
+                    }
+                    continue;
+                }
+                for id in id_refs(inst) {
+                    if self.labels.contains_key(&id) || self.params.contains(&id) {
+                        continue;
+                    }
+                    match self.defs.get(&id) {
+                        Some(&(def_block, def_inst)) => {
+                            let dominated = if def_block == block_idx {
+                                def_inst < inst_idx
+                            } else {
+                                self.dominates(def_block, block_idx)
+                            };
+                            if !dominated {
+                                errors.push(format!(
+                                    "Op{} uses %{}, which does not dominate the use",
+                                    opname, id
+                                ));
+                            }
+                        }
+                        None => {
+                            let is_global = self.validator.defs.contains_key(&id)
+                                || self.validator.functions.contains_key(&id)
+                                || self.validator.module.ext_inst_imports.iter().any(|import| {
+                                    import.result_id == Some(id)
+                                });
+                            if !is_global {
+                                errors.push(format!(
+                                    "Op{} uses %{}, which is not defined in the function",
+                                    opname, id
+                                ));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        for message in errors {
+            self.error(message);
+        }
+    }
+
+    fn ty(&self, id: u32) -> Option<u32> {
+        self.validator.types.get(&id).cloned()
+    }
+
+    fn operand_ty(&self, inst: &Instruction, idx: usize) -> Option<u32> {
+        inst.operands.get(idx).and_then(id_ref).and_then(|id| self.ty(id))
+    }
+
+    fn check_types(&mut self) {
+        let function = self.function;
+        let return_ty = function.def.as_ref().and_then(|def| def.result_type);
+        let mut errors = Vec::new();
+        for inst in function.basic_blocks.iter().flat_map(|block| &block.instructions) {
+            let v = &*self.validator;
+            let opname = inst.class.opname;
+            let result_ty = inst.result_type;
+            match inst.class.opcode {
+                Op::Load => {
+                    let pointee = self.operand_ty(inst, 0).and_then(|ty| v.pointer(ty));
+                    match (pointee, result_ty) {
+                        (Some((_, pointee)), Some(ty)) if v.same_type(pointee, ty) => (),
+                        _ => errors.push(format!(
+                            "OpLoad %{} does not load the type of its pointer",
+                            inst.result_id.unwrap_or(0)
+                        )),
+                    }
+                }
+                Op::Store => {
+                    let pointee = self.operand_ty(inst, 0).and_then(|ty| v.pointer(ty));
+                    let value = self.operand_ty(inst, 1);
+                    match (pointee, value) {
+                        (Some((_, pointee)), Some(value)) if v.same_type(pointee, value) => (),
+                        _ => errors.push(format!(
+                            "OpStore to %{} does not store the type of the pointer",
+                            inst.operands.first().and_then(id_ref).unwrap_or(0)
+                        )),
+                    }
+                }
+                Op::AccessChain | Op::InBoundsAccessChain => {
+                    let base = self.operand_ty(inst, 0).and_then(|ty| v.pointer(ty));
+                    let result = result_ty.and_then(|ty| v.pointer(ty));
+                    match (base, result) {
+                        (Some((base, _)), Some((result, _))) if base == result => (),
+                        _ => errors.push(format!(
+                            "Op{} %{} does not have a pointer type with the storage class \
+                             of its base",
+                            opname,
+                            inst.result_id.unwrap_or(0)
+                        )),
+                    }
+                }
+                Op::Variable => {
+                    let pointer = result_ty.and_then(|ty| v.pointer(ty));
+                    let function_class = match (pointer, &inst.operands[0]) {
+                        (Some((pointer, _)), &Operand::StorageClass(storage_class)) => {
+                            pointer == StorageClass::Function && storage_class == pointer
+                        }
+                        _ => false,
+                    };
+                    if !function_class {
+                        errors.push(format!(
+                            "The local variable %{} is not in the Function storage class",
+                            inst.result_id.unwrap_or(0)
+                        ));
+                    }
+                }
+                Op::FunctionCall => {
+                    let callee = inst.operands.first().and_then(id_ref);
+                    let callee = callee.and_then(|callee| v.functions.get(&callee));
+                    match callee {
+                        Some(callee) => {
+                            let callee_ty = callee.def.as_ref().and_then(|def| def.result_type);
+                            let ret_matches = match (callee_ty, result_ty) {
+                                (Some(callee_ty), Some(ty)) => v.same_type(callee_ty, ty),
+                                _ => false,
+                            };
+                            let args = inst.operands.len() - 1;
+                            let params = callee.parameters.len();
+                            if !ret_matches {
+                                errors.push(format!(
+                                    "OpFunctionCall %{} has a different type than its callee",
+                                    inst.result_id.unwrap_or(0)
+                                ));
+                            }
+                            if args != params {
+                                errors.push(format!(
+                                    "OpFunctionCall %{} passes {} arguments, but the callee \
+                                     takes {}",
+                                    inst.result_id.unwrap_or(0),
+                                    args,
+                                    params
+                                ));
+                            }
+                        }
+                        None => errors.push(format!(
+                            "OpFunctionCall %{} does not call a function",
+                            inst.result_id.unwrap_or(0)
+                        )),
+                    }
+                }
+                Op::ReturnValue => {
+                    let value = self.operand_ty(inst, 0);
+                    match (value, return_ty) {
+                        (Some(value), Some(ret)) if v.same_type(value, ret) => (),
+                        _ => errors.push(
+                            "OpReturnValue does not return the type of the function".to_string(),
+                        ),
+                    }
+                }
+                Op::BranchConditional => {
+                    let condition = self.operand_ty(inst, 0);
+                    if !condition.map_or(false, |ty| v.is_bool(ty)) {
+                        errors.push("The condition of OpBranchConditional is not a bool".into());
+                    }
+                }
+                opcode if is_arithmetic(opcode) || is_comparison(opcode) => {
+                    let lhs = self.operand_ty(inst, 0);
+                    let rhs = self.operand_ty(inst, 1);
+                    let operands_match = match (lhs, rhs) {
+                        (Some(lhs), Some(rhs)) => v.same_type(lhs, rhs),
+                        _ => false,
+                    };
+                    let result_matches = match (is_arithmetic(opcode), lhs, result_ty) {
+                        (true, Some(lhs), Some(ty)) => v.same_type(lhs, ty),
+                        (false, _, Some(ty)) => v.is_bool(ty),
+                        _ => false,
+                    };
+                    if !operands_match || !result_matches {
+                        errors.push(format!(
+                            "The types of Op{} %{} do not match",
+                            opname,
+                            inst.result_id.unwrap_or(0)
+                        ));
+                    }
+                }
+                _ => (),
+            }
+        }
+        for message in errors {
+            self.error(message);
+        }
+    }
+
+    /// The merge instruction of a block, which is the instruction before the
+    /// terminator
+    fn merge_inst(block: &BasicBlock) -> Option<&Instruction> {
+        let len = block.instructions.len();
+        if len < 2 {
+            return None;
+        }
+        let inst = &block.instructions[len - 2];
+        match inst.class.opcode {
+            Op::SelectionMerge | Op::LoopMerge => Some(inst),
+            _ => None,
+        }
+    }
+
+    fn check_structured_control_flow(&mut self) {
+        let function = self.function;
+        // Branching to the merge block or the continue target of a loop
+        // breaks out of the loop or continues it, that does not need a merge.
+        let mut loop_targets = HashSet::new();
+        for block in &function.basic_blocks {
+            if let Some(merge) = Self::merge_inst(block) {
+                if merge.class.opcode == Op::LoopMerge {
+                    loop_targets.extend(id_refs(merge));
+                }
+            }
+        }
+        let mut errors = Vec::new();
+        for (block_idx, block) in function.basic_blocks.iter().enumerate() {
+            let label = block
+                .label
+                .as_ref()
+                .and_then(|label| label.result_id)
+                .unwrap_or(0);
+            let merge = Self::merge_inst(block);
+            let terminator = match block.instructions.last() {
+                Some(terminator) => terminator,
+                None => {
+                    errors.push(format!("The block %{} has no terminator", label));
+                    continue;
+                }
+            };
+            let conditional = match terminator.class.opcode {
+                Op::BranchConditional | Op::Switch => true,
+                _ => false,
+            };
+            if conditional && merge.is_none() {
+                let is_break_or_continue = id_refs(terminator)
+                    .filter(|id| self.labels.contains_key(id))
+                    .any(|target| loop_targets.contains(&target));
+                if terminator.class.opcode == Op::Switch || !is_break_or_continue {
+                    errors.push(format!(
+                        "The conditional branch in %{} is not preceded by OpSelectionMerge \
+                         or OpLoopMerge",
+                        label
+                    ));
+                }
+            }
+            let merge = match merge {
+                Some(merge) => merge,
+                None => continue,
+            };
+            if !self.is_reachable(block_idx) {
+                continue;
+            }
+            // The merge block and the continue target of a loop
+            for target in id_refs(merge) {
+                let target_idx = match self.labels.get(&target) {
+                    Some(&target_idx) => target_idx,
+                    None => {
+                        errors.push(format!(
+                            "Op{} in %{} names %{}, which is not a block",
+                            merge.class.opname, label, target
+                        ));
+                        continue;
+                    }
+                };
+                if self.is_reachable(target_idx) && !self.dominates(block_idx, target_idx) {
+                    errors.push(format!(
+                        "The header %{} does not dominate its merge or continue block %{}",
+                        label, target
+                    ));
+                }
+            }
+        }
+        for message in errors {
+            self.error(message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspirv::mr::Builder;
+
+    fn module_with_body<F>(body: F) -> Module
+    where
+        F: FnOnce(&mut Builder),
+    {
+        module_with_globals(|_| (), body)
+    }
+
+    fn module_with_globals<G, F>(globals: G, body: F) -> Module
+    where
+        G: FnOnce(&mut Builder),
+        F: FnOnce(&mut Builder),
+    {
+        let mut b = Builder::new();
+        b.capability(spirv::Capability::Shader);
+        b.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
+        globals(&mut b);
+        let void = b.type_void();
+        let fn_ty = b.type_function(void, &[]);
+        let main = b
+            .begin_function(void, None, spirv::FunctionControl::empty(), fn_ty)
+            .unwrap();
+        b.begin_basic_block(None).unwrap();
+        body(&mut b);
+        b.end_function().unwrap();
+        b.entry_point(spirv::ExecutionModel::GLCompute, main, "main", &[]);
+        b.module()
+    }
+
+    #[test]
+    fn selection() {
+        let module = module_with_body(|b| {
+            let bool_ty = b.type_bool();
+            let condition = b.constant_true(bool_ty);
+            let (then, merge) = (b.id(), b.id());
+            b.selection_merge(merge, spirv::SelectionControl::empty())
+                .unwrap();
+            b.branch_conditional(condition, then, merge, &[]).unwrap();
+            b.begin_basic_block(Some(then)).unwrap();
+            b.branch(merge).unwrap();
+            b.begin_basic_block(Some(merge)).unwrap();
+            b.ret().unwrap();
+        });
+        assert_eq!(validate(&module), Ok(()));
+    }
+
+    #[test]
+    fn missing_selection_merge() {
+        let module = module_with_body(|b| {
+            let bool_ty = b.type_bool();
+            let condition = b.constant_true(bool_ty);
+            let (then, merge) = (b.id(), b.id());
+            b.branch_conditional(condition, then, merge, &[]).unwrap();
+            b.begin_basic_block(Some(then)).unwrap();
+            b.branch(merge).unwrap();
+            b.begin_basic_block(Some(merge)).unwrap();
+            b.ret().unwrap();
+        });
+        let errors = validate(&module).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("not preceded by OpSelectionMerge"));
+    }
+
+    #[test]
+    fn use_before_definition() {
+        let module = module_with_body(|b| {
+            let uint = b.type_int(32, 0);
+            let one = b.constant_u32(uint, 1);
+            let sum = b.id();
+            b.iadd(uint, None, sum, one).unwrap();
+            b.iadd(uint, Some(sum), one, one).unwrap();
+            b.ret().unwrap();
+        });
+        let errors = validate(&module).unwrap_err();
+        assert!(errors[0].message.contains("does not dominate the use"));
+    }
+
+    #[test]
+    fn block_without_offset() {
+        let module = module_with_globals(
+            |b| {
+                let float = b.type_float(32);
+                let block = b.type_struct(&[float, float]);
+                b.decorate(block, Decoration::Block, &[]);
+                b.member_decorate(block, 0, Decoration::Offset, &[Operand::LiteralInt32(0)]);
+                let ptr = b.type_pointer(None, StorageClass::Uniform, block);
+                b.variable(ptr, None, StorageClass::Uniform, None);
+            },
+            |b| {
+                b.ret().unwrap();
+            },
+        );
+        let errors = validate(&module).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("Member 1"));
+    }
+}
@@ -32,7 +32,9 @@ use rustc_driver::driver::{self, CompileController, CompileState};
 use rustc_driver::{RustcDefaultCalls, run, run_compiler, Compilation, CompilerCalls};
 use rustc_mir::monomorphize::collector::{collect_crate_mono_items, MonoItemCollectionMode};
 use syntax_pos::DUMMY_SP;
-struct RlslCompilerCalls;
+struct RlslCompilerCalls {
+    options: rlsl::Options,
+}
 
 use rustc::session::config::{self, ErrorOutputType, Input};
 use rustc_errors as errors;
@@ -79,6 +81,7 @@ impl<'a> CompilerCalls<'a> for RlslCompilerCalls {
         matches: &getopts::Matches,
     ) -> CompileController<'a> {
 
+        let options = self.options.clone();
         let mut controller = CompileController::basic();
         session.abort_if_errors();
         controller.keep_ast = session.opts.debugging_opts.keep_ast;
@@ -90,7 +93,7 @@ impl<'a> CompilerCalls<'a> for RlslCompilerCalls {
                 controller.keep_ast = true;
                 controller.make_glob_map = rustc_resolve::MakeGlobMap::Yes;
                 controller.after_analysis.run_callback_on_error = false;
-                controller.after_analysis.callback = box |state: &mut CompileState| {
+                controller.after_analysis.callback = box move |state: &mut CompileState| {
                     let tcx = &state.tcx.unwrap();
                     let f = rustc_driver::driver::build_output_filenames(
                         state.input,
@@ -104,7 +107,7 @@ impl<'a> CompilerCalls<'a> for RlslCompilerCalls {
                     let (items, _) = collect_crate_mono_items(*tcx, MonoItemCollectionMode::Eager);
                     // TODO: Custom collector not needed anymore?
                     let items = rlsl::collector::trans_all_items(*tcx, &items);
-                    rlsl::trans_spirv(*tcx, &items);
+                    rlsl::trans_spirv(*tcx, &items, &options);
                 };
             }
         }
@@ -140,9 +143,23 @@ fn main() {
     // args.extend_from_slice(&["--extern".into(), core_path]);
     // args.extend_from_slice(&["--extern".into(), std_path]);
     // args.extend_from_slice(&["--extern".into(), builtins_path]);
+    let options = match rlsl::Options::from_args(&mut args) {
+        Ok(options) => options,
+        Err(err) => early_error(ErrorOutputType::default(), &err),
+    };
+    // Editors read the diagnostics of `rlsl --check` as json, `cargo check`
+    // already asks for json.
+    if options.check && !args.iter().any(|arg| arg.starts_with("--error-format")) {
+        args.push("--error-format=json".into());
+    }
+    // Only binaries are translated, `rlsl --check src/main.rs` has to check
+    // the shaders without an explicit `--crate-type bin`.
+    if options.check && !args.iter().any(|arg| arg.starts_with("--crate-type")) {
+        args.extend_from_slice(&["--crate-type".into(), "bin".into()]);
+    }
     args.extend_from_slice(&["-L".into(), l]);
     args.extend_from_slice(&["--cfg".into(), "spirv".into()]);
     args.extend_from_slice(&["-Z".into(), "always-encode-mir".into()]);
     args.extend_from_slice(&["-Z".into(), "mir-opt-level=3".into()]);
-    let _ = run(move || run_compiler(&args, Box::new(RlslCompilerCalls), None, None));
+    let _ = run(move || run_compiler(&args, Box::new(RlslCompilerCalls { options }), None, None));
 }
@@ -0,0 +1,625 @@
+//! Declares the capabilities and extensions of a module. They are inferred
+//! from the instructions after codegen, so that a new feature can not forget
+//! to declare what it uses. Usage that the target environment does not
+//! support is reported as an error.
+use rspirv::mr::{Instruction, Module, Operand};
+use rustc::session::Session;
+use spirv::{
+    self, BuiltIn, Capability, Decoration, Dim, ExecutionModel, ImageFormat, Op, StorageClass,
+};
+use std::collections::{HashMap, HashSet};
+use TargetEnv;
+
+fn id_ref(operand: &Operand) -> u32 {
+    match *operand {
+        Operand::IdRef(id) => id,
+        ref operand => panic!("Expected an id, found {:?}", operand),
+    }
+}
+
+fn literal(operand: &Operand) -> u32 {
+    match *operand {
+        Operand::LiteralInt32(value) => value,
+        ref operand => panic!("Expected a literal, found {:?}", operand),
+    }
+}
+
+#[derive(Default)]
+struct Requirements {
+    capabilities: Vec<Capability>,
+    extensions: Vec<&'static str>,
+}
+
+impl Requirements {
+    fn capability(&mut self, capability: Capability) {
+        if !self.capabilities.contains(&capability) {
+            self.capabilities.push(capability);
+        }
+    }
+
+    fn extension(&mut self, extension: &'static str) {
+        if !self.extensions.contains(&extension) {
+            self.extensions.push(extension);
+        }
+    }
+}
+
+struct ModuleInfo<'m> {
+    decorations: HashSet<(u32, Decoration)>,
+    /// Types, constants and global variables
+    defs: HashMap<u32, &'m Instruction>,
+    /// The type of every id that has one
+    types: HashMap<u32, u32>,
+    /// The stages of the entry points that use a global variable
+    stages: HashMap<u32, Vec<ExecutionModel>>,
+}
+
+impl<'m> ModuleInfo<'m> {
+    fn new(module: &'m Module) -> Self {
+        let mut info = ModuleInfo {
+            decorations: HashSet::new(),
+            defs: HashMap::new(),
+            types: HashMap::new(),
+            stages: HashMap::new(),
+        };
+        for inst in &module.annotations {
+            if let (Op::Decorate, Some(&Operand::Decoration(decoration))) =
+                (inst.class.opcode, inst.operands.get(1))
+            {
+                info.decorations.insert((id_ref(&inst.operands[0]), decoration));
+            }
+        }
+        for inst in &module.types_global_values {
+            if let Some(id) = inst.result_id {
+                info.defs.insert(id, inst);
+            }
+        }
+        for inst in all_instructions(module) {
+            if let (Some(id), Some(ty)) = (inst.result_id, inst.result_type) {
+                info.types.insert(id, ty);
+            }
+        }
+        for inst in &module.entry_points {
+            let stage = match inst.operands[0] {
+                Operand::ExecutionModel(stage) => stage,
+                _ => continue,
+            };
+            for operand in &inst.operands[3..] {
+                info.stages
+                    .entry(id_ref(operand))
+                    .or_insert_with(Vec::new)
+                    .push(stage);
+            }
+        }
+        info
+    }
+
+    /// The stages that use the variable `id`, or the variables of the
+    /// struct type `id` for a member decoration.
+    fn stages(&self, id: u32) -> Vec<ExecutionModel> {
+        if let Some(stages) = self.stages.get(&id) {
+            return stages.clone();
+        }
+        self.stages
+            .iter()
+            .filter(|&(&var, _)| {
+                self.types
+                    .get(&var)
+                    .and_then(|&ty| self.def(ty, Op::TypePointer))
+                    .map_or(false, |pointer| id_ref(&pointer.operands[1]) == id)
+            }).flat_map(|(_, stages)| stages.iter().cloned())
+            .collect()
+    }
+
+    fn def(&self, id: u32, opcode: Op) -> Option<&'m Instruction> {
+        match self.defs.get(&id) {
+            Some(&inst) if inst.class.opcode == opcode => Some(inst),
+            _ => None,
+        }
+    }
+
+    fn is_block(&self, id: u32) -> bool {
+        self.decorations.contains(&(id, Decoration::Block))
+            || self.decorations.contains(&(id, Decoration::BufferBlock))
+    }
+
+    /// The `OpTypeImage` of an image or of a pointer to an image
+    fn image_ty(&self, id: u32) -> Option<&'m Instruction> {
+        let ty = *self.types.get(&id)?;
+        self.def(ty, Op::TypeImage)
+    }
+
+    /// The capability that is needed to index an array of descriptors, which
+    /// is the pointee of `base`, with an index that is not uniform.
+    fn non_uniform_indexing(&self, base: u32) -> Option<Capability> {
+        let pointer = self.def(*self.types.get(&base)?, Op::TypePointer)?;
+        let storage_class = match pointer.operands[0] {
+            Operand::StorageClass(storage_class) => storage_class,
+            _ => return None,
+        };
+        let array = self.defs.get(&id_ref(&pointer.operands[1]))?;
+        match array.class.opcode {
+            Op::TypeArray | Op::TypeRuntimeArray => (),
+            _ => return None,
+        }
+        let elem = id_ref(&array.operands[0]);
+        let buffer = storage_class == StorageClass::StorageBuffer
+            || self.decorations.contains(&(elem, Decoration::BufferBlock));
+        if buffer {
+            Some(Capability::StorageBufferArrayNonUniformIndexingEXT)
+        } else if self.is_block(elem) {
+            Some(Capability::UniformBufferArrayNonUniformIndexingEXT)
+        } else {
+            None
+        }
+    }
+}
+
+fn all_instructions<'m>(module: &'m Module) -> impl Iterator<Item = &'m Instruction> {
+    module
+        .annotations
+        .iter()
+        .chain(&module.types_global_values)
+        .chain(module.functions.iter().flat_map(|function| {
+            function
+                .def
+                .iter()
+                .chain(&function.parameters)
+                .chain(function.basic_blocks.iter().flat_map(|block| &block.instructions))
+        }))
+}
+
+/// `stages` are the stages that use the builtin, `Layer` can only be written
+/// by a vertex shader with `SPV_EXT_shader_viewport_index_layer`.
+fn builtin_capabilities(
+    builtin: BuiltIn,
+    stages: &[ExecutionModel],
+    requirements: &mut Requirements,
+) {
+    let capability = match builtin {
+        BuiltIn::ClipDistance => Capability::ClipDistance,
+        BuiltIn::CullDistance => Capability::CullDistance,
+        BuiltIn::SampleId | BuiltIn::SamplePosition => Capability::SampleRateShading,
+        BuiltIn::Layer => {
+            if stages.contains(&ExecutionModel::Vertex) {
+                requirements.capability(Capability::ShaderViewportIndexLayerEXT);
+                requirements.extension("SPV_EXT_shader_viewport_index_layer");
+            }
+            if stages.is_empty() || stages.iter().any(|&stage| stage != ExecutionModel::Vertex) {
+                requirements.capability(Capability::Geometry);
+            }
+            return;
+        }
+        BuiltIn::ViewportIndex => Capability::MultiViewport,
+        _ => return,
+    };
+    requirements.capability(capability);
+}
+
+/// Formats that can be used with storage images without
+/// `StorageImageExtendedFormats`
+fn is_base_format(format: ImageFormat) -> bool {
+    use spirv::ImageFormat::*;
+    match format {
+        Unknown | Rgba32f | Rgba16f | R32f | Rgba8 | Rgba8Snorm | Rgba32i | Rgba16i | Rgba8i
+        | R32i | Rgba32ui | Rgba16ui | Rgba8ui | R32ui => true,
+        _ => false,
+    }
+}
+
+fn image_capabilities(inst: &Instruction, requirements: &mut Requirements) {
+    let dim = match inst.operands[1] {
+        Operand::Dim(dim) => dim,
+        _ => return,
+    };
+    let arrayed = literal(&inst.operands[3]) == 1;
+    let multisampled = literal(&inst.operands[4]) == 1;
+    // 1 is sampled, 2 is a storage image
+    let storage = literal(&inst.operands[5]) == 2;
+    let capability = match dim {
+        Dim::Dim1D if storage => Some(Capability::Image1D),
+        Dim::Dim1D => Some(Capability::Sampled1D),
+        Dim::DimRect if storage => Some(Capability::ImageRect),
+        Dim::DimRect => Some(Capability::SampledRect),
+        Dim::DimBuffer if storage => Some(Capability::ImageBuffer),
+        Dim::DimBuffer => Some(Capability::SampledBuffer),
+        Dim::DimCube if arrayed && storage => Some(Capability::ImageCubeArray),
+        Dim::DimCube if arrayed => Some(Capability::SampledCubeArray),
+        Dim::DimSubpassData => Some(Capability::InputAttachment),
+        _ => None,
+    };
+    if let Some(capability) = capability {
+        requirements.capability(capability);
+    }
+    if storage && multisampled {
+        requirements.capability(Capability::StorageImageMultisample);
+        if arrayed {
+            requirements.capability(Capability::ImageMSArray);
+        }
+    }
+    if let Some(&Operand::ImageFormat(format)) = inst.operands.get(6) {
+        if !is_base_format(format) {
+            requirements.capability(Capability::StorageImageExtendedFormats);
+        }
+    }
+}
+
+fn group_non_uniform_capability(inst: &Instruction) -> Option<Capability> {
+    let capability = match inst.class.opcode {
+        Op::GroupNonUniformElect => Capability::GroupNonUniform,
+        Op::GroupNonUniformAll | Op::GroupNonUniformAny | Op::GroupNonUniformAllEqual => {
+            Capability::GroupNonUniformVote
+        }
+        Op::GroupNonUniformBroadcast
+        | Op::GroupNonUniformBroadcastFirst
+        | Op::GroupNonUniformBallot
+        | Op::GroupNonUniformInverseBallot
+        | Op::GroupNonUniformBallotBitExtract
+        | Op::GroupNonUniformBallotBitCount
+        | Op::GroupNonUniformBallotFindLSB
+        | Op::GroupNonUniformBallotFindMSB => Capability::GroupNonUniformBallot,
+        Op::GroupNonUniformShuffle | Op::GroupNonUniformShuffleXor => {
+            Capability::GroupNonUniformShuffle
+        }
+        Op::GroupNonUniformShuffleUp | Op::GroupNonUniformShuffleDown => {
+            Capability::GroupNonUniformShuffleRelative
+        }
+        Op::GroupNonUniformQuadBroadcast | Op::GroupNonUniformQuadSwap => {
+            Capability::GroupNonUniformQuad
+        }
+        // The remaining group operations are the arithmetic ones
+        _ if inst.class.opname.trim_left_matches("Op").starts_with("GroupNonUniform") => {
+            match inst.operands.get(1) {
+                Some(&Operand::GroupOperation(spirv::GroupOperation::ClusteredReduce)) => {
+                    Capability::GroupNonUniformClustered
+                }
+                _ => Capability::GroupNonUniformArithmetic,
+            }
+        }
+        _ => return None,
+    };
+    Some(capability)
+}
+
+fn infer(module: &Module, target_env: TargetEnv) -> Requirements {
+    let info = ModuleInfo::new(module);
+    let mut requirements = Requirements::default();
+    requirements.capability(Capability::Shader);
+    for inst in all_instructions(module) {
+        match inst.class.opcode {
+            Op::TypeInt => match literal(&inst.operands[0]) {
+                8 => requirements.capability(Capability::Int8),
+                16 => requirements.capability(Capability::Int16),
+                64 => requirements.capability(Capability::Int64),
+                _ => (),
+            },
+            Op::TypeFloat => match literal(&inst.operands[0]) {
+                16 => requirements.capability(Capability::Float16),
+                64 => requirements.capability(Capability::Float64),
+                _ => (),
+            },
+            Op::TypeImage => image_capabilities(inst, &mut requirements),
+            Op::TypeRuntimeArray if info.is_block(id_ref(&inst.operands[0])) => {
+                requirements.capability(Capability::RuntimeDescriptorArrayEXT);
+            }
+            // Both start with the storage class
+            Op::TypePointer | Op::Variable => {
+                if let Operand::StorageClass(StorageClass::StorageBuffer) = inst.operands[0] {
+                    if target_env.spirv_version() < (1, 3) {
+                        requirements.extension("SPV_KHR_storage_buffer_storage_class");
+                    }
+                }
+            }
+            Op::ImageQuerySize
+            | Op::ImageQuerySizeLod
+            | Op::ImageQueryLevels
+            | Op::ImageQuerySamples
+            | Op::ImageQueryLod => requirements.capability(Capability::ImageQuery),
+            Op::ImageRead | Op::ImageWrite => {
+                let image = info.image_ty(id_ref(&inst.operands[0]));
+                let unknown_format = image.map_or(false, |image| {
+                    match (&image.operands[1], &image.operands[6]) {
+                        (&Operand::Dim(Dim::DimSubpassData), _) => false,
+                        (_, &Operand::ImageFormat(ImageFormat::Unknown)) => true,
+                        _ => false,
+                    }
+                });
+                if unknown_format && inst.class.opcode == Op::ImageRead {
+                    requirements.capability(Capability::StorageImageReadWithoutFormat);
+                } else if unknown_format {
+                    requirements.capability(Capability::StorageImageWriteWithoutFormat);
+                }
+            }
+            Op::AccessChain
+                if inst.result_id.map_or(false, |id| {
+                    info.decorations.contains(&(id, Decoration::NonUniformEXT))
+                }) =>
+            {
+                if let Some(capability) = info.non_uniform_indexing(id_ref(&inst.operands[0])) {
+                    requirements.capability(capability);
+                }
+            }
+            Op::Decorate => match (&inst.operands[1], inst.operands.get(2)) {
+                (&Operand::Decoration(Decoration::BuiltIn), Some(&Operand::BuiltIn(builtin))) => {
+                    let stages = info.stages(id_ref(&inst.operands[0]));
+                    builtin_capabilities(builtin, &stages, &mut requirements);
+                }
+                (&Operand::Decoration(Decoration::Sample), _) => {
+                    requirements.capability(Capability::SampleRateShading);
+                }
+                (&Operand::Decoration(Decoration::NonUniformEXT), _) => {
+                    requirements.capability(Capability::ShaderNonUniformEXT);
+                }
+                _ => (),
+            },
+            Op::MemberDecorate => {
+                if let Some(&Operand::BuiltIn(builtin)) = inst.operands.get(3) {
+                    let stages = info.stages(id_ref(&inst.operands[0]));
+                    builtin_capabilities(builtin, &stages, &mut requirements);
+                }
+            }
+            _ => {
+                if let Some(capability) = group_non_uniform_capability(inst) {
+                    requirements.capability(capability);
+                }
+            }
+        }
+    }
+    // Descriptor indexing is core since 1.5
+    let descriptor_indexing = requirements
+        .capabilities
+        .iter()
+        .any(|&capability| is_descriptor_indexing(capability));
+    if descriptor_indexing && target_env.spirv_version() < (1, 5) {
+        requirements.extension("SPV_EXT_descriptor_indexing");
+    }
+    requirements
+}
+
+fn is_descriptor_indexing(capability: Capability) -> bool {
+    match capability {
+        Capability::ShaderNonUniformEXT
+        | Capability::RuntimeDescriptorArrayEXT
+        | Capability::UniformBufferArrayNonUniformIndexingEXT
+        | Capability::StorageBufferArrayNonUniformIndexingEXT => true,
+        _ => false,
+    }
+}
+
+/// The SPIR-V version in which a capability was introduced
+fn min_version(capability: Capability) -> (u8, u8) {
+    match capability {
+        Capability::GroupNonUniform
+        | Capability::GroupNonUniformVote
+        | Capability::GroupNonUniformArithmetic
+        | Capability::GroupNonUniformBallot
+        | Capability::GroupNonUniformShuffle
+        | Capability::GroupNonUniformShuffleRelative
+        | Capability::GroupNonUniformClustered
+        | Capability::GroupNonUniformQuad => (1, 3),
+        _ => (1, 0),
+    }
+}
+
+/// An error of `check`, which is reported by `declare`
+#[derive(Debug)]
+struct Unsupported {
+    message: String,
+    help: &'static str,
+}
+
+fn check(requirements: &Requirements, target_env: TargetEnv) -> Vec<Unsupported> {
+    let mut errors = Vec::new();
+    let (major, minor) = target_env.spirv_version();
+    for &capability in &requirements.capabilities {
+        let (min_major, min_minor) = min_version(capability);
+        if (major, minor) < (min_major, min_minor) {
+            errors.push(Unsupported {
+                message: format!(
+                    "`{:?}` requires SPIR-V {}.{}, but `{}` only supports SPIR-V {}.{}",
+                    capability, min_major, min_minor, target_env, major, minor
+                ),
+                help: "select a newer environment with `--target-env`",
+            });
+        }
+    }
+    // `SPV_EXT_descriptor_indexing` only exists for Vulkan
+    if target_env == TargetEnv::OpenGl4_5 {
+        let descriptor_indexing: Vec<_> = requirements
+            .capabilities
+            .iter()
+            .cloned()
+            .filter(|&capability| is_descriptor_indexing(capability))
+            .collect();
+        if !descriptor_indexing.is_empty() {
+            errors.push(Unsupported {
+                message: format!(
+                    "Descriptor indexing ({:?}) is not supported by `{}`",
+                    descriptor_indexing, target_env
+                ),
+                help: "use a fixed size array and only index it with constants",
+            });
+        }
+    }
+    errors
+}
+
+/// Replaces the `OpCapability` and `OpExtension` instructions of `module`
+/// with the ones that its instructions need.
+pub fn declare(sess: &Session, module: &mut Module, target_env: TargetEnv) {
+    let mut requirements = infer(module, target_env);
+    for error in check(&requirements, target_env) {
+        sess.struct_err(&error.message).help(error.help).emit();
+    }
+    // `Shader` stays in front, the rest is sorted to get a stable output
+    requirements.capabilities[1..].sort_by_key(|&capability| capability as u32);
+    requirements.extensions.sort();
+    module.capabilities = requirements
+        .capabilities
+        .into_iter()
+        .map(|capability| {
+            Instruction::new(
+                Op::Capability,
+                None,
+                None,
+                vec![Operand::Capability(capability)],
+            )
+        }).collect();
+    module.extensions = requirements
+        .extensions
+        .into_iter()
+        .map(|extension| {
+            Instruction::new(
+                Op::Extension,
+                None,
+                None,
+                vec![Operand::LiteralString(extension.to_string())],
+            )
+        }).collect();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rspirv::mr::Builder;
+
+    fn builder() -> Builder {
+        let mut b = Builder::new();
+        b.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
+        b
+    }
+
+    /// Adds an entry point for `stage` that runs `body`
+    fn entry_point<F>(b: &mut Builder, stage: ExecutionModel, interface: &[u32], body: F)
+    where
+        F: FnOnce(&mut Builder),
+    {
+        let void = b.type_void();
+        let fn_ty = b.type_function(void, &[]);
+        let main = b
+            .begin_function(void, None, spirv::FunctionControl::empty(), fn_ty)
+            .unwrap();
+        b.begin_basic_block(None).unwrap();
+        body(b);
+        b.ret().unwrap();
+        b.end_function().unwrap();
+        b.entry_point(stage, main, "main", interface);
+    }
+
+    fn has_capability(requirements: &Requirements, capability: Capability) -> bool {
+        requirements.capabilities.contains(&capability)
+    }
+
+    #[test]
+    fn int64_float64() {
+        let mut b = builder();
+        b.type_int(64, 1);
+        b.type_float(64);
+        entry_point(&mut b, ExecutionModel::GLCompute, &[], |_| ());
+        let requirements = infer(&b.module(), TargetEnv::Vulkan1_0);
+        assert!(has_capability(&requirements, Capability::Int64));
+        assert!(has_capability(&requirements, Capability::Float64));
+        assert!(!has_capability(&requirements, Capability::Int16));
+    }
+
+    #[test]
+    fn storage_buffer_extension() {
+        let mut b = builder();
+        let float = b.type_float(32);
+        let block = b.type_struct(&[float]);
+        b.decorate(block, Decoration::Block, &[]);
+        let ptr = b.type_pointer(None, StorageClass::StorageBuffer, block);
+        let var = b.variable(ptr, None, StorageClass::StorageBuffer, None);
+        entry_point(&mut b, ExecutionModel::GLCompute, &[var], |_| ());
+        let module = b.module();
+        assert_eq!(
+            infer(&module, TargetEnv::Spv1_0).extensions,
+            vec!["SPV_KHR_storage_buffer_storage_class"]
+        );
+        assert!(infer(&module, TargetEnv::Spv1_3).extensions.is_empty());
+    }
+
+    #[test]
+    fn non_uniform_access_chain() {
+        let mut b = builder();
+        let uint = b.type_int(32, 0);
+        let float = b.type_float(32);
+        let block = b.type_struct(&[float]);
+        b.decorate(block, Decoration::Block, &[]);
+        let len = b.constant_u32(uint, 4);
+        let array = b.type_array(block, len);
+        let array_ptr = b.type_pointer(None, StorageClass::Uniform, array);
+        let block_ptr = b.type_pointer(None, StorageClass::Uniform, block);
+        let var = b.variable(array_ptr, None, StorageClass::Uniform, None);
+        let index = b.constant_u32(uint, 1);
+        entry_point(&mut b, ExecutionModel::Fragment, &[], |b| {
+            let elem = b.access_chain(block_ptr, None, var, &[index]).unwrap();
+            b.decorate(elem, Decoration::NonUniformEXT, &[]);
+        });
+        let module = b.module();
+        let requirements = infer(&module, TargetEnv::Vulkan1_1);
+        assert!(has_capability(&requirements, Capability::ShaderNonUniformEXT));
+        assert!(has_capability(
+            &requirements,
+            Capability::UniformBufferArrayNonUniformIndexingEXT
+        ));
+        assert_eq!(requirements.extensions, vec!["SPV_EXT_descriptor_indexing"]);
+        assert!(check(&requirements, TargetEnv::Vulkan1_1).is_empty());
+        assert_eq!(check(&requirements, TargetEnv::OpenGl4_5).len(), 1);
+    }
+
+    #[test]
+    fn group_op_needs_spirv_1_3() {
+        let mut b = builder();
+        let uint = b.type_int(32, 0);
+        let bool_ty = b.type_bool();
+        let subgroup = b.constant_u32(uint, spirv::Scope::Subgroup as u32);
+        entry_point(&mut b, ExecutionModel::GLCompute, &[], |b| {
+            b.group_non_uniform_elect(bool_ty, None, subgroup).unwrap();
+        });
+        let requirements = infer(&b.module(), TargetEnv::Vulkan1_0);
+        assert!(has_capability(&requirements, Capability::GroupNonUniform));
+        let errors = check(&requirements, TargetEnv::Vulkan1_0);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].message.contains("`GroupNonUniform` requires SPIR-V 1.3"));
+        assert!(check(&requirements, TargetEnv::Vulkan1_1).is_empty());
+    }
+
+    /// A module that writes `Layer` from `stage`, either as a variable or as
+    /// a member of a block like `gl_PerVertex`
+    fn layer(stage: ExecutionModel, member: bool) -> Requirements {
+        let mut b = builder();
+        let uint = b.type_int(32, 0);
+        let var = if member {
+            let block = b.type_struct(&[uint]);
+            b.member_decorate(
+                block,
+                0,
+                Decoration::BuiltIn,
+                &[Operand::BuiltIn(BuiltIn::Layer)],
+            );
+            let ptr = b.type_pointer(None, StorageClass::Output, block);
+            b.variable(ptr, None, StorageClass::Output, None)
+        } else {
+            let ptr = b.type_pointer(None, StorageClass::Output, uint);
+            let var = b.variable(ptr, None, StorageClass::Output, None);
+            b.decorate(var, Decoration::BuiltIn, &[Operand::BuiltIn(BuiltIn::Layer)]);
+            var
+        };
+        entry_point(&mut b, stage, &[var], |_| ());
+        infer(&b.module(), TargetEnv::Vulkan1_0)
+    }
+
+    #[test]
+    fn layer_capability() {
+        for &member in &[false, true] {
+            let vertex = layer(ExecutionModel::Vertex, member);
+            assert!(has_capability(&vertex, Capability::ShaderViewportIndexLayerEXT));
+            assert!(!has_capability(&vertex, Capability::Geometry));
+            assert_eq!(vertex.extensions, vec!["SPV_EXT_shader_viewport_index_layer"]);
+            let geometry = layer(ExecutionModel::Geometry, member);
+            assert!(has_capability(&geometry, Capability::Geometry));
+            assert!(geometry.extensions.is_empty());
+        }
+    }
+}
@@ -43,7 +43,8 @@ impl<'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for CollectCrateItems<'a, 'tcx>
             );
             let (def_id, substs) = match callee_ty.sty {
                 ty::TypeVariants::TyFnDef(def_id, substs) => (def_id, substs),
-                _ => panic!("Not a function"),
+                // Function pointers are reported in `validate`
+                _ => return,
             };
             let instance =
                 Instance::resolve(self.tcx, ty::ParamEnv::reveal_all(), def_id, substs).unwrap();
@@ -15,10 +15,10 @@ use rustc::ty::{subst, TyCtxt};
 use rustc_data_structures::control_flow_graph::{iterate::post_order_from_to, ControlFlowGraph};
 use rustc_data_structures::indexed_vec::IndexVec;
 use spirv;
-use std::collections::{BTreeMap, HashMap, HashSet};
-use std::path::Path;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::{self, Write};
 use syntax;
-use syntax_pos::DUMMY_SP;
+use syntax_pos::{Span, DUMMY_SP};
 use ConstructTy;
 use {Enum, Variable};
 use {Function, FunctionCall, Intrinsic, IntrinsicType, Ty, Value};
@@ -45,13 +45,49 @@ pub struct CodegenCx<'a, 'tcx: 'a> {
     pub ty_ptr_cache: HashMap<(ty::Ty<'tcx>, spirv::StorageClass), Ty<'tcx>>,
     pub const_cache: HashMap<ty::Const<'tcx>, Value>,
     pub forward_fns: HashMap<(hir::def_id::DefId, &'a Substs<'tcx>), Function>,
+    /// The Rust path of every entry point function. Entry points are not in
+    /// `forward_fns`, because a Rust function gets a SPIR-V function per stage.
+    pub entry_fn_paths: HashMap<spirv::Word, String>,
     pub intrinsic_fns: HashMap<hir::def_id::DefId, Intrinsic>,
     pub debug_symbols: bool,
     pub glsl_ext_id: spirv::Word,
     pub bool_ty: spirv::Word,
+    /// Types that have already been decorated with an explicit layout
+    pub layout_decorated: HashMap<spirv::Word, ::LayoutRules>,
+    /// The type of a Rust type inside of a block with the given rules, see
+    /// `to_layout_ty`
+    pub layout_ty_cache: HashMap<(ty::Ty<'tcx>, ::LayoutRules), Ty<'tcx>>,
+    pub layout_ptr_cache: HashMap<(ty::Ty<'tcx>, spirv::StorageClass, ::LayoutRules), Ty<'tcx>>,
+    /// Pointers into a `Uniform` or `Buffer` block, with the rules of the
+    /// block
+    pub layout_ptrs: HashMap<spirv::Word, ::LayoutRules>,
+    /// Vulkan device extensions that are required by the module
+    pub device_extensions: BTreeSet<&'static str>,
+    pub target_env: ::TargetEnv,
+    /// The span of the statement or terminator that is currently translated,
+    /// used for diagnostics
+    pub span: Span,
 }
 
 impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
+    /// Reports a construct that can not be translated to SPIR-V at the
+    /// current span.
+    pub fn unsupported(&self, feature: &str, help: &str) {
+        struct_span_err!(
+            self.tcx.sess,
+            self.span,
+            E1337,
+            "{} is not supported by rlsl",
+            feature
+        ).help(help)
+        .emit();
+    }
+    /// A placeholder for a value that could not be translated, so that we can
+    /// continue and report more errors. The module is never written.
+    pub fn undef(&mut self, ty: ty::Ty<'tcx>) -> Value {
+        let spirv_ty = self.to_ty_fn(ty);
+        Value::new(self.builder.undef(spirv_ty.word, None))
+    }
     pub fn bool_to_u32(&mut self, bool_load: spirv::Word) -> Value {
         let zero = self.constant_u32(0);
         let one = self.constant_u32(1);
@@ -125,7 +161,13 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
                     .constant_f32(spirv_ty.word, f32::from_bits(value as u32))
             }
             //[FIXME] Add other constants
-            ref rest => unimplemented!("Const"), // ConstValue::Integer(const_int) => {
+            _ => {
+                self.unsupported(
+                    &format!("A constant of type `{}`", const_ty),
+                    "Use a `u32`, `f32` or `bool` constant",
+                );
+                return self.undef(const_ty);
+            } // ConstValue::Integer(const_int) => {
                                                  //     use rustc::ty::util::IntTypeExt;
                                                  //     let ty = const_int.int_type().to_ty(self.tcx);
                                                  //     let spirv_ty = self.to_ty(ty, spirv::StorageClass::Function);
@@ -182,6 +224,14 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
             return *ty;
         }
         let spirv_type: Ty = match ty.sty {
+            // The data of the buffer is the block, see `unwrapped_block`
+            TypeVariants::TyAdt(..) if ::unwrapped_block(self.tcx, ty).is_some() => {
+                let data_ty = ::unwrapped_block(self.tcx, ty).expect("data");
+                let spirv_ty = self.to_ty(data_ty, storage_class);
+                let decoration = self.buffer_block_decoration();
+                self.builder.decorate(spirv_ty.word, decoration, &[]);
+                spirv_ty.word.construct_ty(ty)
+            }
             // TODO: Proper TyNever
             TypeVariants::TyNever => {
                 let ty = self.tcx.mk_nil();
@@ -194,7 +244,10 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
                 use syntax::ast::FloatTy;
                 match f_ty {
                     FloatTy::F32 => self.builder.type_float(32).construct_ty(ty),
-                    FloatTy::F64 => panic!("f64 is not supported"),
+                    FloatTy::F64 => {
+                        self.unsupported("`f64`", "Use `f32` instead");
+                        self.builder.type_float(32).construct_ty(ty)
+                    }
                 }
             }
             TypeVariants::TyTuple(slice) if slice.len() == 0 => {
@@ -316,42 +369,31 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
                                 .map(|ty| self.to_ty(ty, storage_class).word)
                                 .collect();
                             let spirv_struct = self.builder.type_struct(&field_ty_spirv);
-                            if let Some(layout) = ::std430_layout(self.tcx, ty) {
-                                layout
-                                    .offsets()
-                                    .iter()
-                                    .enumerate()
-                                    .for_each(|(idx, &offset)| {
-                                        self.builder.member_decorate(
-                                            spirv_struct,
-                                            idx as u32,
-                                            spirv::Decoration::Offset,
-                                            &[rspirv::mr::Operand::LiteralInt32(offset as u32)],
-                                        );
-                                    });
-                            }
                             // TODO: Proper input
-                            if let Some(uniform) = ::Uniform::new(self.tcx, ty) {
+                            if ::get_builtin_adt(self.tcx, ty, "Uniform").is_some() {
                                 self.builder
                                     .decorate(spirv_struct, spirv::Decoration::Block, &[]);
                             }
-                            if needs_block {
+                            if ::buffer_fields(self.tcx, ty).is_some() {
+                                let decoration = self.buffer_block_decoration();
+                                self.builder.decorate(spirv_struct, decoration, &[]);
+                            }
+                            let builtin = ::builtin_output(self.tcx, ty);
+                            if let Some(builtin) = builtin {
                                 self.builder
                                     .decorate(spirv_struct, spirv::Decoration::Block, &[]);
-                                let fields: Vec<_> = adt.all_fields().take(2).collect();
-                                let location_ty = fields[1].ty(self.tcx, mono_substs);
-                                let location_index = ::extract_location(self.tcx, location_ty)
-                                    .expect("location index");
-
-                                //println!("atrs {:?}", self.tcx.get_attrs(location_id));
-                                //let variable_ty = fields[0].ty(self.tcx, mono_substs);
                                 self.builder.member_decorate(
                                     spirv_struct,
                                     0,
-                                    spirv::Decoration::Location,
-                                    &[rspirv::mr::Operand::LiteralInt32(location_index as u32)],
+                                    spirv::Decoration::BuiltIn,
+                                    &[rspirv::mr::Operand::BuiltIn(builtin)],
                                 );
                             }
+                            // The location is decorated on the variable
+                            if needs_block {
+                                self.builder
+                                    .decorate(spirv_struct, spirv::Decoration::Block, &[]);
+                            }
 
                             if self.debug_symbols {
                                 let fields: Vec<_> = adt
@@ -372,7 +414,11 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
                             spirv_struct.construct_ty(ty)
                         })
                     }
-                    ref r => unimplemented!("{:?}", r),
+                    ty::AdtKind::Union => {
+                        self.unsupported("A union", "Use a struct or an enum instead");
+                        let placeholder = self.tcx.types.u32;
+                        self.to_ty(placeholder, storage_class).word.construct_ty(ty)
+                    }
                 }
             }
             TypeVariants::TyArray(ty, length) => {
@@ -391,7 +437,14 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
                 let spirv_struct = self.builder.type_struct(&field_ty_spirv);
                 spirv_struct.construct_ty(ty)
             }
-            ref r => unimplemented!("{:?}", r),
+            _ => {
+                self.unsupported(
+                    &format!("The type `{}`", ty),
+                    "Use structs, enums, arrays, `u32`, `i32`, `f32` or `bool`",
+                );
+                let placeholder = self.tcx.types.u32;
+                self.to_ty(placeholder, storage_class).word.construct_ty(ty)
+            }
         };
         if is_ptr {
             self.ty_ptr_cache.insert((ty, storage_class), spirv_type);
@@ -423,6 +476,338 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
         item.map(|item| &*item.attrs)
     }
 
+    /// The type of `ty` inside of a block that is laid out with `rules`,
+    /// decorated with `Offset`, `ArrayStride` and `MatrixStride`. Vulkan
+    /// requires an explicit layout for every type that is used inside of a
+    /// `Uniform` or `Buffer` block. A type is decorated with the first rules
+    /// that it is used with. If it is used with other rules later on, like a
+    /// struct in a std140 `Uniform` and a std430 `Buffer`, it gets a copy of
+    /// its own.
+    pub fn to_layout_ty(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        rules: ::LayoutRules,
+    ) -> Ty<'tcx> {
+        if let Some(&layout_ty) = self.layout_ty_cache.get(&(ty, rules)) {
+            return layout_ty;
+        }
+        if rules == ::LayoutRules::Scalar {
+            self.require_device_extension("VK_EXT_scalar_block_layout");
+        }
+        let spirv_ty = self.to_ty(ty, storage_class);
+        // The buffer and its data share the same type
+        if let Some(data_ty) = ::unwrapped_block(self.tcx, ty) {
+            let layout_ty = self.to_layout_ty(data_ty, storage_class, rules);
+            if layout_ty.word != spirv_ty.word {
+                let decoration = self.buffer_block_decoration();
+                self.builder.decorate(layout_ty.word, decoration, &[]);
+            }
+            self.layout_ty_cache.insert((ty, rules), layout_ty);
+            return layout_ty;
+        }
+        let members = self.layout_members(ty);
+        let member_tys: Vec<_> = members
+            .iter()
+            .map(|&member| self.to_layout_ty(member, storage_class, rules).word)
+            .collect();
+        let shares_members = members
+            .iter()
+            .zip(&member_tys)
+            .all(|(&member, &word)| self.to_ty(member, storage_class).word == word);
+        let unused_or_same_rules = self
+            .layout_decorated
+            .get(&spirv_ty.word)
+            .map_or(true, |&previous_rules| previous_rules == rules);
+        let layout_ty = if members.is_empty() {
+            spirv_ty
+        } else if shares_members && unused_or_same_rules {
+            if !self.layout_decorated.contains_key(&spirv_ty.word) {
+                self.layout_decorated.insert(spirv_ty.word, rules);
+                self.decorate_explicit_layout(ty, spirv_ty.word, rules);
+            }
+            spirv_ty
+        } else {
+            let word = self.layout_copy(ty, storage_class, &member_tys);
+            self.decorate_explicit_layout(ty, word, rules);
+            word.construct_ty(ty)
+        };
+        self.layout_ty_cache.insert((ty, rules), layout_ty);
+        layout_ty
+    }
+
+    pub fn to_layout_ty_as_ptr(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        rules: ::LayoutRules,
+    ) -> Ty<'tcx> {
+        let layout_ty = self.to_layout_ty(ty, storage_class, rules);
+        if layout_ty.word == self.to_ty(ty, storage_class).word {
+            return self.to_ty_as_ptr(ty, storage_class);
+        }
+        if let Some(&ptr_ty) = self.layout_ptr_cache.get(&(ty, storage_class, rules)) {
+            return ptr_ty;
+        }
+        let ptr_ty = self
+            .builder
+            .type_pointer(None, storage_class, layout_ty.word)
+            .construct_ty(ty);
+        self.layout_ptr_cache
+            .insert((ty, storage_class, rules), ptr_ty);
+        ptr_ty
+    }
+
+    /// The type of `ty` inside of a block with `rules`, or the type that is
+    /// used everywhere else.
+    pub fn to_ty_with_layout(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        rules: Option<::LayoutRules>,
+    ) -> Ty<'tcx> {
+        match rules {
+            Some(rules) => self.to_layout_ty(ty, storage_class, rules),
+            None => self.to_ty(ty, storage_class),
+        }
+    }
+
+    pub fn to_ty_as_ptr_with_layout(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        rules: Option<::LayoutRules>,
+    ) -> Ty<'tcx> {
+        match rules {
+            Some(rules) => self.to_layout_ty_as_ptr(ty, storage_class, rules),
+            None => self.to_ty_as_ptr(ty, storage_class),
+        }
+    }
+
+    /// Converts `value` between two types of `ty` with a different layout,
+    /// member by member. Used to load from and to store into a block whose
+    /// types are a copy, see `to_layout_ty`.
+    pub fn convert_layout(
+        &mut self,
+        value: spirv::Word,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        from: Option<::LayoutRules>,
+        to: Option<::LayoutRules>,
+    ) -> spirv::Word {
+        use rustc::ty::TypeVariants;
+        let from_ty = self.to_ty_with_layout(ty, storage_class, from);
+        let to_ty = self.to_ty_with_layout(ty, storage_class, to);
+        if from_ty.word == to_ty.word {
+            return value;
+        }
+        let members: Vec<_> = match ty.sty {
+            TypeVariants::TyArray(elem_ty, len) => {
+                (0..len.unwrap_usize(self.tcx)).map(|_| elem_ty).collect()
+            }
+            _ => self.layout_members(ty),
+        };
+        let converted: Vec<_> = members
+            .iter()
+            .enumerate()
+            .map(|(idx, &member)| {
+                let member_ty = self.to_ty_with_layout(member, storage_class, from);
+                let member_value = self
+                    .builder
+                    .composite_extract(member_ty.word, None, value, &[idx as u32])
+                    .expect("composite extract");
+                self.convert_layout(member_value, member, storage_class, from, to)
+            }).collect();
+        self.builder
+            .composite_construct(to_ty.word, None, &converted)
+            .expect("composite construct")
+    }
+
+    /// The types inside of `ty` that have a layout of their own
+    fn layout_members(&self, ty: ty::Ty<'tcx>) -> Vec<ty::Ty<'tcx>> {
+        use rustc::ty::TypeVariants;
+        match IntrinsicType::from_ty(self.tcx, ty) {
+            Some(IntrinsicType::DescriptorArray(array)) => return vec![array.elem],
+            Some(IntrinsicType::RuntimeArray(rt_array)) => return vec![rt_array.ty],
+            Some(_) => return Vec::new(),
+            None => (),
+        }
+        match ty.sty {
+            TypeVariants::TyArray(elem_ty, _) => vec![elem_ty],
+            TypeVariants::TyAdt(adt, substs) if adt.is_struct() => adt
+                .all_fields()
+                .map(|field| field.ty(self.tcx, substs))
+                .filter(|ty| !ty.is_phantom_data())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Creates a copy of the type of `ty` with different member types, see
+    /// `to_layout_ty`
+    fn layout_copy(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        storage_class: spirv::StorageClass,
+        member_tys: &[spirv::Word],
+    ) -> spirv::Word {
+        use rustc::ty::TypeVariants;
+        match IntrinsicType::from_ty(self.tcx, ty) {
+            Some(IntrinsicType::DescriptorArray(array)) => {
+                // Every element is a block of its own
+                let block = self.builder.type_struct(member_tys);
+                let decoration = if array.buffer {
+                    self.buffer_block_decoration()
+                } else {
+                    spirv::Decoration::Block
+                };
+                self.builder.decorate(block, decoration, &[]);
+                self.builder.member_decorate(
+                    block,
+                    0,
+                    spirv::Decoration::Offset,
+                    &[rspirv::mr::Operand::LiteralInt32(0)],
+                );
+                return match array.len {
+                    Some(len) => {
+                        let len = self.constant_u32(len as u32).word;
+                        self.builder.type_array(block, len)
+                    }
+                    None => self.builder.type_runtime_array(block),
+                };
+            }
+            Some(IntrinsicType::RuntimeArray(_)) => {
+                return self.builder.type_runtime_array(member_tys[0]);
+            }
+            _ => (),
+        }
+        match ty.sty {
+            TypeVariants::TyArray(_, len) => {
+                let len = self.constant(len).word;
+                self.builder.type_array(member_tys[0], len)
+            }
+            TypeVariants::TyAdt(adt, substs) => {
+                let spirv_struct = self.builder.type_struct(member_tys);
+                if self.debug_symbols {
+                    let fields: Vec<_> = adt
+                        .all_fields()
+                        .filter(|field| !field.ty(self.tcx, substs).is_phantom_data())
+                        .collect();
+                    for (index, field) in fields.iter().enumerate() {
+                        self.builder.member_name(
+                            spirv_struct,
+                            index as u32,
+                            field.ident.as_str().to_string(),
+                        );
+                    }
+                }
+                if ::get_builtin_adt(self.tcx, ty, "Uniform").is_some() {
+                    self.builder
+                        .decorate(spirv_struct, spirv::Decoration::Block, &[]);
+                }
+                if ::buffer_fields(self.tcx, ty).is_some() {
+                    let decoration = self.buffer_block_decoration();
+                    self.builder.decorate(spirv_struct, decoration, &[]);
+                }
+                self.name_from_def_id(adt.did, spirv_struct);
+                spirv_struct
+            }
+            _ => unreachable!("`{}` has no members", ty),
+        }
+    }
+
+    /// Decorates the type `spirv_ty` of `ty` with `Offset`, `ArrayStride` and
+    /// `MatrixStride`, the types inside of it are decorated by `to_layout_ty`.
+    fn decorate_explicit_layout(
+        &mut self,
+        ty: ty::Ty<'tcx>,
+        spirv_ty: spirv::Word,
+        rules: ::LayoutRules,
+    ) {
+        use rspirv::mr::Operand::LiteralInt32;
+        use rustc::ty::TypeVariants;
+        match IntrinsicType::from_ty(self.tcx, ty) {
+            // Arrays of descriptors are not laid out in memory, only the
+            // blocks inside of them.
+            Some(IntrinsicType::DescriptorArray(_)) => return,
+            Some(IntrinsicType::RuntimeArray(rt_array)) => {
+                let stride = ::layout_ty(self.tcx, rt_array.ty)
+                    .map(|elem| ::LayoutTy::RuntimeArray(Box::new(elem)))
+                    .and_then(|array| ::Layout::new(&array, rules).array_stride())
+                    .expect("layout");
+                self.builder.decorate(
+                    spirv_ty,
+                    spirv::Decoration::ArrayStride,
+                    &[LiteralInt32(stride as u32)],
+                );
+                return;
+            }
+            _ => (),
+        }
+        match ty.sty {
+            TypeVariants::TyArray(..) => {
+                let stride = ::ty_layout(self.tcx, ty, rules)
+                    .and_then(|layout| layout.array_stride())
+                    .expect("layout");
+                self.builder.decorate(
+                    spirv_ty,
+                    spirv::Decoration::ArrayStride,
+                    &[LiteralInt32(stride as u32)],
+                );
+            }
+            TypeVariants::TyAdt(adt, substs)
+                if adt.is_struct() && IntrinsicType::from_ty(self.tcx, ty).is_none() =>
+            {
+                let layout = ::ty_layout(self.tcx, ty, rules).expect("layout");
+                let field_tys: Vec<_> = adt
+                    .all_fields()
+                    .map(|field| field.ty(self.tcx, substs))
+                    .filter(|ty| !ty.is_phantom_data())
+                    .collect();
+                for (idx, (&field_ty, offset)) in
+                    field_tys.iter().zip(layout.offsets()).enumerate()
+                {
+                    self.builder.member_decorate(
+                        spirv_ty,
+                        idx as u32,
+                        spirv::Decoration::Offset,
+                        &[LiteralInt32(offset as u32)],
+                    );
+                    if let Some(stride) = ::matrix_stride(self.tcx, field_ty, rules) {
+                        self.builder.member_decorate(
+                            spirv_ty,
+                            idx as u32,
+                            spirv::Decoration::ColMajor,
+                            &[],
+                        );
+                        self.builder.member_decorate(
+                            spirv_ty,
+                            idx as u32,
+                            spirv::Decoration::MatrixStride,
+                            &[LiteralInt32(stride as u32)],
+                        );
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Records a Vulkan device extension that has to be enabled to use the
+    /// module. The extension is also noted with `OpSourceExtension`.
+    pub fn require_device_extension(&mut self, name: &'static str) {
+        if self.device_extensions.insert(name) {
+            self.builder.source_extension(name);
+        }
+    }
+
+    /// Arrays of descriptors that are indexed at runtime, or that are
+    /// runtime-sized, need `VK_EXT_descriptor_indexing`. The capabilities are
+    /// declared by `capabilities::declare`.
+    pub fn require_descriptor_indexing(&mut self) {
+        self.require_device_extension("VK_EXT_descriptor_indexing");
+    }
+
     pub fn name_from_def_id(&mut self, def_id: hir::def_id::DefId, id: spirv::Word) {
         if self.debug_symbols {
             //self.builder.name(id, self.tcx.item_name(def_id).as_ref());
@@ -434,10 +819,47 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
             self.builder.name(id, name);
         }
     }
+    /// The Rust path of every translated function, keyed by the id of the
+    /// SPIR-V function. Generic functions include their substs.
+    pub fn fn_paths(&self) -> HashMap<spirv::Word, String> {
+        self.forward_fns
+            .iter()
+            .map(|(&(def_id, substs), function)| {
+                (function.0, ty::Instance::new(def_id, substs).to_string())
+            }).chain(
+                self.entry_fn_paths
+                    .iter()
+                    .map(|(&id, path)| (id, path.clone())),
+            ).collect()
+    }
+    /// The storage class of buffers, depends on the target environment
+    pub fn buffer_storage_class(&self) -> spirv::StorageClass {
+        if self.target_env.has_storage_buffer() {
+            spirv::StorageClass::StorageBuffer
+        } else {
+            spirv::StorageClass::Uniform
+        }
+    }
+    /// Buffers in the `Uniform` storage class are marked with `BufferBlock`
+    pub fn buffer_block_decoration(&self) -> spirv::Decoration {
+        if self.target_env.has_storage_buffer() {
+            spirv::Decoration::Block
+        } else {
+            spirv::Decoration::BufferBlock
+        }
+    }
+    /// Records the device extension for the `StorageBuffer` storage class if
+    /// the target environment needs it.
+    pub fn require_buffer_storage_class(&mut self) {
+        if self.target_env.needs_storage_buffer_extension() {
+            self.require_device_extension("VK_KHR_storage_buffer_storage_class");
+        }
+    }
     pub fn build_module(self) -> rspirv::mr::Module {
         let mut module = self.builder.module();
         if let Some(header) = module.header.as_mut() {
-            header.set_version(1, 0);
+            let (major, minor) = self.target_env.spirv_version();
+            header.set_version(major, minor);
         }
         module
     }
@@ -535,14 +957,17 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
             var
         })
     }
-    pub fn new(tcx: ty::TyCtxt<'a, 'tcx, 'tcx>) -> CodegenCx<'a, 'tcx> {
+    pub fn new(
+        tcx: ty::TyCtxt<'a, 'tcx, 'tcx>,
+        target_env: ::TargetEnv,
+    ) -> CodegenCx<'a, 'tcx> {
         let mut builder = Builder::new();
-        builder.capability(spirv::Capability::Shader);
         let glsl_ext_id = builder.ext_inst_import("GLSL.std.450");
         builder.memory_model(spirv::AddressingModel::Logical, spirv::MemoryModel::GLSL450);
         CodegenCx {
             bool_ty: builder.type_bool(),
             debug_symbols: true,
+            target_env,
             builder,
             compute: None,
             per_vertex: None,
@@ -551,7 +976,14 @@ impl<'a, 'tcx> CodegenCx<'a, 'tcx> {
             ty_ptr_cache: HashMap::new(),
             const_cache: HashMap::new(),
             forward_fns: HashMap::new(),
+            entry_fn_paths: HashMap::new(),
             intrinsic_fns: HashMap::new(),
+            layout_decorated: HashMap::new(),
+            layout_ty_cache: HashMap::new(),
+            layout_ptr_cache: HashMap::new(),
+            layout_ptrs: HashMap::new(),
+            device_extensions: BTreeSet::new(),
+            span: DUMMY_SP,
             tcx,
             glsl_ext_id,
         }
@@ -740,6 +1172,7 @@ pub fn find_merge(
 /// that are between the branch header and the merge block are dominated by the
 /// header. This can be fixed by duplicated those shared blocks.
 pub fn fix_overlapping_control_flow<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
     mut mir: mir::Mir<'tcx>,
     merge_blocks: impl Iterator<Item = &'a Cfg>,
 ) -> mir::Mir<'tcx> {
@@ -772,7 +1205,7 @@ pub fn fix_overlapping_control_flow<'a, 'tcx>(
                 overlapping_block
             );
             for previous_block in previous_blocks {
-                insert_block_inbetween(&mut mir, previous_block, overlapping_block, new_block);
+                insert_block_inbetween(tcx, &mut mir, previous_block, overlapping_block, new_block);
             }
         }
     }
@@ -792,12 +1225,15 @@ pub struct SpirvMir<'a, 'tcx: 'a> {
     pub control_flow: BTreeMap<mir::BasicBlock, Cfg>,
     pub tcx: TyCtxt<'a, 'tcx, 'tcx>,
 }
-fn insert_block_inbetween<'tcx>(
+/// Redirects the edge from `previous_block` to `old_block` to `target_block`
+fn insert_block_inbetween<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
     mir: &mut mir::Mir<'tcx>,
     previous_block: mir::BasicBlock,
     old_block: mir::BasicBlock,
     target_block: mir::BasicBlock,
 ) {
+    let span = mir.basic_blocks()[previous_block].terminator().source_info.span;
     let kind = &mut mir.basic_blocks_mut()[previous_block].terminator_mut().kind;
     match kind {
         mir::TerminatorKind::Call {
@@ -817,7 +1253,16 @@ fn insert_block_inbetween<'tcx>(
                 .expect("Should contain merge block");
             *bb = target_block;
         }
-        _ => unimplemented!("Fix prev merge block"),
+        _ => {
+            struct_span_err!(
+                tcx.sess,
+                span,
+                E1337,
+                "{} is not supported by rlsl",
+                "Leaving a branch or a loop from here"
+            ).help("move the code after the branch or the loop")
+            .emit();
+        }
     }
 }
 impl<'a, 'tcx> SpirvMir<'a, 'tcx> {
@@ -832,7 +1277,7 @@ impl<'a, 'tcx> SpirvMir<'a, 'tcx> {
         let cfgs = compute_control_flow(&PetMir::from_mir(&spirv_mir));
 
         //println!("{:#?}", cfgs);
-        let mut spirv_mir = fix_overlapping_control_flow(spirv_mir, cfgs.values());
+        let mut spirv_mir = fix_overlapping_control_flow(mcx.tcx, spirv_mir, cfgs.values());
         let mut cfgs = compute_control_flow(&PetMir::from_mir(&spirv_mir));
         //println!("{:?}", cfgs.values().collect::<Vec<_>>());
         let order = post_order_from_to(&spirv_mir, mir::START_BLOCK, None);
@@ -869,7 +1314,13 @@ impl<'a, 'tcx> SpirvMir<'a, 'tcx> {
             let goto_data = mir::BasicBlockData::new(Some(terminator));
             let goto_block = spirv_mir.basic_blocks_mut().push(goto_data);
             for previous_block in previous_blocks {
-                insert_block_inbetween(&mut spirv_mir, previous_block, cfg.merge_block, goto_block);
+                insert_block_inbetween(
+                    mcx.tcx,
+                    &mut spirv_mir,
+                    previous_block,
+                    cfg.merge_block,
+                    goto_block,
+                );
             }
             cfg.merge_block = goto_block;
         });
@@ -922,16 +1373,93 @@ impl<'a, 'tcx> MirContext<'a, 'tcx> {
     }
 }
 
-pub fn save_module<P: AsRef<Path>>(module: &rspirv::mr::Module, file_name: P) {
+pub fn write_module<W: Write>(module: &rspirv::mr::Module, w: &mut W) -> io::Result<()> {
     use rspirv::binary::Assemble;
-    use std::fs::File;
-    use std::io::Write;
     use std::mem::size_of;
     let bytes: Vec<u8> = module
         .assemble()
         .iter()
         .flat_map(|val| (0..size_of::<u32>()).map(move |i| ((val >> (8 * i)) & 0xff) as u8))
         .collect();
-    let mut file = File::create(file_name.as_ref()).expect("file");
-    file.write(&bytes);
+    w.write_all(&bytes)
+}
+
+/// Writes the module as SPIR-V assembly. Every function is preceded by a
+/// comment with its Rust path from `fn_paths`.
+pub fn write_module_asm<W: Write>(
+    module: &rspirv::mr::Module,
+    fn_paths: &HashMap<spirv::Word, String>,
+    w: &mut W,
+) -> io::Result<()> {
+    use rspirv::binary::Disassemble;
+    for line in module.disassemble().lines() {
+        if let Some(path) = function_id(line).and_then(|id| fn_paths.get(&id)) {
+            writeln!(w, "; fn {}", path)?;
+        }
+        writeln!(w, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// The result id of an `OpFunction`, like `%12 = OpFunction %2 None %3`
+fn function_id(line: &str) -> Option<spirv::Word> {
+    let mut words = line.split_whitespace();
+    let id = words.next()?;
+    if words.next() != Some("=") || words.next() != Some("OpFunction") {
+        return None;
+    }
+    id.trim_left_matches('%').parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{function_id, write_module_asm};
+    use rspirv::mr::Builder;
+    use spirv;
+    use std::collections::HashMap;
+
+    #[test]
+    fn function_ids() {
+        assert_eq!(function_id("%12 = OpFunction %2 None %3"), Some(12));
+        assert_eq!(function_id("  %7 = OpFunction %2 None %3"), Some(7));
+        assert_eq!(function_id("%12 = OpFunctionCall %2 %5"), None);
+        assert_eq!(function_id("OpFunctionEnd"), None);
+        assert_eq!(function_id("%main = OpFunction %2 None %3"), None);
+    }
+
+    /// An empty `void` function
+    fn function(builder: &mut Builder) -> spirv::Word {
+        let void = builder.type_void();
+        let fn_ty = builder.type_function(void, vec![]);
+        let id = builder
+            .begin_function(void, None, spirv::FunctionControl::empty(), fn_ty)
+            .expect("begin fn");
+        builder.begin_basic_block(None).expect("block");
+        builder.ret().expect("ret");
+        builder.end_function().expect("end fn");
+        id
+    }
+
+    #[test]
+    fn annotated_asm() {
+        let mut builder = Builder::new();
+        let entry = function(&mut builder);
+        let helper = function(&mut builder);
+        let module = builder.module();
+        let mut fn_paths = HashMap::new();
+        fn_paths.insert(entry, "shader::fragment".to_string());
+        fn_paths.insert(helper, "shader::shade::<f32>".to_string());
+        let mut asm = Vec::new();
+        write_module_asm(&module, &fn_paths, &mut asm).expect("write");
+        let asm = String::from_utf8(asm).expect("utf8");
+        let lines: Vec<_> = asm.lines().collect();
+        for (id, path) in &[(entry, "shader::fragment"), (helper, "shader::shade::<f32>")] {
+            let index = lines
+                .iter()
+                .position(|line| function_id(line) == Some(*id))
+                .expect("OpFunction");
+            assert_eq!(lines[index - 1], format!("; fn {}", path));
+        }
+        assert_eq!(lines.iter().filter(|line| line.starts_with("; fn")).count(), 2);
+    }
 }
@@ -10,7 +10,7 @@ use rustc_data_structures::control_flow_graph::{iterate::post_order_from_to, Con
 use spirv;
 use std::collections::HashMap;
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::Path;
 pub struct PetMir<'a, 'tcx: 'a> {
     pub mir: &'a mir::Mir<'tcx>,
@@ -74,8 +74,8 @@ impl<'a, 'tcx> PetMir<'a, 'tcx> {
         PetMir { mir, graph }
     }
 
-    pub fn export(&self, w: &mut impl Write) {
+    pub fn export(&self, w: &mut impl Write) -> io::Result<()> {
         let dot = petgraph::dot::Dot::with_config(&self.graph, &[]);
-        write!(w, "{:?}", dot);
+        write!(w, "{:?}", dot)
     }
 }
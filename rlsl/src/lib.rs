@@ -10,6 +10,9 @@ extern crate arena;
 extern crate env_logger;
 extern crate getopts;
 extern crate itertools;
+extern crate rlsl_layout;
+extern crate rlsl_reflect;
+extern crate rlsl_validate;
 extern crate rspirv;
 extern crate rustc;
 extern crate rustc_borrowck;
@@ -37,18 +40,24 @@ use rustc::{hir, mir};
 use rustc_data_structures::fx::FxHashSet;
 use rustc_data_structures::indexed_vec::Idx;
 use rustc_target::spec::abi::Abi;
+pub mod capabilities;
 pub mod collector;
 pub mod context;
 pub mod graph;
 pub mod iterate;
+pub mod options;
+pub mod reflect;
 pub mod typ;
+pub mod validate;
 use self::context::{CodegenCx, MirContext, SpirvMir};
+pub use options::{EmitKind, Options, TargetEnv};
+pub use rlsl_layout::{Layout, LayoutRules, LayoutTy};
+use rlsl_reflect::DescriptorCount;
 use self::typ::*;
 use itertools::{Either, Itertools};
 use rustc::ty;
 use rustc::ty::subst::Substs;
 use std::collections::HashMap;
-use std::path::Path;
 #[derive(Copy, Clone, Debug)]
 pub enum IntrinsicFn {
     Dot,
@@ -64,44 +73,161 @@ pub fn remove_ptr_ty<'tcx>(ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
     }
 }
 
-pub struct TyErrorVisitor {
-    has_error: bool,
-}
-impl TyErrorVisitor {
-    pub fn has_error(mcxs: &[MirContext]) -> bool {
-        let mut visitor = TyErrorVisitor { has_error: false };
-        for mcx in mcxs {
-            if visitor.has_error {
-                return true;
+/// Returns the value of a type level constant like `N5`, which is tagged with
+/// `#[spirv(Const5)]`. `FromAttr` has no value, the location or binding has
+/// to be set with an attribute on the entry point.
+pub fn extract_location<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<u32> {
+    if let TypeVariants::TyAdt(_, substs) = ty.sty {
+        let inner_ty = substs.type_at(0);
+        let location_id = inner_ty.ty_to_def_id().expect("id location");
+        let attrs = tcx.get_attrs(location_id);
+        let val = ::extract_attr(&attrs, "spirv", |s| {
+            if s.starts_with("Const") {
+                s["Const".len()..].parse::<u32>().ok()
             } else {
-                visitor.visit_mir(mcx.mir());
+                None
+            }
+        });
+        return val.get(0).map(|&i| i);
+    }
+    None
+}
+
+/// The location, set or binding of an argument of an entry point. They can
+/// be set with `#[spirv(param(uv, location = 5))]` and
+/// `#[spirv(param(tex, set = 1, binding = 7))]` on the entry point and take
+/// precedence over the type level constants like `N0`.
+#[derive(Copy, Clone, Debug, Default, Hash, PartialEq, Eq)]
+pub struct ParamAttrs {
+    pub location: Option<u32>,
+    pub set: Option<u32>,
+    pub binding: Option<u32>,
+}
+
+/// Returns every `param(..)` of a `#[spirv(..)]` attribute together with the
+/// name of the argument it belongs to.
+fn param_metas(attrs: &[syntax::ast::Attribute]) -> Vec<(String, syntax::ast::MetaItem)> {
+    attrs
+        .iter()
+        .filter_map(|attr| attr.meta())
+        .filter(|meta| meta.name() == "spirv")
+        .flat_map(|meta| meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default())
+        .filter_map(|nested| match nested.node {
+            syntax::ast::NestedMetaItemKind::MetaItem(ref meta) if meta.name() == "param" => {
+                let name = meta
+                    .meta_item_list()
+                    .and_then(|list| list.get(0).and_then(|first| first.word()).map(|word| word.name()))
+                    .map(|name| name.to_string())?;
+                Some((name, meta.clone()))
+            }
+            _ => None,
+        }).collect()
+}
+
+/// Returns every `output(..)` of a `#[spirv(..)]` attribute together with the
+/// index of the output it belongs to, like `#[spirv(output(0, location = 9))]`.
+fn output_metas(attrs: &[syntax::ast::Attribute]) -> Vec<(u32, syntax::ast::MetaItem)> {
+    use syntax::ast::{LitKind, NestedMetaItemKind};
+    attrs
+        .iter()
+        .filter_map(|attr| attr.meta())
+        .filter(|meta| meta.name() == "spirv")
+        .flat_map(|meta| meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default())
+        .filter_map(|nested| match nested.node {
+            NestedMetaItemKind::MetaItem(ref meta) if meta.name() == "output" => {
+                let index = meta
+                    .meta_item_list()
+                    .and_then(|list| list.get(0).cloned())
+                    .and_then(|first| match first.node {
+                        NestedMetaItemKind::Literal(ref lit) => match lit.node {
+                            LitKind::Int(value, _) if value <= u32::max_value() as u128 => {
+                                Some(value as u32)
+                            }
+                            _ => None,
+                        },
+                        _ => None,
+                    })?;
+                Some((index, meta.clone()))
+            }
+            _ => None,
+        }).collect()
+}
+
+/// Accepts `location = 5` as well as `location = "5"`
+fn meta_u32(meta: &syntax::ast::MetaItem) -> Option<u32> {
+    use syntax::ast::{LitKind, MetaItemKind};
+    match meta.node {
+        MetaItemKind::NameValue(ref lit) => match lit.node {
+            LitKind::Int(value, _) if value <= u32::max_value() as u128 => Some(value as u32),
+            LitKind::Str(value, _) => value.as_str().parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn param_attrs<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    def_id: hir::def_id::DefId,
+    name: &str,
+) -> ParamAttrs {
+    let mut param_attrs = ParamAttrs::default();
+    for (_, meta) in param_metas(&tcx.get_attrs(def_id))
+        .into_iter()
+        .filter(|&(ref param, _)| param == name)
+    {
+        for nested in meta.meta_item_list().into_iter().flat_map(|list| list.iter().skip(1)) {
+            let item = match nested.meta_item() {
+                Some(item) => item,
+                None => continue,
+            };
+            let value = meta_u32(item);
+            match &*item.name().as_str() {
+                "location" => param_attrs.location = value,
+                "set" => param_attrs.set = value,
+                "binding" => param_attrs.binding = value,
+                _ => (),
             }
         }
-        false
     }
+    param_attrs
 }
-impl<'tcx> rustc::mir::visit::Visitor<'tcx> for TyErrorVisitor {
-    fn visit_ty(&mut self, ty: &ty::Ty<'tcx>, _: TyContext) {
-        self.super_ty(ty);
-        if let TypeVariants::TyError = ty.sty {
-            self.has_error = true;
+
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum Interpolation {
+    Smooth,
+    Flat,
+    NoPerspective,
+    Centroid,
+    Sample,
+}
+
+impl Interpolation {
+    pub fn decorations(self) -> &'static [spirv::Decoration] {
+        match self {
+            Interpolation::Smooth => &[],
+            Interpolation::Flat => &[spirv::Decoration::Flat],
+            Interpolation::NoPerspective => &[spirv::Decoration::NoPerspective],
+            Interpolation::Centroid => &[spirv::Decoration::Centroid],
+            Interpolation::Sample => &[spirv::Decoration::Sample],
         }
     }
 }
 
-pub fn extract_location<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<u32> {
+pub fn extract_interpolation<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<Interpolation> {
     if let TypeVariants::TyAdt(_, substs) = ty.sty {
-        //assert!(substs.len() == 1, "Len should be 1");
         let inner_ty = substs.type_at(0);
-        let location_id = inner_ty.ty_to_def_id().expect("id location");
-        let attrs = tcx.get_attrs(location_id);
+        let interpolation_id = inner_ty.ty_to_def_id().expect("id interpolation");
+        let attrs = tcx.get_attrs(interpolation_id);
         let val = ::extract_attr(&attrs, "spirv", |s| match s {
-            "Const0" => Some(0u32),
-            "Const1" => Some(1),
-            "Const2" => Some(2),
-            "Const3" => Some(3),
-            "Const4" => Some(4),
-            "Const5" => Some(5),
+            "Smooth" => Some(Interpolation::Smooth),
+            "Flat" => Some(Interpolation::Flat),
+            "NoPerspective" => Some(Interpolation::NoPerspective),
+            "Centroid" => Some(Interpolation::Centroid),
+            "Sample" => Some(Interpolation::Sample),
             _ => None,
         });
         return val.get(0).map(|&i| i);
@@ -109,46 +235,753 @@ pub fn extract_location<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>)
     None
 }
 
+/// Integer varyings can not be interpolated and need to be `Flat`.
+fn is_integer_varying<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    let ty = TyVec::from_ty(tcx, ty).map(|ty_vec| ty_vec.ty).unwrap_or(ty);
+    ty.is_integral() || ty.is_bool()
+}
+
+/// Returns the type that occupies each location when `ty` is used as a
+/// varying. Struct members are assigned consecutive locations, arrays and
+/// matrices take one location per element or column.
+pub fn flatten_locations<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Vec<ty::Ty<'tcx>> {
+    if let Some(intrinsic) = IntrinsicType::from_ty(tcx, ty) {
+        return match intrinsic {
+            IntrinsicType::TyMat(ty_mat) => (0..ty_mat.cols).map(|_| ty_mat.ty).collect(),
+            _ => vec![ty],
+        };
+    }
+    match ty.sty {
+        TypeVariants::TyArray(elem_ty, len) => {
+            let elem_locations = flatten_locations(tcx, elem_ty);
+            (0..len.unwrap_usize(tcx))
+                .flat_map(|_| elem_locations.iter().cloned())
+                .collect()
+        }
+        TypeVariants::TyTuple(slice) => slice
+            .iter()
+            .flat_map(|ty| flatten_locations(tcx, ty))
+            .collect(),
+        TypeVariants::TyAdt(adt, substs) if adt.is_struct() => adt
+            .all_fields()
+            .map(|field| field.ty(tcx, substs))
+            .filter(|ty| !ty.is_phantom_data())
+            .flat_map(|ty| flatten_locations(tcx, ty))
+            .collect(),
+        _ => vec![ty],
+    }
+}
+
 pub struct EntryPoint<'a, 'tcx: 'a> {
     pub entry_type: IntrinsicEntry,
     pub mcx: SpirvMir<'a, 'tcx>,
+    /// The name of the `OpEntryPoint`, either from `#[spirv(name = "..")]`
+    /// or the name of the function.
+    pub name: String,
 }
 
 impl<'a, 'tcx> EntryPoint<'a, 'tcx> {
+    /// Creates an entry point for every stage that is declared on the function.
+    pub fn from_mir(mcx: &SpirvMir<'a, 'tcx>) -> Vec<Self> {
+        let tcx = mcx.tcx;
+        let attrs = tcx.get_attrs(mcx.def_id);
+        let name = extract_attr_value(&attrs, "spirv", "name")
+            .unwrap_or_else(|| tcx.item_name(mcx.def_id).to_string());
+        extract_attr(&attrs, "spirv", |s| match s {
+            "vertex" => Some(IntrinsicEntry::Vertex),
+            "fragment" => Some(IntrinsicEntry::Fragment),
+            "compute" => Some(IntrinsicEntry::Compute),
+            _ => None,
+        }).into_iter()
+        .map(|entry_type| EntryPoint {
+            mcx: mcx.clone(),
+            entry_type,
+            name: name.clone(),
+        }).collect()
+    }
+
+    /// The first argument can be the builtin of the stage, like `Fragment` or
+    /// `&mut Vertex`.
+    pub fn builtin_local(&self) -> Option<mir::Local> {
+        let local = self.mcx.mir().args_iter().nth(0)?;
+        let ty = self.mcx.mir().local_decls[local].ty;
+        stage_of_builtin(self.mcx.tcx, ty).map(|_| local)
+    }
+
+    /// The builtin argument has to belong to the stage of the entry point.
+    pub fn check_stage(&self) {
+        let tcx = self.mcx.tcx;
+        if let Some(local) = self.mcx.mir().args_iter().nth(0) {
+            let local_decl = &self.mcx.mir().local_decls[local];
+            match stage_of_builtin(tcx, local_decl.ty) {
+                Some(stage) if stage != self.entry_type => {
+                    tcx.sess.span_err(
+                        local_decl.source_info.span,
+                        &format!(
+                            "`{}` can not be used as a {:?} shader, the argument belongs to a {:?} shader",
+                            self.name, self.entry_type, stage
+                        ),
+                    );
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// Every member of a tuple or a `#[spirv(FragmentOutputs)]` struct has
+    /// to be an `Output` or a builtin output. `PhantomData` is ignored.
+    pub fn check_outputs(&self) {
+        let tcx = self.mcx.tcx;
+        if !self.has_multiple_outputs() {
+            return;
+        }
+        let span = self.mcx.mir().local_decls[mir::RETURN_PLACE].source_info.span;
+        for (idx, ty) in self.output_tys().into_iter().enumerate() {
+            if ty.is_nil() || ty.is_phantom_data() || self.output(idx, ty).is_some() {
+                continue;
+            }
+            tcx.sess
+                .struct_span_err(
+                    span,
+                    &format!("`{}` is not an output of `{}`", ty, self.name),
+                ).help("Every member has to be an `Output`, `FragDepth` or `SampleMask`")
+                .emit();
+        }
+    }
+
     pub fn input_iter(&'a self) -> impl Iterator<Item = Input<'tcx>> + 'a {
-        self.mcx.mir().args_iter().filter_map(move |local| {
-            let ty = self.mcx.mir().local_decls[local].ty;
-            Input::new(self, ty)
-        })
+        self.mcx
+            .mir()
+            .args_iter()
+            .filter_map(move |local| Input::new(self, local))
     }
 
     pub fn uniform_iter(&'a self) -> impl Iterator<Item = Uniform<'tcx>> + 'a {
-        self.mcx.mir().args_iter().filter_map(move |local| {
-            let ty = self.mcx.mir().local_decls[local].ty;
-            Uniform::new(self.mcx.tcx, ty)
-        })
+        self.mcx
+            .mir()
+            .args_iter()
+            .filter_map(move |local| Uniform::new(self, local))
     }
 
     pub fn buffer_iter(&'a self) -> impl Iterator<Item = Buffer<'tcx>> + 'a {
-        self.mcx.mir().args_iter().filter_map(move |local| {
-            let ty = self.mcx.mir().local_decls[local].ty;
-            Buffer::new(self.mcx.tcx, ty)
-        })
+        self.mcx
+            .mir()
+            .args_iter()
+            .filter_map(move |local| Buffer::new(self, local))
     }
 
     pub fn output_iter(&'a self) -> impl Iterator<Item = Output<'tcx>> + 'a {
-        use std::iter::once;
-        once(self.mcx.mir().return_ty()).filter_map(move |ty| Output::new(self.mcx.tcx, ty))
+        self.output_tys()
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(idx, ty)| self.output(idx, ty))
     }
 
-    pub fn args(&self) -> Vec<mir::Local> {
+    /// The output at `idx` of `output_tys`
+    pub fn output(&self, idx: usize, ty: ty::Ty<'tcx>) -> Option<Output<'tcx>> {
+        Output::new(self.mcx.tcx, ty, self.output_location(idx))
+    }
+
+    /// The location of an output, set with `#[spirv(output(0, location = 9))]`.
+    /// The index is the position of the output in the returned tuple or
+    /// struct, or `0` for a single output.
+    pub fn output_location(&self, idx: usize) -> Option<u32> {
+        output_metas(&self.mcx.tcx.get_attrs(self.mcx.def_id))
+            .into_iter()
+            .filter(|&(index, _)| index as usize == idx)
+            .flat_map(|(_, meta)| meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default())
+            .filter_map(|nested| nested.meta_item().cloned())
+            .filter(|item| item.name() == "location")
+            .filter_map(|item| meta_u32(&item))
+            .last()
+    }
+
+    /// A fragment shader can write to multiple outputs by returning a tuple
+    /// or a struct that is marked with `#[spirv(FragmentOutputs)]`.
+    pub fn has_multiple_outputs(&self) -> bool {
+        let tcx = self.mcx.tcx;
+        match self.mcx.mir().return_ty().sty {
+            TypeVariants::TyTuple(slice) => !slice.is_empty(),
+            TypeVariants::TyAdt(adt, _) => is_fragment_outputs(tcx, adt.did),
+            _ => false,
+        }
+    }
+
+    /// Returns the type of every output, in the order of the members of the
+    /// return type.
+    pub fn output_tys(&self) -> Vec<ty::Ty<'tcx>> {
+        let tcx = self.mcx.tcx;
+        let ret_ty = self.mcx.mir().return_ty();
+        if !self.has_multiple_outputs() {
+            return vec![ret_ty];
+        }
+        match ret_ty.sty {
+            TypeVariants::TyTuple(slice) => slice.iter().cloned().collect(),
+            TypeVariants::TyAdt(adt, substs) => adt
+                .all_fields()
+                .map(|field| field.ty(tcx, substs))
+                .collect(),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Every input has to occupy its own locations. Struct varyings take up
+    /// several consecutive locations, which makes it easy to accidentally
+    /// overlap with the next input.
+    pub fn check_locations(&self) {
+        let tcx = self.mcx.tcx;
+        let mut used: HashMap<u32, mir::Local> = HashMap::new();
+        for local in self.mcx.mir().args_iter() {
+            let local_decl = &self.mcx.mir().local_decls[local];
+            let input = if let Some(input) = Input::new(self, local) {
+                input
+            } else {
+                continue;
+            };
+            for (location, _) in input.locations(tcx) {
+                if let Some(&other) = used.get(&location) {
+                    let other_span = self.mcx.mir().local_decls[other].source_info.span;
+                    tcx.sess
+                        .struct_span_err(
+                            local_decl.source_info.span,
+                            &format!("Location {} is already used by another input", location),
+                        ).span_note(other_span, "previously used here")
+                        .emit();
+                    break;
+                }
+                used.insert(location, local);
+            }
+        }
+    }
+
+    /// Integer inputs of a fragment shader have to be declared with `Flat`,
+    /// otherwise the resulting SPIR-V is invalid. Vertex inputs and fragment
+    /// outputs can not be interpolated at all.
+    pub fn check_interpolation(&self) {
+        let tcx = self.mcx.tcx;
+        if self.entry_type == IntrinsicEntry::Fragment {
+            let span = self.mcx.mir().local_decls[mir::RETURN_PLACE].source_info.span;
+            for output in self.output_iter() {
+                if output.interpolation != Interpolation::Smooth {
+                    tcx.sess
+                        .struct_span_err(
+                            span,
+                            &format!(
+                                "The output `{}` of a fragment shader can not be interpolated",
+                                output.ty
+                            ),
+                        ).help("remove the interpolation qualifier")
+                        .emit();
+                }
+            }
+        }
+        for local in self.mcx.mir().args_iter() {
+            let local_decl = &self.mcx.mir().local_decls[local];
+            let input = match Input::new(self, local) {
+                Some(input) => input,
+                None => continue,
+            };
+            match self.entry_type {
+                IntrinsicEntry::Vertex if input.interpolation != Interpolation::Smooth => {
+                    tcx.sess
+                        .struct_span_err(
+                            local_decl.source_info.span,
+                            "The inputs of a vertex shader can not be interpolated",
+                        ).help("remove the interpolation qualifier")
+                        .emit();
+                }
+                // Every location of a struct or array varying is checked
+                IntrinsicEntry::Fragment if input.interpolation != Interpolation::Flat => {
+                    for (location, ty) in input.locations(tcx) {
+                        if is_integer_varying(tcx, ty) {
+                            tcx.sess
+                                .struct_span_err(
+                                    local_decl.source_info.span,
+                                    "Integer inputs of a fragment shader need to be `Flat`",
+                                ).note(&format!("`{}` is read from location {}", ty, location))
+                                .emit();
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+    }
+
+    /// The execution modes that are requested with attributes like
+    /// `#[spirv(early_fragment_tests)]`.
+    fn fragment_modes(&self) -> Vec<spirv::ExecutionMode> {
+        use spirv::ExecutionMode;
+        let attrs = self.mcx.tcx.get_attrs(self.mcx.def_id);
+        extract_attr(&attrs, "spirv", |s| match s {
+            "origin_lower_left" => Some(ExecutionMode::OriginLowerLeft),
+            "depth_replacing" => Some(ExecutionMode::DepthReplacing),
+            "depth_greater" => Some(ExecutionMode::DepthGreater),
+            "depth_less" => Some(ExecutionMode::DepthLess),
+            "depth_unchanged" => Some(ExecutionMode::DepthUnchanged),
+            "early_fragment_tests" => Some(ExecutionMode::EarlyFragmentTests),
+            "pixel_center_integer" => Some(ExecutionMode::PixelCenterInteger),
+            _ => None,
+        })
+    }
+
+    /// Fragment modes can only be used on a fragment shader, and at most one
+    /// of the depth modes can be used.
+    pub fn check_execution_modes(&self) {
+        use spirv::ExecutionMode;
+        let tcx = self.mcx.tcx;
+        let span = tcx.def_span(self.mcx.def_id);
+        let fragment_modes = self.fragment_modes();
+        if self.entry_type != IntrinsicEntry::Fragment {
+            if !fragment_modes.is_empty() {
+                tcx.sess.span_err(
+                    span,
+                    &format!(
+                        "{:?} can only be used on a fragment shader",
+                        fragment_modes
+                    ),
+                );
+            }
+            return;
+        }
+        let depth_modes = self
+            .execution_modes()
+            .iter()
+            .filter(|&&(mode, _)| match mode {
+                ExecutionMode::DepthGreater
+                | ExecutionMode::DepthLess
+                | ExecutionMode::DepthUnchanged => true,
+                _ => false,
+            }).count();
+        if depth_modes > 1 {
+            tcx.sess.span_err(
+                span,
+                "Only one of `depth_greater`, `depth_less` and `depth_unchanged` can be used",
+            );
+        }
+    }
+
+    /// Returns the execution modes of the entry point. Fragment shaders use
+    /// `OriginUpperLeft` by default and can opt into other modes with
+    /// attributes like `#[spirv(early_fragment_tests)]`, which are checked by
+    /// `check_execution_modes`.
+    pub fn execution_modes(&self) -> Vec<(spirv::ExecutionMode, Vec<u32>)> {
+        use spirv::ExecutionMode;
         match self.entry_type {
-            // Need to skip?
-            IntrinsicEntry::Vertex => self.mcx.mir().args_iter().skip(1).collect(),
-            IntrinsicEntry::Fragment => self.mcx.mir().args_iter().collect(),
-            IntrinsicEntry::Compute => self.mcx.mir().args_iter().collect(),
+            IntrinsicEntry::Vertex => Vec::new(),
+            IntrinsicEntry::Compute => vec![(ExecutionMode::LocalSize, vec![1, 1, 1])],
+            IntrinsicEntry::Fragment => {
+                let fragment_modes = self.fragment_modes();
+                let mut modes = Vec::new();
+                if !fragment_modes.contains(&ExecutionMode::OriginLowerLeft) {
+                    modes.push(ExecutionMode::OriginUpperLeft);
+                }
+                // Writing to `FragDepth` requires `DepthReplacing`
+                let writes_depth = self
+                    .output_iter()
+                    .any(|output| output.builtin == Some(spirv::BuiltIn::FragDepth));
+                if writes_depth {
+                    modes.push(ExecutionMode::DepthReplacing);
+                }
+                for mode in fragment_modes {
+                    if !modes.contains(&mode) {
+                        modes.push(mode);
+                    }
+                }
+                modes.into_iter().map(|mode| (mode, Vec::new())).collect()
+            }
+        }
+    }
+
+    /// The attributes of an argument, set with `#[spirv(param(name, ..))]`
+    pub fn param_attrs(&self, local: mir::Local) -> ParamAttrs {
+        match self.mcx.mir().local_decls[local].name {
+            Some(name) => param_attrs(self.mcx.tcx, self.mcx.def_id, &name.as_str()),
+            None => ParamAttrs::default(),
+        }
+    }
+
+    /// Every `param(..)` has to refer to an argument, every `output(..)` to
+    /// an output and every `FromAttr` has to be set by one of them.
+    pub fn check_params(&self) {
+        let tcx = self.mcx.tcx;
+        let mir = self.mcx.mir();
+        let names: Vec<String> = mir
+            .args_iter()
+            .filter_map(|local| mir.local_decls[local].name)
+            .map(|name| name.to_string())
+            .collect();
+        for (name, meta) in param_metas(&tcx.get_attrs(self.mcx.def_id)) {
+            if !names.contains(&name) {
+                tcx.sess.span_err(
+                    meta.span,
+                    &format!("`{}` is not an argument of `{}`", name, self.name),
+                );
+            }
+            let items = meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default();
+            for nested in items.iter().skip(1) {
+                match nested.meta_item() {
+                    Some(item) if ["location", "set", "binding"].contains(&&*item.name().as_str()) => {
+                        if meta_u32(item).is_none() {
+                            tcx.sess
+                                .struct_span_err(item.span, "Expected a number")
+                                .help("use `location = 5`")
+                                .emit();
+                        }
+                    }
+                    _ => {
+                        tcx.sess
+                            .struct_span_err(nested.span, "Unknown parameter attribute")
+                            .help("Expected `location`, `set` or `binding`")
+                            .emit();
+                    }
+                }
+            }
+        }
+
+        for local in mir.args_iter() {
+            let local_decl = &mir.local_decls[local];
+            let attrs = self.param_attrs(local);
+            let constants = if let Some(fields) = builtin_fields(tcx, local_decl.ty, "Input") {
+                vec![("location", attrs.location, fields[1])]
+            } else if let Some((fields, _)) =
+                descriptor_fields(tcx, local_decl.ty, &["Uniform"], "UniformArray")
+                    .or_else(|| descriptor_fields(tcx, local_decl.ty, BUFFERS, "BufferArray"))
+            {
+                vec![
+                    ("binding", attrs.binding, fields[1]),
+                    ("set", attrs.set, fields[2]),
+                ]
+            } else {
+                Vec::new()
+            };
+            let missing = constants
+                .into_iter()
+                .filter(|&(_, attr, ty)| attr.or_else(|| extract_location(tcx, ty)).is_none())
+                .map(|(key, _, _)| key);
+            let name = local_decl
+                .name
+                .map(|name| name.to_string())
+                .unwrap_or_else(|| "_".into());
+            for key in missing {
+                tcx.sess
+                    .struct_span_err(
+                        local_decl.source_info.span,
+                        &format!("The {} of `{}` is not set", key, name),
+                    ).help(&format!(
+                        "use `#[spirv(param({}, {} = ..))]` on the entry point",
+                        name, key
+                    )).emit();
+            }
+        }
+
+        let output_tys = self.output_tys();
+        for (index, meta) in output_metas(&tcx.get_attrs(self.mcx.def_id)) {
+            if index as usize >= output_tys.len() {
+                tcx.sess.span_err(
+                    meta.span,
+                    &format!("`{}` has no output {}", self.name, index),
+                );
+            }
+            let items = meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default();
+            for nested in items.iter().skip(1) {
+                match nested.meta_item() {
+                    Some(item) if item.name() == "location" => {
+                        if meta_u32(item).is_none() {
+                            tcx.sess
+                                .struct_span_err(item.span, "Expected a number")
+                                .help("use `location = 5`")
+                                .emit();
+                        }
+                    }
+                    _ => {
+                        tcx.sess
+                            .struct_span_err(nested.span, "Unknown output attribute")
+                            .help("Expected `location`")
+                            .emit();
+                    }
+                }
+            }
+        }
+
+        for (idx, &ty) in output_tys.iter().enumerate() {
+            if let Some(fields) = builtin_fields(tcx, ty, "Output") {
+                let location = self
+                    .output_location(idx)
+                    .or_else(|| extract_location(tcx, fields[1]));
+                if location.is_none() {
+                    tcx.sess
+                        .struct_span_err(
+                            tcx.def_span(self.mcx.def_id),
+                            &format!("The location of the output `{}` is not set", ty),
+                        ).help(&format!(
+                            "use `#[spirv(output({}, location = ..))]` on the entry point",
+                            idx
+                        )).emit();
+                }
+            }
         }
     }
+
+    /// A `RuntimeArray` can only be the data of a buffer, or the last member
+    /// of the data.
+    pub fn check_runtime_arrays(&self) {
+        let tcx = self.mcx.tcx;
+        let mir = self.mcx.mir();
+        for local in mir.args_iter() {
+            let local_decl = &mir.local_decls[local];
+            let valid = if let Some(fields) = buffer_fields(tcx, local_decl.ty) {
+                let data_ty = fields[0];
+                if let Some(array) = RuntimeArray::from_ty(tcx, data_ty) {
+                    !contains_runtime_array(tcx, array.ty)
+                } else if let TypeVariants::TyAdt(adt, substs) = data_ty.sty {
+                    let field_tys: Vec<_> = adt
+                        .all_fields()
+                        .map(|field| field.ty(tcx, substs))
+                        .filter(|ty| !ty.is_phantom_data())
+                        .collect();
+                    match field_tys.split_last() {
+                        Some((&last, rest)) => {
+                            let last = RuntimeArray::from_ty(tcx, last).map_or(last, |array| array.ty);
+                            !rest.iter().any(|&ty| contains_runtime_array(tcx, ty))
+                                && !contains_runtime_array(tcx, last)
+                        }
+                        None => true,
+                    }
+                } else {
+                    !contains_runtime_array(tcx, data_ty)
+                }
+            } else if let Some(fields) = builtin_fields(tcx, local_decl.ty, "Uniform") {
+                !contains_runtime_array(tcx, fields[0])
+            } else {
+                true
+            };
+            if !valid {
+                tcx.sess.span_err(
+                    local_decl.source_info.span,
+                    "A `RuntimeArray` can only be the last member of a buffer",
+                );
+            }
+        }
+    }
+
+    /// Writing to a `BufferRead` is an error. A buffer that is passed to
+    /// another function, for example as `&buffer.data`, is followed into the
+    /// callee. `functions` contains the MIR of every function that is not an
+    /// entry point.
+    pub fn check_buffer_writes(
+        &self,
+        intrinsic_fns: &HashMap<hir::def_id::DefId, Intrinsic>,
+        functions: &HashMap<validate::Node<'tcx>, &mir::Mir<'tcx>>,
+    ) {
+        let tcx = self.mcx.tcx;
+        let mir = self.mcx.mir();
+        let node = (self.mcx.def_id, self.mcx.substs);
+        // The function, the local that refers to the read-only buffer, the
+        // name of the buffer and the calls that passed it to the function
+        let mut queue: Vec<_> = mir
+            .args_iter()
+            .filter(|&local| {
+                BufferAccess::from_ty(tcx, mir.local_decls[local].ty) == BufferAccess::ReadOnly
+            }).map(|local| {
+                let name = mir.local_decls[local]
+                    .name
+                    .map(|name| name.to_string())
+                    .unwrap_or_else(|| "_".into());
+                let calls: Vec<(syntax_pos::Span, hir::def_id::DefId)> = Vec::new();
+                (node, mir, local, name, calls)
+            }).collect();
+        let mut uses = HashMap::new();
+        let mut visited = HashSet::new();
+        while let Some((node, mir, local, name, calls)) = queue.pop() {
+            let fn_uses = uses
+                .entry(node)
+                .or_insert_with(|| BufferUses::new(tcx, node.1, mir, intrinsic_fns));
+            for &(span, _) in fn_uses.writes.iter().filter(|&&(_, root)| root == local) {
+                let mut err = tcx.sess.struct_span_err(
+                    span,
+                    &format!("Cannot write to the read-only buffer `{}`", name),
+                );
+                for &(call_span, callee) in &calls {
+                    err.span_note(
+                        call_span,
+                        &format!(
+                            "`{}` is passed to `{}` here",
+                            name,
+                            tcx.item_path_str(callee)
+                        ),
+                    );
+                }
+                err.help("use `Buffer` instead of `BufferRead`").emit();
+            }
+            for &(callee, arg, _, span) in fn_uses
+                .calls
+                .iter()
+                .filter(|&&(_, _, root, _)| root == local)
+            {
+                let callee_mir = match functions.get(&callee) {
+                    Some(&callee_mir) => callee_mir,
+                    None => continue,
+                };
+                let callee_local = match callee_mir.args_iter().nth(arg) {
+                    Some(callee_local) => callee_local,
+                    None => continue,
+                };
+                if visited.insert((callee, callee_local, name.clone())) {
+                    let mut calls = calls.clone();
+                    calls.push((span, callee.0));
+                    queue.push((callee, callee_mir, callee_local, name.clone(), calls));
+                }
+            }
+        }
+    }
+
+    pub fn args(&self) -> Vec<mir::Local> {
+        let builtin = self.builtin_local();
+        self.mcx
+            .mir()
+            .args_iter()
+            .filter(|&local| Some(local) != builtin)
+            .collect()
+    }
+}
+
+/// The writes and calls of a function, together with the local that they
+/// refer to. Eg `_5 = &(_2.0)` followed by a store to `_5` is a write to
+/// `_2`.
+struct BufferUses<'tcx> {
+    writes: Vec<(syntax_pos::Span, mir::Local)>,
+    /// The callee, the index of the argument, the local that is passed and
+    /// the span of the call
+    calls: Vec<(validate::Node<'tcx>, usize, mir::Local, syntax_pos::Span)>,
+}
+
+impl<'tcx> BufferUses<'tcx> {
+    fn new<'a>(
+        tcx: TyCtxt<'a, 'tcx, 'tcx>,
+        substs: &'tcx Substs<'tcx>,
+        mir: &mir::Mir<'tcx>,
+        intrinsic_fns: &HashMap<hir::def_id::DefId, Intrinsic>,
+    ) -> Self {
+        let mut references = HashMap::new();
+        for data in mir.basic_blocks() {
+            for statement in &data.statements {
+                if let mir::StatementKind::Assign(
+                    mir::Place::Local(local),
+                    mir::Rvalue::Ref(_, _, ref place),
+                ) = statement.kind
+                {
+                    references.insert(local, place);
+                }
+            }
+        }
+        let root_local = |mut place: &mir::Place<'tcx>| loop {
+            match *place {
+                mir::Place::Local(local) => match references.get(&local) {
+                    Some(&referenced) => place = referenced,
+                    None => return Some(local),
+                },
+                mir::Place::Projection(ref proj) => place = &proj.base,
+                _ => return None,
+            }
+        };
+        let operand_root = |operand: &mir::Operand<'tcx>| match *operand {
+            mir::Operand::Move(ref place) | mir::Operand::Copy(ref place) => root_local(place),
+            _ => None,
+        };
+        let mut uses = BufferUses {
+            writes: Vec::new(),
+            calls: Vec::new(),
+        };
+        for data in mir.basic_blocks() {
+            for statement in &data.statements {
+                // Assigning to the local itself is not a write to the buffer
+                if let mir::StatementKind::Assign(ref place @ mir::Place::Projection(_), _) =
+                    statement.kind
+                {
+                    if let Some(local) = root_local(place) {
+                        uses.writes.push((statement.source_info.span, local));
+                    }
+                }
+            }
+            let terminator = data.terminator();
+            if let mir::TerminatorKind::Call {
+                ref func, ref args, ..
+            } = terminator.kind
+            {
+                let span = terminator.source_info.span;
+                let is_store = match func.ty(mir, tcx).sty {
+                    TypeVariants::TyFnDef(def_id, _) => match intrinsic_fns.get(&def_id) {
+                        Some(Intrinsic::RuntimeArray(RuntimeArrayIntrinsic::Store))
+                        | Some(Intrinsic::DescriptorArray(DescriptorArrayIntrinsic::Store {
+                            ..
+                        })) => true,
+                        _ => false,
+                    },
+                    _ => false,
+                };
+                if is_store {
+                    if let Some(local) = args.get(0).and_then(|arg| operand_root(arg)) {
+                        uses.writes.push((span, local));
+                    }
+                    continue;
+                }
+                if let Some(callee) = validate::resolve_call(tcx, substs, mir, func) {
+                    for (idx, arg) in args.iter().enumerate() {
+                        if let Some(local) = operand_root(arg) {
+                            uses.calls.push((callee, idx, local, span));
+                        }
+                    }
+                }
+            }
+        }
+        uses
+    }
+}
+
+/// Two entry points of the same stage can not share a name.
+pub fn check_entry_names<'a, 'tcx>(entry_points: &[EntryPoint<'a, 'tcx>]) {
+    let mut names: HashMap<(IntrinsicEntry, &str), hir::def_id::DefId> = HashMap::new();
+    for entry in entry_points {
+        let tcx = entry.mcx.tcx;
+        let key = (entry.entry_type, entry.name.as_str());
+        if let Some(&other) = names.get(&key) {
+            tcx.sess
+                .struct_span_err(
+                    tcx.def_span(entry.mcx.def_id),
+                    &format!(
+                        "A {:?} entry point with the name `{}` is already defined",
+                        entry.entry_type, entry.name
+                    ),
+                ).span_note(tcx.def_span(other), "previous definition here")
+                .help("use `#[spirv(name = \"..\")]` to rename the entry point")
+                .emit();
+        } else {
+            names.insert(key, entry.mcx.def_id);
+        }
+    }
+}
+
+/// Returns the stage a builtin like `Vertex`, `Fragment` or `Compute` belongs to.
+fn stage_of_builtin<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<IntrinsicEntry> {
+    if let TypeVariants::TyAdt(adt, _) = remove_ptr_ty(ty).sty {
+        let attrs = tcx.get_attrs(adt.did);
+        extract_attr(&attrs, "spirv", |s| match s {
+            "PerVertex" => Some(IntrinsicEntry::Vertex),
+            "PerFragment" => Some(IntrinsicEntry::Fragment),
+            "Compute" => Some(IntrinsicEntry::Compute),
+            _ => None,
+        }).get(0)
+        .cloned()
+    } else {
+        None
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -198,20 +1031,38 @@ use std::collections::HashSet;
 pub struct Input<'tcx> {
     pub ty: ty::Ty<'tcx>,
     pub location: u32,
+    pub interpolation: Interpolation,
 }
 
 impl<'tcx> Input<'tcx> {
-    fn new<'a>(entry_point: &EntryPoint<'a, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
-        let (adt, substs) = get_builtin_adt(entry_point.mcx.tcx, ty, "Input")?;
-        let fields: Vec<_> = adt
-            .all_fields()
-            .map(|field| field.ty(entry_point.mcx.tcx, substs))
-            .collect();
-        assert!(fields.len() == 2, "Input should have two fields");
-        let location_ty = fields[1];
-        let location =
-            extract_location(entry_point.mcx.tcx, location_ty).expect("Unable to extract location");
-        Some(Input { ty, location })
+    fn new<'a>(entry_point: &EntryPoint<'a, 'tcx>, local: mir::Local) -> Option<Self> {
+        let tcx = entry_point.mcx.tcx;
+        let ty = entry_point.mcx.mir().local_decls[local].ty;
+        let fields = builtin_fields(tcx, ty, "Input")?;
+        assert!(fields.len() == 3, "Input should have three fields");
+        // A missing location is reported by `check_params`
+        let location = entry_point
+            .param_attrs(local)
+            .location
+            .or_else(|| extract_location(tcx, fields[1]))
+            .unwrap_or(0);
+        let interpolation =
+            extract_interpolation(tcx, fields[2]).expect("Unable to extract interpolation");
+        Some(Input {
+            ty,
+            location,
+            interpolation,
+        })
+    }
+
+    /// The type of the data that is wrapped inside the `Input`
+    pub fn data_ty<'a>(&self, tcx: TyCtxt<'a, 'tcx, 'tcx>) -> ty::Ty<'tcx> {
+        data_ty(tcx, self.ty)
+    }
+
+    /// Maps every location that is occupied by this input to its type
+    pub fn locations<'a>(&self, tcx: TyCtxt<'a, 'tcx, 'tcx>) -> Vec<(u32, ty::Ty<'tcx>)> {
+        locations(tcx, self.ty, self.location)
     }
 }
 
@@ -219,42 +1070,209 @@ impl<'tcx> Input<'tcx> {
 pub struct Output<'tcx> {
     pub ty: ty::Ty<'tcx>,
     pub location: u32,
+    pub interpolation: Interpolation,
+    pub builtin: Option<spirv::BuiltIn>,
 }
 
 impl<'tcx> Output<'tcx> {
-    fn new<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
+    /// `location` is set with `#[spirv(output(..))]` and takes precedence
+    /// over the type level constant
+    fn new<'a>(
+        tcx: TyCtxt<'a, 'tcx, 'tcx>,
+        ty: ty::Ty<'tcx>,
+        location: Option<u32>,
+    ) -> Option<Self> {
+        if let Some(builtin) = builtin_output(tcx, ty) {
+            return Some(Output {
+                ty,
+                location: 0,
+                interpolation: Interpolation::Smooth,
+                builtin: Some(builtin),
+            });
+        }
         let (adt, substs) = get_builtin_adt(tcx, ty, "Output")?;
         let fields: Vec<_> = adt
             .all_fields()
             .map(|field| field.ty(tcx, substs))
             .collect();
-        assert!(fields.len() == 2, "Output should have two fields");
-        let location_ty = fields[1];
-        let location = extract_location(tcx, location_ty).expect("Unable to extract location");
-        Some(Output { ty, location })
+        assert!(fields.len() == 3, "Output should have three fields");
+        // A missing location is reported by `check_params`
+        let location = location
+            .or_else(|| extract_location(tcx, fields[1]))
+            .unwrap_or(0);
+        let interpolation =
+            extract_interpolation(tcx, fields[2]).expect("Unable to extract interpolation");
+        Some(Output {
+            ty,
+            location,
+            interpolation,
+            builtin: None,
+        })
+    }
+
+    /// Maps every location that is occupied by this output to its type
+    pub fn locations<'a>(&self, tcx: TyCtxt<'a, 'tcx, 'tcx>) -> Vec<(u32, ty::Ty<'tcx>)> {
+        if self.builtin.is_some() {
+            return Vec::new();
+        }
+        locations(tcx, self.ty, self.location)
+    }
+}
+
+/// Returns the builtin of `FragDepth` and `SampleMask`
+pub fn builtin_output<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<spirv::BuiltIn> {
+    if let TypeVariants::TyAdt(adt, _) = ty.sty {
+        let attrs = tcx.get_attrs(adt.did);
+        extract_attr(&attrs, "spirv", |s| match s {
+            "FragDepth" => Some(spirv::BuiltIn::FragDepth),
+            "SampleMask" => Some(spirv::BuiltIn::SampleMask),
+            _ => None,
+        }).get(0)
+        .cloned()
+    } else {
+        None
+    }
+}
+
+fn is_fragment_outputs<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, def_id: hir::def_id::DefId) -> bool {
+    let attrs = tcx.get_attrs(def_id);
+    extract_attr(&attrs, "spirv", |s| match s {
+        "FragmentOutputs" => Some(()),
+        _ => None,
+    }).get(0)
+    .is_some()
+}
+
+fn locations<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    location: u32,
+) -> Vec<(u32, ty::Ty<'tcx>)> {
+    flatten_locations(tcx, data_ty(tcx, ty))
+        .into_iter()
+        .enumerate()
+        .map(|(idx, ty)| (location + idx as u32, ty))
+        .collect()
+}
+
+/// Returns the type of the first field of a builtin wrapper like `Input<N0, T>`.
+fn data_ty<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> ty::Ty<'tcx> {
+    match ty.sty {
+        TypeVariants::TyAdt(adt, substs) => adt
+            .all_fields()
+            .nth(0)
+            .expect("data field")
+            .ty(tcx, substs),
+        _ => ty,
+    }
+}
+
+/// The location is placed on the variable and not on the block, because the
+/// same type can be used at different locations. If the data is a struct,
+/// its members are assigned consecutive locations.
+fn decorate_location<'a, 'tcx>(stx: &mut CodegenCx<'a, 'tcx>, var: spirv::Word, location: u32) {
+    stx.builder.decorate(
+        var,
+        spirv::Decoration::Location,
+        &[rspirv::mr::Operand::LiteralInt32(location)],
+    );
+}
+
+/// Interpolation decorations are only allowed on the outputs of a vertex
+/// shader and on the inputs of a fragment shader, which `check_interpolation`
+/// makes sure of. The decoration is placed on the variable, because the type
+/// of the block is shared by every variable of the same Rust type.
+fn decorate_interpolation<'a, 'tcx>(
+    stx: &mut CodegenCx<'a, 'tcx>,
+    var: spirv::Word,
+    interpolation: Interpolation,
+) {
+    for &decoration in interpolation.decorations() {
+        stx.builder.decorate(var, decoration, &[]);
     }
 }
 
+/// The descriptor decorations are placed once on the global variable, which
+/// is shared by every entry point that uses the same descriptor.
+fn decorate_descriptor<'a, 'tcx>(
+    stx: &mut CodegenCx<'a, 'tcx>,
+    var: spirv::Word,
+    set: u32,
+    binding: u32,
+) {
+    stx.builder.decorate(
+        var,
+        spirv::Decoration::DescriptorSet,
+        &[rspirv::mr::Operand::LiteralInt32(set)],
+    );
+    stx.builder.decorate(
+        var,
+        spirv::Decoration::Binding,
+        &[rspirv::mr::Operand::LiteralInt32(binding)],
+    );
+}
+
 #[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
 pub struct Uniform<'tcx> {
     pub ty: ty::Ty<'tcx>,
     pub set: u32,
     pub binding: u32,
+    pub rules: LayoutRules,
+    /// `UniformArray` binds an array of descriptors
+    pub count: DescriptorCount,
 }
 
 impl<'tcx> Uniform<'tcx> {
-    fn new<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
-        let (adt, substs) = get_builtin_adt(tcx, ty, "Uniform")?;
-        let fields: Vec<_> = adt
-            .all_fields()
-            .map(|field| field.ty(tcx, substs))
-            .collect();
+    fn new<'a>(entry_point: &EntryPoint<'a, 'tcx>, local: mir::Local) -> Option<Self> {
+        let tcx = entry_point.mcx.tcx;
+        let ty = entry_point.mcx.mir().local_decls[local].ty;
+        let (fields, count) = descriptor_fields(tcx, ty, &["Uniform"], "UniformArray")?;
         assert_eq!(fields.len(), 3, "Uniform should have 3 fields");
-        let binding_ty = fields[1];
-        let set_ty = fields[2];
-        let binding = extract_location(tcx, binding_ty).expect("Unable to extract location");
-        let set = extract_location(tcx, set_ty).expect("Unable to extract location");
-        Some(Uniform { ty, binding, set })
+        let attrs = entry_point.param_attrs(local);
+        // A missing binding or set is reported by `check_params`
+        let binding = attrs
+            .binding
+            .or_else(|| extract_location(tcx, fields[1]))
+            .unwrap_or(0);
+        let set = attrs
+            .set
+            .or_else(|| extract_location(tcx, fields[2]))
+            .unwrap_or(0);
+        let rules = block_layout_rules(tcx, fields[0], LayoutRules::Std140);
+        Some(Uniform {
+            ty,
+            binding,
+            set,
+            rules,
+            count,
+        })
+    }
+}
+
+/// The builtins that are a single storage buffer
+const BUFFERS: &[&str] = &["Buffer", "BufferRead", "BufferWrite"];
+
+/// `BufferRead` is decorated with `NonWritable` and `BufferWrite` with
+/// `NonReadable`.
+#[derive(Copy, Clone, Debug, Hash, PartialEq, Eq)]
+pub enum BufferAccess {
+    ReadWrite,
+    ReadOnly,
+    WriteOnly,
+}
+
+impl BufferAccess {
+    pub fn from_ty<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Self {
+        if get_builtin_adt(tcx, ty, "BufferRead").is_some() {
+            BufferAccess::ReadOnly
+        } else if get_builtin_adt(tcx, ty, "BufferWrite").is_some() {
+            BufferAccess::WriteOnly
+        } else {
+            BufferAccess::ReadWrite
+        }
     }
 }
 
@@ -263,24 +1281,152 @@ pub struct Buffer<'tcx> {
     pub ty: ty::Ty<'tcx>,
     pub set: u32,
     pub binding: u32,
+    pub rules: LayoutRules,
+    /// `BufferArray` binds an array of descriptors
+    pub count: DescriptorCount,
+    pub access: BufferAccess,
 }
 
 impl<'tcx> Buffer<'tcx> {
-    fn new<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
-        let (adt, substs) = get_builtin_adt(tcx, ty, "Buffer")?;
-        let fields: Vec<_> = adt
-            .all_fields()
-            .map(|field| field.ty(tcx, substs))
-            .collect();
+    fn new<'a>(entry_point: &EntryPoint<'a, 'tcx>, local: mir::Local) -> Option<Self> {
+        let tcx = entry_point.mcx.tcx;
+        let ty = entry_point.mcx.mir().local_decls[local].ty;
+        let (fields, count) = descriptor_fields(tcx, ty, BUFFERS, "BufferArray")?;
         assert_eq!(fields.len(), 3, "Buffer should have 3 fields");
-        let binding_ty = fields[1];
-        let set_ty = fields[2];
-        let binding = extract_location(tcx, binding_ty).expect("Unable to extract location");
-        let set = extract_location(tcx, set_ty).expect("Unable to extract location");
-        Some(Buffer { ty, binding, set })
+        let attrs = entry_point.param_attrs(local);
+        // A missing binding or set is reported by `check_params`
+        let binding = attrs
+            .binding
+            .or_else(|| extract_location(tcx, fields[1]))
+            .unwrap_or(0);
+        let set = attrs
+            .set
+            .or_else(|| extract_location(tcx, fields[2]))
+            .unwrap_or(0);
+        let rules = block_layout_rules(tcx, fields[0], LayoutRules::Std430);
+        Some(Buffer {
+            ty,
+            binding,
+            set,
+            rules,
+            count,
+            access: BufferAccess::from_ty(tcx, ty),
+        })
+    }
+}
+
+/// The layout of a block can be changed with `#[spirv(layout = "scalar")]`,
+/// either on the type of the data or crate-wide. For arrays the attribute is
+/// looked up on the element type.
+fn block_layout_rules<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    data_ty: ty::Ty<'tcx>,
+    default: LayoutRules,
+) -> LayoutRules {
+    if let Some(IntrinsicType::RuntimeArray(rt_array)) = IntrinsicType::from_ty(tcx, data_ty) {
+        return block_layout_rules(tcx, rt_array.ty, default);
+    }
+    if let TypeVariants::TyArray(elem_ty, _) = data_ty.sty {
+        return block_layout_rules(tcx, elem_ty, default);
+    }
+    let crate_def_id = hir::def_id::DefId::local(hir::def_id::CRATE_DEF_INDEX);
+    let item_layout = data_ty.ty_to_def_id().and_then(|def_id| {
+        extract_attr_value(&tcx.get_attrs(def_id), "spirv", "layout").map(|layout| (def_id, layout))
+    });
+    let crate_layout = || {
+        extract_attr_value(&tcx.get_attrs(crate_def_id), "spirv", "layout")
+            .map(|layout| (crate_def_id, layout))
+    };
+    match item_layout.or_else(crate_layout) {
+        Some((def_id, layout)) => LayoutRules::from_str(&layout).unwrap_or_else(|| {
+            tcx.sess
+                .struct_span_err(tcx.def_span(def_id), &format!("Unknown layout `{}`", layout))
+                .help("Expected `std140`, `std430` or `scalar`")
+                .emit();
+            default
+        }),
+        None => default,
+    }
+}
+
+fn contains_runtime_array<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> bool {
+    if RuntimeArray::from_ty(tcx, ty).is_some() {
+        return true;
+    }
+    match ty.sty {
+        TypeVariants::TyArray(elem_ty, _) => contains_runtime_array(tcx, elem_ty),
+        TypeVariants::TyAdt(adt, substs) if adt.is_struct() => adt
+            .all_fields()
+            .any(|field| contains_runtime_array(tcx, field.ty(tcx, substs))),
+        _ => false,
+    }
+}
+
+/// Returns the data of a `Buffer` if it is a struct that ends with a
+/// `RuntimeArray`, like `struct Particles { count: u32, data: RuntimeArray<Particle> }`.
+/// SPIR-V only allows a runtime array as the last member of the block itself,
+/// which is why the data is used as the block instead of being wrapped.
+pub fn unwrapped_block<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<ty::Ty<'tcx>> {
+    let data_ty = buffer_fields(tcx, ty)?[0];
+    match data_ty.sty {
+        TypeVariants::TyAdt(adt, substs)
+            if adt.is_struct() && IntrinsicType::from_ty(tcx, data_ty).is_none() =>
+        {
+            let last_ty = adt
+                .all_fields()
+                .map(|field| field.ty(tcx, substs))
+                .filter(|ty| !ty.is_phantom_data())
+                .last()?;
+            RuntimeArray::from_ty(tcx, last_ty).map(|_| data_ty)
+        }
+        _ => None,
     }
 }
 
+/// Returns the fields of a single descriptor like `Uniform` or an array like
+/// `UniformArray`, together with the number of descriptors that it binds.
+fn descriptor_fields<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    single: &[&str],
+    array: &str,
+) -> Option<(Vec<ty::Ty<'tcx>>, DescriptorCount)> {
+    if let Some(fields) = single.iter().filter_map(|s| builtin_fields(tcx, ty, s)).next() {
+        return Some((fields, DescriptorCount::One));
+    }
+    let fields = builtin_fields(tcx, ty, array)?;
+    let count = match fields[0].sty {
+        TypeVariants::TyArray(_, len) => DescriptorCount::Array(len.unwrap_usize(tcx) as u32),
+        _ => DescriptorCount::Runtime,
+    };
+    Some((fields, count))
+}
+
+/// Returns the fields of a `Buffer`, `BufferRead` or `BufferWrite`
+fn buffer_fields<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+) -> Option<Vec<ty::Ty<'tcx>>> {
+    BUFFERS.iter().filter_map(|s| builtin_fields(tcx, ty, s)).next()
+}
+
+/// Returns the types of the fields of a builtin like `Uniform<N0, N1, T>`
+fn builtin_fields<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    attribute: &str,
+) -> Option<Vec<ty::Ty<'tcx>>> {
+    let (adt, substs) = get_builtin_adt(tcx, ty, attribute)?;
+    Some(
+        adt.all_fields()
+            .map(|field| field.ty(tcx, substs))
+            .collect(),
+    )
+}
+
 use rustc::ty::AdtDef;
 fn get_builtin_adt<'a, 'tcx>(
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
@@ -311,6 +1457,9 @@ impl<'tcx> Global<'tcx> for Buffer<'tcx> {
     fn ty(&self) -> ty::Ty<'tcx> {
         self.ty
     }
+    fn layout_rules(&self) -> Option<LayoutRules> {
+        Some(self.rules)
+    }
 }
 
 impl<'tcx> Global<'tcx> for Output<'tcx> {
@@ -322,10 +1471,74 @@ impl<'tcx> Global<'tcx> for Uniform<'tcx> {
     fn ty(&self) -> ty::Ty<'tcx> {
         self.ty
     }
+    fn layout_rules(&self) -> Option<LayoutRules> {
+        Some(self.rules)
+    }
+}
+
+/// Checks that the inputs of every fragment shader match the outputs of the
+/// vertex shaders in the same module.
+pub fn check_interface<'a, 'tcx>(entry_points: &[EntryPoint<'a, 'tcx>]) {
+    let vertex_entries: Vec<_> = entry_points
+        .iter()
+        .filter(|entry| entry.entry_type == IntrinsicEntry::Vertex)
+        .collect();
+    if vertex_entries.is_empty() {
+        return;
+    }
+    let fragment_entries = entry_points
+        .iter()
+        .filter(|entry| entry.entry_type == IntrinsicEntry::Fragment);
+    for fragment in fragment_entries {
+        let tcx = fragment.mcx.tcx;
+        for vertex in &vertex_entries {
+            let outputs: HashMap<u32, ty::Ty<'tcx>> = vertex
+                .output_iter()
+                .flat_map(|output| output.locations(tcx))
+                .collect();
+            for local in fragment.mcx.mir().args_iter() {
+                let local_decl = &fragment.mcx.mir().local_decls[local];
+                let input = if let Some(input) = Input::new(fragment, local) {
+                    input
+                } else {
+                    continue;
+                };
+                for (location, input_ty) in input.locations(tcx) {
+                    match outputs.get(&location) {
+                        Some(&output_ty) if output_ty != input_ty => {
+                            tcx.sess
+                                .struct_span_err(
+                                    local_decl.source_info.span,
+                                    &format!(
+                                        "Interface mismatch at location {}: `{}` is read as `{}`",
+                                        location, output_ty, input_ty
+                                    ),
+                                ).span_note(vertex.mcx.mir().span, "written by this vertex shader")
+                                .emit();
+                        }
+                        None if vertex_entries.len() == 1 => {
+                            tcx.sess.span_warn(
+                                local_decl.source_info.span,
+                                &format!(
+                                    "Location {} is not written by the vertex shader",
+                                    location
+                                ),
+                            );
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
 }
 
 pub trait Global<'tcx>: Hash + Eq {
     fn ty(&self) -> ty::Ty<'tcx>;
+    /// The layout of a block, see `CodegenCx::to_layout_ty`
+    fn layout_rules(&self) -> Option<LayoutRules> {
+        None
+    }
 }
 impl<'tcx> Entry<'tcx, Input<'tcx>> {
     pub fn input<'a>(entry_points: &[EntryPoint<'a, 'tcx>], stx: &mut CodegenCx<'a, 'tcx>) -> Self {
@@ -333,7 +1546,12 @@ impl<'tcx> Entry<'tcx, Input<'tcx>> {
             .iter()
             .flat_map(|entry| EntryPoint::input_iter(entry))
             .collect();
-        Self::create(set, stx, spirv::StorageClass::Input)
+        let entry = Self::create(set, stx, spirv::StorageClass::Input);
+        for (input, global) in &entry.global_vars {
+            decorate_location(stx, global.var, input.location);
+            decorate_interpolation(stx, global.var, input.interpolation);
+        }
+        entry
     }
 
     fn variable_iter<'borrow, 'a>(
@@ -343,138 +1561,88 @@ impl<'tcx> Entry<'tcx, Input<'tcx>> {
         entry
             .mcx
             .mir()
-            .args_iter()
-            .filter_map(move |local| {
-                let ty = entry.mcx.mir().local_decls[local].ty;
-                Input::new(entry, ty).map(|input| (local, input))
-            }).map(move |(local, input)| {
-                (local, *self.global_vars.get(&input).expect("Entry compute"))
-            })
-    }
-}
-
-#[derive(Debug, Clone)]
-pub enum Layout<'tcx> {
-    Single(SingleLayout<'tcx>),
-    Composition(Vec<Box<Layout<'tcx>>>),
-}
-
-impl<'tcx> Layout<'tcx> {
-    pub fn size(&self) -> usize {
-        self.size_impl().0
+            .args_iter()
+            .filter_map(move |local| Input::new(entry, local).map(|input| (local, input)))
+            .map(move |(local, input)| {
+                (local, *self.global_vars.get(&input).expect("Entry compute"))
+            })
     }
+}
 
-    pub fn offsets(&self) -> Vec<usize> {
-        self.size_impl().1
-    }
+use syntax::ast;
 
-    fn size_impl(&self) -> (usize, Vec<usize>) {
-        match *self {
-            Layout::Single(single) => (single.size, Vec::new()),
-            Layout::Composition(ref comp) => {
-                let mut offset = 0;
-                let offsets = comp
-                    .iter()
-                    .map(|layout| {
-                        let align = layout.align();
-                        let aligned_offset = (align - (offset % align)) % align + offset;
-                        offset = aligned_offset + layout.size();
-                        aligned_offset
-                    }).collect_vec();
-                (offset, offsets)
-            }
-        }
-    }
+pub fn std140_layout<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Layout> {
+    ty_layout(tcx, ty, LayoutRules::Std140)
+}
 
-    pub fn align(&self) -> usize {
-        match *self {
-            Layout::Single(single) => single.align,
-            Layout::Composition(ref comp) => {
-                let max_align = comp.iter().map(|layout| layout.align()).max().unwrap_or(0);
-                max_align.max(16).min(16)
-            }
-        }
-    }
+pub fn std430_layout<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Layout> {
+    ty_layout(tcx, ty, LayoutRules::Std430)
 }
 
-#[derive(Debug, Copy, Clone)]
-pub struct SingleLayout<'tcx> {
-    pub ty: ty::Ty<'tcx>,
-    pub size: usize,
-    pub align: usize,
+pub fn ty_layout<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    rules: LayoutRules,
+) -> Option<Layout> {
+    layout_ty(tcx, ty).map(|layout_ty| Layout::new(&layout_ty, rules))
 }
-use syntax::ast;
-pub fn std430_layout<'a, 'tcx>(
+
+/// Returns the stride between the columns of a matrix, or `None` if `ty`
+/// is not a matrix or an array of matrices.
+pub fn matrix_stride<'a, 'tcx>(
     tcx: TyCtxt<'a, 'tcx, 'tcx>,
     ty: ty::Ty<'tcx>,
-) -> Option<Layout<'tcx>> {
+    rules: LayoutRules,
+) -> Option<usize> {
+    ty_layout(tcx, ty, rules).and_then(|layout| layout.matrix_stride())
+}
+
+/// Describes `ty` for the layout engine, or returns `None` if `ty` can not be
+/// used inside of a `Uniform` or `Buffer` block.
+pub fn layout_ty<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<LayoutTy> {
     if let Some(intrinsic) = IntrinsicType::from_ty(tcx, ty) {
-        match intrinsic {
-            IntrinsicType::TyVec(ty_vec) => {
-                let multiplier = match ty_vec.dim {
-                    2 => 2,
-                    3 => 4,
-                    4 => 4,
-                    _ => unreachable!(),
-                };
-                return std430_layout(tcx, ty_vec.ty).map(|inner_layout| {
-                    let single = SingleLayout {
-                        ty,
-                        size: inner_layout.size() * ty_vec.dim,
-                        align: inner_layout.align() * multiplier,
-                    };
-                    Layout::Single(single)
-                });
+        return match intrinsic {
+            IntrinsicType::TyVec(ty_vec) => Some(LayoutTy::Vector {
+                scalar: scalar_size(ty_vec.ty)?,
+                dim: ty_vec.dim,
+            }),
+            IntrinsicType::TyMat(ty_mat) => {
+                let column = TyVec::from_ty(tcx, ty_mat.ty)?;
+                Some(LayoutTy::Matrix {
+                    scalar: scalar_size(column.ty)?,
+                    rows: column.dim,
+                    cols: ty_mat.cols,
+                })
             }
             IntrinsicType::RuntimeArray(rt_array) => {
-                return std430_layout(tcx, rt_array.ty).map(|inner_layout| {
-                    let single = SingleLayout {
-                        ty,
-                        size: 0,
-                        // [FIXME] proper alignment for arrays
-                        align: inner_layout.align() % 16,
-                    };
-                    Layout::Single(single)
-                });
+                layout_ty(tcx, rt_array.ty).map(|elem| LayoutTy::RuntimeArray(Box::new(elem)))
             }
-            _ => unimplemented!("Intrinsic std140"),
-        }
+            IntrinsicType::DescriptorArray(_) => None,
+        };
     }
 
     match ty.sty {
-        // TODO: Other variants
-        TypeVariants::TyUint(uint_ty) => {
-            assert!(uint_ty == ast::UintTy::U32, "only u32");
-            let single = SingleLayout {
-                ty,
-                size: 4,
-                align: 4,
-            };
-            Some(Layout::Single(single))
-        }
-        TypeVariants::TyFloat(float_ty) => {
-            assert!(float_ty == ast::FloatTy::F32, "F64 is not supported");
-            let single = SingleLayout {
-                ty,
-                size: 4,
-                align: 4,
-            };
-            Some(Layout::Single(single))
-        }
-        TypeVariants::TyAdt(adt, substs) => {
-            if adt.is_struct() {
-                let comp = adt
-                    .all_fields()
-                    .map(|field| field.ty(tcx, substs))
-                    .filter(|ty| !ty.is_phantom_data())
-                    .map(|ty| {
-                        Box::new(std430_layout(tcx, ty).expect("No layout inside Composition"))
-                    }).collect_vec();
-                Some(Layout::Composition(comp))
-            } else {
-                None
-            }
-        }
+        TypeVariants::TyArray(elem_ty, len) => layout_ty(tcx, elem_ty).map(|elem| LayoutTy::Array {
+            elem: Box::new(elem),
+            len: len.unwrap_usize(tcx) as usize,
+        }),
+        TypeVariants::TyAdt(adt, substs) if adt.is_struct() => adt
+            .all_fields()
+            .map(|field| field.ty(tcx, substs))
+            .filter(|ty| !ty.is_phantom_data())
+            .map(|ty| layout_ty(tcx, ty))
+            .collect::<Option<Vec<_>>>()
+            .map(LayoutTy::Struct),
+        _ => scalar_size(ty).map(LayoutTy::Scalar),
+    }
+}
+
+/// The size in bytes of a scalar that can be stored inside of a block
+fn scalar_size(ty: ty::Ty) -> Option<usize> {
+    match ty.sty {
+        TypeVariants::TyUint(ast::UintTy::U32)
+        | TypeVariants::TyInt(ast::IntTy::I32)
+        | TypeVariants::TyFloat(ast::FloatTy::F32) => Some(4),
         _ => None,
     }
 }
@@ -488,7 +1656,26 @@ impl<'tcx> Entry<'tcx, Buffer<'tcx>> {
             .iter()
             .flat_map(|entry| EntryPoint::buffer_iter(entry))
             .collect();
-        Self::create(set, stx, spirv::StorageClass::StorageBuffer)
+        let storage_class = stx.buffer_storage_class();
+        let entry = Self::create(set, stx, storage_class);
+        if !entry.global_vars.is_empty() {
+            stx.require_buffer_storage_class();
+        }
+        for (buffer, global) in &entry.global_vars {
+            decorate_descriptor(stx, global.var, buffer.set, buffer.binding);
+            match buffer.access {
+                BufferAccess::ReadOnly => {
+                    stx.builder
+                        .decorate(global.var, spirv::Decoration::NonWritable, &[])
+                }
+                BufferAccess::WriteOnly => {
+                    stx.builder
+                        .decorate(global.var, spirv::Decoration::NonReadable, &[])
+                }
+                BufferAccess::ReadWrite => (),
+            }
+        }
+        entry
     }
 
     fn variable_iter<'borrow, 'a>(
@@ -499,10 +1686,8 @@ impl<'tcx> Entry<'tcx, Buffer<'tcx>> {
             .mcx
             .mir()
             .args_iter()
-            .filter_map(move |local| {
-                let ty = entry.mcx.mir().local_decls[local].ty;
-                Buffer::new(entry.mcx.tcx, ty).map(|input| (local, input))
-            }).map(move |(local, uniform)| {
+            .filter_map(move |local| Buffer::new(entry, local).map(|input| (local, input)))
+            .map(move |(local, uniform)| {
                 (
                     local,
                     *self.global_vars.get(&uniform).expect("Entry compute"),
@@ -519,7 +1704,11 @@ impl<'tcx> Entry<'tcx, Uniform<'tcx>> {
             .iter()
             .flat_map(|entry| EntryPoint::uniform_iter(entry))
             .collect();
-        Self::create(set, stx, spirv::StorageClass::Uniform)
+        let entry = Self::create(set, stx, spirv::StorageClass::Uniform);
+        for (uniform, global) in &entry.global_vars {
+            decorate_descriptor(stx, global.var, uniform.set, uniform.binding);
+        }
+        entry
     }
 
     fn variable_iter<'borrow, 'a>(
@@ -530,10 +1719,8 @@ impl<'tcx> Entry<'tcx, Uniform<'tcx>> {
             .mcx
             .mir()
             .args_iter()
-            .filter_map(move |local| {
-                let ty = entry.mcx.mir().local_decls[local].ty;
-                Uniform::new(entry.mcx.tcx, ty).map(|input| (local, input))
-            }).map(move |(local, uniform)| {
+            .filter_map(move |local| Uniform::new(entry, local).map(|input| (local, input)))
+            .map(move |(local, uniform)| {
                 (
                     local,
                     *self.global_vars.get(&uniform).expect("Entry compute"),
@@ -554,10 +1741,14 @@ where
         let global_vars: HashMap<_, _> = set
             .into_iter()
             .map(|global| {
-                let spirv_ty = stx.to_ty_as_ptr(global.ty(), storage_class);
+                let rules = global.layout_rules();
+                let spirv_ty = stx.to_ty_as_ptr_with_layout(global.ty(), storage_class, rules);
                 let var = stx
                     .builder
                     .variable(spirv_ty.word, None, storage_class, None);
+                if let Some(rules) = rules {
+                    stx.layout_ptrs.insert(var, rules);
+                }
                 let global_var = GlobalVar {
                     var,
                     ty: global.ty(),
@@ -579,22 +1770,34 @@ impl<'tcx> Entry<'tcx, Output<'tcx>> {
             .iter()
             .flat_map(|entry| EntryPoint::output_iter(entry))
             .collect();
-        Self::create(set, stx, spirv::StorageClass::Output)
+        let entry = Self::create(set, stx, spirv::StorageClass::Output);
+        for (output, global) in &entry.global_vars {
+            if output.builtin.is_none() {
+                decorate_location(stx, global.var, output.location);
+                decorate_interpolation(stx, global.var, output.interpolation);
+            }
+        }
+        entry
     }
 
+    /// Returns every output of the entry point together with the index of
+    /// the member inside the return type.
     fn variable_iter<'borrow, 'a>(
         &'borrow self,
         entry: &'borrow EntryPoint<'a, 'tcx>,
-    ) -> impl Iterator<Item = (mir::Local, GlobalVar<'tcx>)> + 'borrow {
-        let ty = entry.mcx.mir().return_ty();
-        if ty.is_nil() {
-            return None.into_iter();
-        }
-        let output = Output::new(entry.mcx.tcx, ty).expect("Should be output");
-        Some((
-            mir::Local::new(0),
-            *self.global_vars.get(&output).expect("Entry compute"),
-        )).into_iter()
+    ) -> impl Iterator<Item = (u32, GlobalVar<'tcx>)> + 'borrow {
+        entry
+            .output_tys()
+            .into_iter()
+            .enumerate()
+            .filter_map(move |(idx, ty)| {
+                // Members that are not outputs are reported by `check_outputs`
+                let output = entry.output(idx, ty)?;
+                Some((
+                    idx as u32,
+                    *self.global_vars.get(&output).expect("Entry output"),
+                ))
+            })
     }
 }
 
@@ -610,6 +1813,14 @@ impl<'tcx> Entry<'tcx, Output<'tcx>> {
 pub enum RuntimeArrayIntrinsic {
     Get,
     Store,
+    Len,
+}
+/// `non_uniform` is set for `get_non_uniform` and `store_non_uniform`, whose
+/// index can be different for every invocation.
+#[derive(Debug, Copy, Clone)]
+pub enum DescriptorArrayIntrinsic {
+    Get { non_uniform: bool },
+    Store { non_uniform: bool },
 }
 #[derive(Debug, Copy, Clone)]
 pub enum Intrinsic {
@@ -617,6 +1828,7 @@ pub enum Intrinsic {
     Abort,
     Discard,
     RuntimeArray(RuntimeArrayIntrinsic),
+    DescriptorArray(DescriptorArrayIntrinsic),
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -636,18 +1848,25 @@ pub struct FunctionCx<'b, 'a: 'b, 'tcx: 'a> {
     pub references: HashMap<mir::Place<'tcx>, mir::Place<'tcx>>,
     pub instance_ty: InstanceType,
     pub resume_at: Option<mir::BasicBlock>,
+    /// Outputs of an entry point that returns multiple outputs, with the
+    /// index of the member inside the return value.
+    pub entry_outputs: Vec<(u32, GlobalVar<'tcx>)>,
 }
 
 #[derive(Debug, Copy, Clone)]
 pub enum IntrinsicType<'tcx> {
     TyVec(TyVec<'tcx>),
+    TyMat(TyMat<'tcx>),
     RuntimeArray(RuntimeArray<'tcx>),
+    DescriptorArray(DescriptorArray<'tcx>),
 }
 impl<'tcx> IntrinsicType<'tcx> {
     pub fn from_ty<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
         TyVec::from_ty(tcx, ty)
             .map(IntrinsicType::TyVec)
+            .or_else(|| TyMat::from_ty(tcx, ty).map(IntrinsicType::TyMat))
             .or_else(|| RuntimeArray::from_ty(tcx, ty).map(IntrinsicType::RuntimeArray))
+            .or_else(|| DescriptorArray::from_ty(tcx, ty).map(IntrinsicType::DescriptorArray))
     }
     pub fn contruct_ty<'a>(
         &self,
@@ -661,18 +1880,46 @@ impl<'tcx> IntrinsicType<'tcx> {
                 let ty = cx.builder.type_vector(spirv_ty.word, ty_vec.dim as u32);
                 ty.construct_ty(ty_vec.ty)
             }
+            IntrinsicType::TyMat(ty_mat) => {
+                let spirv_ty = cx.to_ty(ty_mat.ty, storage_class);
+                let ty = cx.builder.type_matrix(spirv_ty.word, ty_mat.cols as u32);
+                ty.construct_ty(ty_mat.ty)
+            }
             IntrinsicType::RuntimeArray(rt_array) => {
                 let spirv_ty = cx.to_ty(rt_array.ty, storage_class);
+                // The `ArrayStride` depends on the layout of the surrounding
+                // block, see `CodegenCx::to_layout_ty`.
                 let ty: spirv::Word = cx.builder.type_runtime_array(spirv_ty.word);
-                let layout = std430_layout(cx.tcx, rt_array.ty).expect("Should have layout");
-                cx.builder.decorate(
-                    ty,
-                    spirv::Decoration::ArrayStride,
-                    &[rspirv::mr::Operand::LiteralInt32(layout.size() as u32)],
-                );
                 Ty::new(ty, rt_array.ty)
             }
-            _ => unimplemented!(),
+            IntrinsicType::DescriptorArray(array) => {
+                // Every element is a block of its own
+                let spirv_ty = cx.to_ty(array.elem, storage_class);
+                let block = cx.builder.type_struct(&[spirv_ty.word]);
+                let decoration = if array.buffer {
+                    cx.buffer_block_decoration()
+                } else {
+                    spirv::Decoration::Block
+                };
+                cx.builder.decorate(block, decoration, &[]);
+                cx.builder.member_decorate(
+                    block,
+                    0,
+                    spirv::Decoration::Offset,
+                    &[rspirv::mr::Operand::LiteralInt32(0)],
+                );
+                let ty = match array.len {
+                    Some(len) => {
+                        let len = cx.constant_u32(len as u32).word;
+                        cx.builder.type_array(block, len)
+                    }
+                    None => {
+                        cx.require_descriptor_indexing();
+                        cx.builder.type_runtime_array(block)
+                    }
+                };
+                Ty::new(ty, array.elem)
+            }
         }
     }
 }
@@ -706,6 +1953,34 @@ impl<'tcx> RuntimeArray<'tcx> {
         }
     }
 }
+/// The data of a `UniformArray` or `BufferArray`, `len` is `None` for a
+/// runtime-sized array.
+#[derive(Debug, Copy, Clone)]
+pub struct DescriptorArray<'tcx> {
+    pub elem: ty::Ty<'tcx>,
+    pub len: Option<u64>,
+    /// `true` for a `BufferArray`
+    pub buffer: bool,
+}
+impl<'tcx> DescriptorArray<'tcx> {
+    pub fn from_ty<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
+        let (fields, buffer) = builtin_fields(tcx, ty, "UniformArray")
+            .map(|fields| (fields, false))
+            .or_else(|| builtin_fields(tcx, ty, "BufferArray").map(|fields| (fields, true)))?;
+        match fields[0].sty {
+            TypeVariants::TyArray(elem, len) => Some(DescriptorArray {
+                elem,
+                len: Some(len.unwrap_usize(tcx)),
+                buffer,
+            }),
+            _ => RuntimeArray::from_ty(tcx, fields[0]).map(|rt_array| DescriptorArray {
+                elem: rt_array.ty,
+                len: None,
+                buffer,
+            }),
+        }
+    }
+}
 #[derive(Debug, Copy, Clone)]
 pub struct TyVec<'tcx> {
     pub ty: ty::Ty<'tcx>,
@@ -734,31 +2009,85 @@ impl<'tcx> TyVec<'tcx> {
         }
     }
 }
-#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+/// A column major matrix, `ty` is the type of a column.
+#[derive(Debug, Copy, Clone)]
+pub struct TyMat<'tcx> {
+    pub ty: ty::Ty<'tcx>,
+    pub cols: usize,
+}
+impl<'tcx> TyMat<'tcx> {
+    pub fn from_ty<'a>(tcx: TyCtxt<'a, 'tcx, 'tcx>, ty: ty::Ty<'tcx>) -> Option<Self> {
+        if let TypeVariants::TyAdt(adt, substs) = ty.sty {
+            let attrs = tcx.get_attrs(adt.did);
+            let cols = extract_attr(&attrs, "spirv", |s| match s {
+                "Mat2" => Some(2),
+                "Mat3" => Some(3),
+                "Mat4" => Some(4),
+                _ => None,
+            }).get(0)
+            .cloned()?;
+            assert!(adt.is_struct(), "A Mat should be a struct");
+            let field = adt
+                .all_fields()
+                .nth(0)
+                .expect("A Mat should have at least one field");
+            let field_ty = field.ty(tcx, substs);
+            Some(TyMat { ty: field_ty, cols })
+        } else {
+            None
+        }
+    }
+}
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
 pub enum IntrinsicEntry {
     Vertex,
     Fragment,
     Compute,
 }
 
+/// Visits every nested item, so that `#[spirv(fragment, early_fragment_tests)]`
+/// is the same as writing both attributes separately.
 pub fn extract_attr_impl<R, F>(
     meta_item: &syntax::ast::MetaItem,
     keywords: &[&str],
     f: &F,
-) -> Option<R>
+) -> Vec<R>
 where
     F: Fn(&str) -> Option<R>,
 {
     if keywords.is_empty() {
-        return f(&*meta_item.name().as_str());
+        return f(&*meta_item.name().as_str()).into_iter().collect();
     }
-    let meta_item_list = &meta_item.meta_item_list()?[0];
-    if meta_item.name() == keywords[0] {
-        if let syntax::ast::NestedMetaItemKind::MetaItem(ref meta) = meta_item_list.node {
-            return extract_attr_impl(meta, &keywords[1..], f);
-        }
+    if meta_item.name() != keywords[0] {
+        return Vec::new();
     }
-    None
+    meta_item
+        .meta_item_list()
+        .into_iter()
+        .flat_map(|list| list.iter())
+        .filter_map(|nested| match nested.node {
+            syntax::ast::NestedMetaItemKind::MetaItem(ref meta) => Some(meta),
+            _ => None,
+        }).flat_map(|meta| extract_attr_impl(meta, &keywords[1..], f))
+        .collect()
+}
+/// Returns the value of a name value pair like `#[spirv(name = "main")]`.
+pub fn extract_attr_value(
+    attrs: &[syntax::ast::Attribute],
+    keyword: &str,
+    name: &str,
+) -> Option<String> {
+    attrs
+        .iter()
+        .filter_map(|attr| attr.meta())
+        .filter(|meta| meta.name() == keyword)
+        .flat_map(|meta| meta.meta_item_list().map(|list| list.to_vec()).unwrap_or_default())
+        .filter_map(|nested| match nested.node {
+            syntax::ast::NestedMetaItemKind::MetaItem(ref meta) if meta.name() == name => {
+                meta.value_str().map(|value| value.to_string())
+            }
+            _ => None,
+        }).nth(0)
 }
 // TODO: Better API
 pub fn extract_attr<R, F>(attrs: &[syntax::ast::Attribute], keyword: &str, f: F) -> Vec<R>
@@ -767,10 +2096,9 @@ where
 {
     attrs
         .iter()
-        .filter_map(|attr| {
-            attr.meta()
-                .and_then(|meta| extract_attr_impl(&meta, &[keyword], &f))
-        }).collect::<Vec<_>>()
+        .filter_map(|attr| attr.meta())
+        .flat_map(|meta| extract_attr_impl(&meta, &[keyword], &f))
+        .collect::<Vec<_>>()
 }
 
 pub enum FunctionCall {
@@ -783,10 +2111,12 @@ pub fn find_ref_functions<'borrow, 'a, 'tcx>(
 ) -> impl Iterator<Item = &'borrow MirContext<'a, 'tcx>> {
     items.iter().filter(|mcx| is_ptr(mcx.mir.return_ty()))
 }
-pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<MonoItem<'tcx>>) {
-    //struct_span_err!(tcx.sess, DUMMY_SP, E1337, "Test not allowed").emit();
-
-    let mut ctx = CodegenCx::new(tcx);
+pub fn trans_spirv<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    items: &'a FxHashSet<MonoItem<'tcx>>,
+    options: &Options,
+) {
+    let mut ctx = CodegenCx::new(tcx, options.target_env);
 
     let mut instances: Vec<MirContext> = items
         .iter()
@@ -803,17 +2133,13 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
             }
             None
         }).collect();
-    let mut file =
-        std::fs::File::create("/home/maik/projects/rlsl/issues/mir/mir.dot").expect("graph");
-    for (id, mcx) in instances.iter().enumerate() {
-        let graph = graph::PetMir::from_mir(mcx.mir);
-        graph.export(&mut file);
-    }
-    let mut mir_before =
-        std::fs::File::create("/home/maik/projects/rlsl/issues/mir/mir_before_orig.dot")
-            .expect("graph");
-    for mcx in &instances {
-        rustc_mir::util::write_mir_fn_graphviz(tcx, mcx.def_id, &mcx.mir, &mut mir_before);
+    if options.emits(EmitKind::MirDot) {
+        write_output(tcx, options, EmitKind::MirDot, |file| {
+            for mcx in &instances {
+                rustc_mir::util::write_mir_fn_graphviz(tcx, mcx.def_id, &mcx.mir, file)?;
+            }
+            Ok(())
+        });
     }
     items.iter().for_each(|item| {
         use spirv::GLOp::*;
@@ -855,6 +2181,19 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
         let intrinsic = extract_attr(&attrs, "spirv", |s| match s {
             "runtime_array_get" => Some(Intrinsic::RuntimeArray(RuntimeArrayIntrinsic::Get)),
             "runtime_array_store" => Some(Intrinsic::RuntimeArray(RuntimeArrayIntrinsic::Store)),
+            "runtime_array_len" => Some(Intrinsic::RuntimeArray(RuntimeArrayIntrinsic::Len)),
+            "descriptor_array_get" => Some(Intrinsic::DescriptorArray(
+                DescriptorArrayIntrinsic::Get { non_uniform: false },
+            )),
+            "descriptor_array_get_non_uniform" => Some(Intrinsic::DescriptorArray(
+                DescriptorArrayIntrinsic::Get { non_uniform: true },
+            )),
+            "descriptor_array_store" => Some(Intrinsic::DescriptorArray(
+                DescriptorArrayIntrinsic::Store { non_uniform: false },
+            )),
+            "descriptor_array_store_non_uniform" => Some(Intrinsic::DescriptorArray(
+                DescriptorArrayIntrinsic::Store { non_uniform: true },
+            )),
             _ => None,
         }).first()
         .cloned();
@@ -863,7 +2202,7 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
         }
     });
 
-    if TyErrorVisitor::has_error(&instances) {
+    if validate::has_unsupported(&instances, &ctx.intrinsic_fns) {
         return;
     }
     use rustc_mir::transform::inline::Inline;
@@ -888,28 +2227,29 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
         .filter(|mcx| mcx.def_id != entry_fn && tcx.lang_items().start_fn() != Some(mcx.def_id))
         .map(|mcx| context::SpirvMir::from_mir(mcx))
         .collect();
-    //let mut mir_after =
-    //    std::fs::File::create("/home/maik/projects/rlsl/issues/mir/mir_after.dot").expect("graph");
-    //for (id, mcx) in spirv_instances.iter().enumerate() {
-    //    let graph = graph::PetMir::from_mir(&mcx.mir);
-    //    //println!("{:#?}", loops);
-    //    graph.export(&mut mir_after);
-    //}
-    // let mut mir_after_orig =
-    //     std::fs::File::create("/home/maik/projects/rlsl/issues/mir/mir_after_orig.dot")
-    //         .expect("graph");
-    // for mcx in &spirv_instances {
-    //     rustc_mir::util::write_mir_fn_graphviz(tcx, mcx.def_id, &mcx.mir, &mut mir_after_orig);
-    // }
-    // spirv_instances.iter().for_each(|scx| {
-    //     println!("{:#?}", scx.def_id);
-    //     println!("{:#?}", scx.mir);
-    // });
-    let mut file =
-        std::fs::File::create("/home/maik/projects/rlsl/issues/mir/mir_after.dot").expect("graph");
-    for (id, mcx) in spirv_instances.iter().enumerate() {
-        let graph = graph::PetMir::from_mir(&mcx.mir);
-        graph.export(&mut file);
+
+    let call_graph = validate::CallGraph::new(
+        tcx,
+        spirv_instances
+            .iter()
+            .map(|scx| ((scx.def_id, scx.substs), &scx.mir)),
+    );
+    let entry_points: Vec<_> = spirv_instances
+        .iter()
+        .map(|scx| (scx.def_id, scx.substs))
+        .filter(|&(def_id, _)| validate::is_entry_point(tcx, def_id))
+        .collect();
+    // The inliner below would not terminate on a recursive function
+    if validate::has_recursion(tcx, &call_graph, &entry_points) {
+        return;
+    }
+    if options.emits(EmitKind::SpirvMirDot) {
+        write_output(tcx, options, EmitKind::SpirvMirDot, |file| {
+            for mcx in &spirv_instances {
+                graph::PetMir::from_mir(&mcx.mir).export(file)?;
+            }
+            Ok(())
+        });
     }
 
     // Finds functions that return a reference
@@ -952,33 +2292,43 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
     });
 
 
-    for mcx in &spirv_instances {
-        let id = ctx.builder.id();
-        ctx.forward_fns
-            .insert((mcx.def_id, mcx.substs), Function(id));
-    }
-
     //println!("instances {:#?}", spirv_instances.iter().map(|m|m.def_id).collect::<Vec<_>>());
-    let (entry_instances, fn_instances): (Vec<_>, Vec<_>) =
+    let (entry_instances, fn_instances): (Vec<Vec<_>>, Vec<_>) =
         spirv_instances.into_iter().partition_map(|mcx| {
-            let attrs = tcx.get_attrs(mcx.def_id);
-            let entry = extract_attr(&attrs, "spirv", |s| match s {
-                "vertex" => Some(IntrinsicEntry::Vertex),
-                "fragment" => Some(IntrinsicEntry::Fragment),
-                "compute" => Some(IntrinsicEntry::Compute),
-                _ => None,
-            }).iter()
-            .nth(0)
-            .map(|&entry_type| EntryPoint {
-                mcx: mcx.clone(),
-                entry_type,
-            });
-            if let Some(entry_point) = entry {
-                Either::Left(entry_point)
-            } else {
+            let entry_points = EntryPoint::from_mir(&mcx);
+            if entry_points.is_empty() {
                 Either::Right(mcx)
+            } else {
+                Either::Left(entry_points)
             }
         });
+    let entry_instances: Vec<_> = entry_instances.into_iter().flat_map(|e| e).collect();
+    // Entry points get their ids in `trans_entry`
+    for mcx in &fn_instances {
+        let id = ctx.builder.id();
+        ctx.forward_fns
+            .insert((mcx.def_id, mcx.substs), Function(id));
+    }
+    let functions: HashMap<_, _> = fn_instances
+        .iter()
+        .map(|scx| ((scx.def_id, scx.substs), &scx.mir))
+        .collect();
+    entry_instances.iter().for_each(|entry| {
+        entry.check_stage();
+        entry.check_outputs();
+        entry.check_params();
+        entry.check_runtime_arrays();
+        entry.check_buffer_writes(&ctx.intrinsic_fns, &functions);
+        entry.check_interpolation();
+        entry.check_locations();
+        entry.check_execution_modes();
+    });
+    check_entry_names(&entry_instances);
+    check_interface(&entry_instances);
+    tcx.sess.abort_if_errors();
+    if options.check {
+        return;
+    }
     let entry_input = Entry::input(&entry_instances, &mut ctx);
     let entry_output = Entry::output(&entry_instances, &mut ctx);
     let entry_descriptor = Entry::uniform(&entry_instances, &mut ctx);
@@ -997,17 +2347,62 @@ pub fn trans_spirv<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, items: &'a FxHashSet<M
     fn_instances.iter().for_each(|mcx| {
         FunctionCx::trans_fn(mcx, &mut ctx);
     });
-    std::fs::create_dir_all(".shaders").expect("create dir");
-    let file_name = tcx
+    // Unsupported code is reported during codegen, we don't write a module
+    // that contains placeholders.
+    tcx.sess.abort_if_errors();
+    if options.emits(EmitKind::Reflection) {
+        let reflection = reflect::reflect(&entry_instances, &ctx);
+        write_output(tcx, options, EmitKind::Reflection, |file| reflection.write(file));
+    }
+    let fn_paths = ctx.fn_paths();
+    let mut module = ctx.build_module();
+    capabilities::declare(tcx.sess, &mut module, options.target_env);
+    tcx.sess.abort_if_errors();
+    // An invalid module is a bug in rlsl, debug builds catch it before a
+    // driver does.
+    if cfg!(debug_assertions) {
+        if let Err(errors) = rlsl_validate::validate(&module) {
+            for error in errors {
+                tcx.sess.err(&format!("rlsl generated an invalid module: {}", error));
+            }
+        }
+    }
+    // The assembly of an invalid module is still written to find the
+    // offending instruction, but the module itself is not.
+    if options.emits(EmitKind::SpirvAsm) {
+        write_output(tcx, options, EmitKind::SpirvAsm, |file| {
+            context::write_module_asm(&module, &fn_paths, file)
+        });
+    }
+    tcx.sess.abort_if_errors();
+    if options.emits(EmitKind::Spirv) {
+        write_output(tcx, options, EmitKind::Spirv, |file| context::write_module(&module, file));
+    }
+}
+
+/// Creates the file of an output in `options.out_dir` and reports an error if
+/// it can not be written.
+fn write_output<'a, 'tcx, F>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    options: &Options,
+    kind: EmitKind,
+    f: F,
+) where
+    F: FnOnce(&mut std::fs::File) -> std::io::Result<()>,
+{
+    let source_name = tcx
         .sess
         .local_crate_source_file
         .as_ref()
         .and_then(|p| p.file_stem())
-        .map(Path::new)
-        .map(|p| Path::new(".shaders").join(p.with_extension("spv")))
-        .expect("file name");
-    let module = ctx.build_module();
-    context::save_module(&module, file_name);
+        .map_or("main".into(), |p| p.to_string_lossy());
+    let path = options.output_path(&source_name, kind);
+    let result = std::fs::create_dir_all(&options.out_dir)
+        .and_then(|_| std::fs::File::create(&path))
+        .and_then(|mut file| f(&mut file));
+    if let Err(err) = result {
+        tcx.sess.err(&format!("Unable to write `{}`: {}", path.display(), err));
+    }
 }
 
 impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
@@ -1033,7 +2428,14 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
                     let expr = self.scx.constant(value);
                     Operand::new(ty, OperandVariant::Value(expr))
                 }
-                ref rest => unimplemented!("{:?}", rest),
+                mir::Literal::Promoted { .. } => {
+                    self.scx.unsupported(
+                        "A reference to a constant",
+                        "Assign the constant to a local with `let` first",
+                    );
+                    let expr = self.scx.undef(ty);
+                    Operand::new(ty, OperandVariant::Value(expr))
+                }
             },
         }
     }
@@ -1117,19 +2519,16 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
         use mir::visit::Visitor;
         let def_id = entry_point.mcx.def_id;
         let mir = entry_point.mcx.mir();
-        // TODO: Fix properly
-        if entry_point.entry_type == IntrinsicEntry::Vertex {
-            let first_local = mir::Local::new(1);
-            let per_vertex = scx.get_per_vertex(mir.local_decls[first_local].ty);
-        }
-        if entry_point.entry_type == IntrinsicEntry::Fragment {
-            let first_local = mir::Local::new(1);
-            let per_fragment = scx.get_per_fragment(mir.local_decls[first_local].ty);
-        }
-        if entry_point.entry_type == IntrinsicEntry::Compute {
-            let first_local = mir::Local::new(1);
-            let per_fragment = scx.get_compute(mir.local_decls[first_local].ty);
-        }
+        // The builtin variables need to be created outside of the function
+        let builtin = entry_point.builtin_local().map(|local| {
+            let ty = mir.local_decls[local].ty;
+            let variable = match entry_point.entry_type {
+                IntrinsicEntry::Vertex => scx.get_per_vertex(ty),
+                IntrinsicEntry::Fragment => scx.get_per_fragment(ty),
+                IntrinsicEntry::Compute => scx.get_compute(ty),
+            };
+            (local, variable)
+        });
         let void = scx.tcx.mk_nil();
         let fn_sig = scx.tcx.mk_fn_sig(
             [].into_iter(),
@@ -1141,19 +2540,19 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
         let void_spirv = scx.to_ty_fn(void);
         let fn_ty = scx.tcx.mk_fn_ptr(Binder::bind(fn_sig));
         let fn_ty_spirv = scx.to_ty_fn(fn_ty);
-        let forward_fn = scx
-            .forward_fns
-            .get(&(def_id, entry_point.mcx.substs))
-            .map(|f| f.0)
-            .expect("forward");
+        // The same Rust function can be used by several stages, which is why
+        // every entry point gets its own SPIR-V function.
         let spirv_function = scx
             .builder
             .begin_function(
                 void_spirv.word,
-                Some(forward_fn),
+                None,
                 spirv::FunctionControl::empty(),
                 fn_ty_spirv.word,
             ).expect("begin fn");
+        scx.name_from_def_id(def_id, spirv_function);
+        let path = ty::Instance::new(def_id, entry_point.mcx.substs).to_string();
+        scx.entry_fn_paths.insert(spirv_function, path);
         scx.builder.begin_basic_block(None).expect("block");
         // entry_point.descriptor_iter().for_each(|input| {
         //     if let TypeVariants::TyAdt(adt, substs) = input.ty.sty {
@@ -1165,41 +2564,9 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
         //     }
         // });
         let inputs_iter = entry_input.variable_iter(&entry_point);
-        let output_iter = entry_output.variable_iter(&entry_point);
         let descriptor_iter = entry_descriptor.variable_iter(&entry_point);
         let buffer_iter = entry_buffer.variable_iter(&entry_point);
-        entry_descriptor
-            .global_vars
-            .iter()
-            .for_each(|(uniform, global)| {
-                scx.builder.decorate(
-                    global.var,
-                    spirv::Decoration::DescriptorSet,
-                    &[rspirv::mr::Operand::LiteralInt32(uniform.set)],
-                );
-                scx.builder.decorate(
-                    global.var,
-                    spirv::Decoration::Binding,
-                    &[rspirv::mr::Operand::LiteralInt32(uniform.binding)],
-                );
-            });
-        entry_buffer
-            .global_vars
-            .iter()
-            .for_each(|(buffer, global)| {
-                scx.builder.decorate(
-                    global.var,
-                    spirv::Decoration::DescriptorSet,
-                    &[rspirv::mr::Operand::LiteralInt32(buffer.set)],
-                );
-                scx.builder.decorate(
-                    global.var,
-                    spirv::Decoration::Binding,
-                    &[rspirv::mr::Operand::LiteralInt32(buffer.binding)],
-                );
-            });
         let mut variable_map: HashMap<mir::Local, Variable<'tcx>> = inputs_iter
-            .chain(output_iter)
             .chain(descriptor_iter)
             .chain(buffer_iter)
             .map(|(local, global)| {
@@ -1213,56 +2580,58 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
                 )
             }).collect();
 
-        // Only add the per vertex variable in the vertex shader
-        if entry_point.entry_type == IntrinsicEntry::Vertex {
-            let first_local = mir::Local::new(1);
-            let per_vertex = scx.get_per_vertex(mir.local_decls[first_local].ty);
-            variable_map.insert(first_local, per_vertex);
+        if let Some((local, variable)) = builtin {
+            variable_map.insert(local, variable);
         }
-        if entry_point.entry_type == IntrinsicEntry::Fragment {
-            let first_local = mir::Local::new(1);
-            let per_fragment = scx.get_per_fragment(mir.local_decls[first_local].ty);
-            variable_map.insert(first_local, per_fragment);
-        }
-        if entry_point.entry_type == IntrinsicEntry::Compute {
-            let first_local = mir::Local::new(1);
-            let compute = scx.get_compute(mir.local_decls[first_local].ty);
-            variable_map.insert(first_local, compute);
-        }
-        let outputs = entry_output
-            .variable_iter(&entry_point)
-            .map(|(_, gv)| gv)
-            .collect_vec();
-        if let Some(output_var) = outputs.first() {
-            // Insert the return variable
-            variable_map.insert(
-                mir::Local::new(0),
-                Variable {
-                    word: output_var.var,
-                    ty: mir.return_ty(),
-                    storage_class: output_var.storage_class,
-                },
+        let outputs = entry_output.variable_iter(&entry_point).collect_vec();
+        let entry_outputs = if entry_point.has_multiple_outputs() {
+            // The return value is a tuple or a struct of outputs. We store it
+            // in a local variable and copy every member into its output when
+            // the entry point returns.
+            let ret_var = Variable::alloca(scx, mir.return_ty(), spirv::StorageClass::Function);
+            variable_map.insert(mir::Local::new(0), ret_var);
+            outputs.clone()
+        } else {
+            if let Some(&(_, output_var)) = outputs.first() {
+                // Insert the return variable
+                variable_map.insert(
+                    mir::Local::new(0),
+                    Variable {
+                        word: output_var.var,
+                        ty: mir.return_ty(),
+                        storage_class: output_var.storage_class,
+                    },
+                );
+            }
+            Vec::new()
+        };
+
+        {
+            let mut fx = FunctionCx::new(
+                InstanceType::Entry(entry_point.entry_type),
+                &entry_point.mcx,
+                variable_map,
+                scx,
             );
+            fx.entry_outputs = entry_outputs;
+            fx.visit_mir(&entry_point.mcx.mir);
         }
-
-        let raw_builtin = variable_map
-            .get(&mir::Local::new(1))
-            .as_ref()
-            .expect("")
-            .word;
-        FunctionCx::new(
-            InstanceType::Entry(entry_point.entry_type),
-            &entry_point.mcx,
-            variable_map,
-            scx,
-        ).visit_mir(&entry_point.mcx.mir);
         let mut inputs_raw = entry_input
             .variable_iter(&entry_point)
             .map(|(_, gv)| gv.var)
             .collect_vec();
-        inputs_raw.extend(outputs.iter().map(|gv| gv.var));
-        inputs_raw.push(raw_builtin);
-        let name = entry_point.mcx.tcx.item_name(def_id);
+        inputs_raw.extend(outputs.iter().map(|(_, gv)| gv.var));
+        inputs_raw.extend(builtin.map(|(_, variable)| variable.word));
+        // Since SPIR-V 1.4 the descriptors are part of the interface as well
+        if scx.target_env.interface_has_all_globals() {
+            inputs_raw.extend(
+                entry_descriptor
+                    .variable_iter(&entry_point)
+                    .chain(entry_buffer.variable_iter(&entry_point))
+                    .map(|(_, gv)| gv.var),
+            );
+        }
+        let name = entry_point.name.clone();
         let model = match entry_point.entry_type {
             IntrinsicEntry::Vertex => spirv::ExecutionModel::Vertex,
             IntrinsicEntry::Fragment => spirv::ExecutionModel::Fragment,
@@ -1270,21 +2639,9 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
         };
         scx.builder
             .entry_point(model, spirv_function, name, inputs_raw);
-        match entry_point.entry_type {
-            IntrinsicEntry::Vertex | IntrinsicEntry::Fragment => {
-                scx.builder.execution_mode(
-                    spirv_function,
-                    spirv::ExecutionMode::OriginUpperLeft,
-                    &[],
-                );
-            }
-            IntrinsicEntry::Compute => {
-                scx.builder.execution_mode(
-                    spirv_function,
-                    spirv::ExecutionMode::LocalSize,
-                    &[1, 1, 1],
-                );
-            }
+        for (mode, operands) in entry_point.execution_modes() {
+            scx.builder
+                .execution_mode(spirv_function, mode, &operands);
         }
     }
     pub fn to_ty(&mut self, ty: ty::Ty<'tcx>, storage_class: spirv::StorageClass) -> Ty<'tcx> {
@@ -1372,6 +2729,7 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
             references: HashMap::new(),
             merge_blocks: HashMap::new(),
             resume_at: None,
+            entry_outputs: Vec::new(),
         };
         visitor
     }
@@ -1455,6 +2813,9 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
         self.super_ty(ty);
         //println!("{:?}", ty);
     }
+    fn visit_source_info(&mut self, source_info: &mir::SourceInfo) {
+        self.scx.span = source_info.span;
+    }
     fn visit_statement(
         &mut self,
         block: mir::BasicBlock,
@@ -1542,7 +2903,13 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
             if is_ptr(lvalue_ty) && is_ptr(ty) {
                 let place = match operand {
                     mir::Operand::Copy(place) | mir::Operand::Move(place) => place,
-                    _ => unimplemented!(),
+                    mir::Operand::Constant(_) => {
+                        self.scx.unsupported(
+                            "A reference to a constant",
+                            "Assign the constant to a local with `let` first",
+                        );
+                        return;
+                    }
                 };
 
                 // TODO: Insert local into references
@@ -1596,11 +2963,7 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                         .expect("composite"),
                 )
             }
-            &mir::Rvalue::Discriminant(ref lvalue) => {
-                let local = match lvalue {
-                    &mir::Place::Local(local) => local,
-                    _ => panic!("Should be local"),
-                };
+            &mir::Rvalue::Discriminant(mir::Place::Local(local)) => {
                 let var = *self.vars.get(&local).expect("local");
                 let ty = self.mcx.mir().local_decls[local].ty;
                 let ty = self.mcx.monomorphize(&ty);
@@ -1643,14 +3006,33 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                         .expect("op not");
                     Value::new(not)
                 }
-                _ => unimplemented!("unary op"),
+                mir::UnOp::Neg => {
+                    self.scx
+                        .unsupported("Negation with `-`", "Subtract the value from zero instead");
+                    self.scx.undef(lvalue_ty)
+                }
             },
-            &mir::Rvalue::Cast(_, ref _op, _ty) => {
-                //let op_ty = op.ty(&self.mcx.mir().local_decls, self.mcx.tcx);
-                unimplemented!("cast {:?} {:?} {:?}", _op, lvalue_ty, _ty)
+            rest => {
+                let (feature, help) = match rest {
+                    mir::Rvalue::Discriminant(_) => (
+                        "Matching on an enum that is not a local variable",
+                        "Move the enum into a local with `let` before matching on it",
+                    ),
+                    mir::Rvalue::Cast(..) => ("A cast with `as`", "Use values of the same type"),
+                    mir::Rvalue::Repeat(..) => (
+                        "An array repeat expression like `[x; N]`",
+                        "Write out every element of the array",
+                    ),
+                    mir::Rvalue::Len(..) => ("A slice", "Use an array with a fixed length"),
+                    mir::Rvalue::NullaryOp(..) => (
+                        "`box` and `size_of`",
+                        "Values can only live on the stack in a shader",
+                    ),
+                    _ => ("This expression", "Rewrite it with locals and function calls"),
+                };
+                self.scx.unsupported(feature, help);
+                self.scx.undef(lvalue_ty)
             }
-
-            rest => unimplemented!("{:?}", rest),
         };
 
         let variable = Variable::access_chain(self, lvalue);
@@ -1780,6 +3162,7 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
             &mir::TerminatorKind::Return => {
                 // If we are inside an entry, we just return void
                 if self.instance_ty != InstanceType::Fn {
+                    self.store_entry_outputs();
                     return self.scx.builder.ret().expect("ret");
                 } else {
                     match mir.return_ty().sty {
@@ -1802,8 +3185,7 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                 }
             }
             &mir::TerminatorKind::Goto { target } => {
-                let label = self.label_blocks.get(&target).expect("no goto label");
-                self.scx.builder.branch(label.0).expect("branch");
+                self.branch(target);
             }
             &mir::TerminatorKind::SwitchInt {
                 ref discr,
@@ -1859,7 +3241,13 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                 let fn_ty = func.ty(self.mcx.mir(), self.mcx.tcx);
                 let (def_id, substs) = match fn_ty.sty {
                     TypeVariants::TyFnDef(def_id, ref substs) => (def_id, substs),
-                    _ => panic!("Not a function"),
+                    _ => {
+                        self.scx.unsupported(
+                            "Calling a function pointer",
+                            "Call the function directly or use generics",
+                        );
+                        return self.branch_after_call(destination);
+                    }
                 };
 
                 let tcx = self.mcx.tcx;
@@ -1876,13 +3264,20 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                     def_id,
                     &mono_substs,
                 ).expect("resolve instance call");
-                let fn_call = self
+                let fn_call = match self
                     .scx
                     .get_function_call(resolve_instance.def_id(), resolve_instance.substs)
-                    .expect(&format!(
-                        "function call for {:?}",
-                        resolve_instance.def_id()
-                    ));
+                {
+                    Some(fn_call) => fn_call,
+                    None => {
+                        let path = tcx.item_path_str(resolve_instance.def_id());
+                        self.scx.unsupported(
+                            &format!("Calling `{}`", path),
+                            "Only functions with a body in Rust can be called from a shader",
+                        );
+                        return self.branch_after_call(destination);
+                    }
+                };
                 // Split the rust-call tupled arguments off.
                 let (first_args, untuple) = if abi == Abi::RustCall && !args.is_empty() {
                     let (tup, args) = args.split_last().unwrap();
@@ -1934,7 +3329,14 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                             });
                             arg_operand_loads.extend(tuple_iter);
                         }
-                        _ => panic!("tup"),
+                        _ => {
+                            self.scx.unsupported(
+                                "Spreading arguments that are not a tuple",
+                                "pass the arguments of the closure as a tuple",
+                            );
+                            let undef = self.scx.undef(ty);
+                            arg_operand_loads.push(undef.word);
+                        }
                     }
                 }
                 let spirv_fn_call = match fn_call {
@@ -1962,7 +3364,7 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                         }
                         Intrinsic::Abort => {
                             self.scx.builder.unreachable().expect("unreachable");
-                            None
+                            return;
                         }
                         Intrinsic::Discard => {
                             self.scx.builder.kill().expect("unreachable");
@@ -1970,9 +3372,11 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                         }
                         Intrinsic::RuntimeArray(runtime_array) => match runtime_array {
                             RuntimeArrayIntrinsic::Store => {
-                                let ty = args[2].ty(local_decls, self.scx.tcx);
+                                let ty = self.mcx.monomorphize(&args[2].ty(local_decls, tcx));
+                                let storage_class = self.scx.buffer_storage_class();
+                                let rules = self.scx.layout_ptrs.get(&arg_operand_loads[0]).cloned();
                                 let spirv_ptr_ty =
-                                    self.to_ty_as_ptr(ty, spirv::StorageClass::StorageBuffer);
+                                    self.scx.to_ty_as_ptr_with_layout(ty, storage_class, rules);
                                 let access_chain = self
                                     .scx
                                     .builder
@@ -1982,15 +3386,24 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                                         arg_operand_loads[0],
                                         &arg_operand_loads[1..2],
                                     ).expect("access chain");
+                                let value = self.scx.convert_layout(
+                                    arg_operand_loads[2],
+                                    ty,
+                                    storage_class,
+                                    None,
+                                    rules,
+                                );
                                 self.scx
                                     .builder
-                                    .store(access_chain, arg_operand_loads[2], None, &[])
+                                    .store(access_chain, value, None, &[])
                                     .expect("store");
                                 None
                             }
                             RuntimeArrayIntrinsic::Get => {
+                                let storage_class = self.scx.buffer_storage_class();
+                                let rules = self.scx.layout_ptrs.get(&arg_operand_loads[0]).cloned();
                                 let spirv_ptr_ty =
-                                    self.to_ty_as_ptr(ret_ty, spirv::StorageClass::StorageBuffer);
+                                    self.scx.to_ty_as_ptr_with_layout(ret_ty, storage_class, rules);
                                 let access_chain = self
                                     .scx
                                     .builder
@@ -2000,14 +3413,141 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                                         arg_operand_loads[0],
                                         &arg_operand_loads[1..],
                                     ).expect("access chain");
+                                let load_ty =
+                                    self.scx.to_ty_with_layout(ret_ty, storage_class, rules);
                                 let load = self
                                     .scx
                                     .builder
-                                    .load(spirv_ty.word, None, access_chain, None, &[])
+                                    .load(load_ty.word, None, access_chain, None, &[])
                                     .expect("Load access_chain");
-                                Some(load)
+                                Some(self.scx.convert_layout(load, ret_ty, storage_class, rules, None))
+                            }
+                            RuntimeArrayIntrinsic::Len => {
+                                // `OpArrayLength` needs the block that contains the
+                                // runtime array and the index of the member.
+                                let place = match args[0] {
+                                    mir::Operand::Copy(ref place)
+                                    | mir::Operand::Move(ref place) => self
+                                        .references
+                                        .get(place)
+                                        .cloned()
+                                        .unwrap_or_else(|| place.clone()),
+                                    _ => unreachable!("A RuntimeArray can not be a constant"),
+                                };
+                                let len = match place {
+                                    mir::Place::Projection(ref proj) => match proj.elem {
+                                        mir::ProjectionElem::Field(field, _) => {
+                                            let block = Variable::access_chain(self, &proj.base);
+                                            let len = self
+                                                .scx
+                                                .builder
+                                                .array_length(
+                                                    spirv_ty.word,
+                                                    None,
+                                                    block.word,
+                                                    field.index() as u32,
+                                                ).expect("array length");
+                                            Some(len)
+                                        }
+                                        _ => None,
+                                    },
+                                    _ => None,
+                                };
+                                if len.is_some() {
+                                    len
+                                } else {
+                                    self.scx.span =
+                                        mir.basic_blocks()[block].terminator().source_info.span;
+                                    self.scx.unsupported(
+                                        "`len` of a `RuntimeArray` that is not a member of a buffer",
+                                        "call `len` on the trailing `RuntimeArray` field of the buffer",
+                                    );
+                                    Some(self.scx.undef(ret_ty).word)
+                                }
                             }
                         },
+                        Intrinsic::DescriptorArray(descriptor_array) => {
+                            let array_ty = args[0].ty(local_decls, tcx);
+                            let array_ty = remove_ptr_ty(self.mcx.monomorphize(&array_ty));
+                            let storage_class =
+                                if get_builtin_adt(tcx, array_ty, "UniformArray").is_some() {
+                                    spirv::StorageClass::Uniform
+                                } else {
+                                    self.scx.buffer_storage_class()
+                                };
+                            let (elem_ty, non_uniform) = match descriptor_array {
+                                DescriptorArrayIntrinsic::Get { non_uniform } => {
+                                    (ret_ty, non_uniform)
+                                }
+                                DescriptorArrayIntrinsic::Store { non_uniform } => (
+                                    self.mcx.monomorphize(&args[2].ty(local_decls, tcx)),
+                                    non_uniform,
+                                ),
+                            };
+                            let rules = self.scx.layout_ptrs.get(&arg_operand_loads[0]).cloned();
+                            let spirv_ptr_ty =
+                                self.scx.to_ty_as_ptr_with_layout(elem_ty, storage_class, rules);
+                            // Index into the array and then into the data of the block
+                            let data_index = self.constant_u32(0).word;
+                            let access_chain = self
+                                .scx
+                                .builder
+                                .access_chain(
+                                    spirv_ptr_ty.word,
+                                    None,
+                                    arg_operand_loads[0],
+                                    &[arg_operand_loads[1], data_index],
+                                ).expect("access chain");
+                            // A dynamic index is only non-uniform if the user asks
+                            // for it, otherwise it has to be dynamically uniform
+                            if non_uniform {
+                                self.scx.require_descriptor_indexing();
+                                self.scx.builder.decorate(
+                                    access_chain,
+                                    spirv::Decoration::NonUniformEXT,
+                                    &[],
+                                );
+                            }
+                            match descriptor_array {
+                                DescriptorArrayIntrinsic::Get { .. } => {
+                                    let load_ty =
+                                        self.scx.to_ty_with_layout(elem_ty, storage_class, rules);
+                                    let load = self
+                                        .scx
+                                        .builder
+                                        .load(load_ty.word, None, access_chain, None, &[])
+                                        .expect("Load access_chain");
+                                    if non_uniform {
+                                        self.scx.builder.decorate(
+                                            load,
+                                            spirv::Decoration::NonUniformEXT,
+                                            &[],
+                                        );
+                                    }
+                                    Some(self.scx.convert_layout(
+                                        load,
+                                        elem_ty,
+                                        storage_class,
+                                        rules,
+                                        None,
+                                    ))
+                                }
+                                DescriptorArrayIntrinsic::Store { .. } => {
+                                    let value = self.scx.convert_layout(
+                                        arg_operand_loads[2],
+                                        elem_ty,
+                                        storage_class,
+                                        None,
+                                        rules,
+                                    );
+                                    self.scx
+                                        .builder
+                                        .store(access_chain, value, None, &[])
+                                        .expect("store");
+                                    None
+                                }
+                            }
+                        }
                     },
                 };
                 // only write op store if the result is not nil
@@ -2022,18 +3562,19 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                                     .store(var.word, spirv_fn_call, None, &[])
                                     .expect("store");
                             }
-                            rest => unimplemented!("{:?}", rest),
+                            _ => {
+                                self.scx.unsupported(
+                                    "Storing the result of a call in a field",
+                                    "Assign the result to a local with `let` first",
+                                );
+                            }
                         };
                     }
                 }
-                let destination = destination.as_ref().expect("Fn call is diverging");
-                let &(_, target_block) = destination;
-                let target_label = self.label_blocks.get(&target_block).expect("no label");
-                self.scx.builder.branch(target_label.0).expect("label");
+                self.branch_after_call(destination);
             }
             &mir::TerminatorKind::Assert { target, .. } => {
-                let target_label = self.label_blocks.get(&target).expect("no label");
-                self.scx.builder.branch(target_label.0).expect("label");
+                self.branch(target);
             }
             &mir::TerminatorKind::Drop {
                 ref location,
@@ -2044,23 +3585,37 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
                 // self.scx.builder.branch(target_label.0).expect("label");
                 if let Some(unwind) = unwind {
                     self.resume_at = Some(target);
-                    let target_label = self.label_blocks.get(&unwind).expect("no label");
-                    self.scx.builder.branch(target_label.0).expect("label");
+                    self.branch(unwind);
                 } else {
-                    let target_label = self.label_blocks.get(&target).expect("no label");
-                    self.scx.builder.branch(target_label.0).expect("label");
+                    self.branch(target);
                 }
             }
-            &mir::TerminatorKind::Resume => {
-                let resume_at = self.resume_at.expect("Resume");
-                let target_label = self.label_blocks.get(&resume_at).expect("no label");
-                self.scx.builder.branch(target_label.0).expect("label");
-                //self.scx.builder.unreachable();
-            }
+            &mir::TerminatorKind::Resume => match self.resume_at {
+                Some(resume_at) => self.branch(resume_at),
+                None => {
+                    self.scx.builder.unreachable().expect("unreachable");
+                }
+            },
             mir::TerminatorKind::Unreachable => {
                 self.scx.builder.unreachable();
             }
-            rest => unimplemented!("{:?}", rest),
+            rest => {
+                let (feature, help) = match rest {
+                    mir::TerminatorKind::Yield { .. } | mir::TerminatorKind::GeneratorDrop => {
+                        ("A generator", "Use a loop instead")
+                    }
+                    mir::TerminatorKind::DropAndReplace { .. } => (
+                        "Replacing a value that implements `Drop`",
+                        "Remove the `Drop` implementation",
+                    ),
+                    _ => (
+                        "This control flow",
+                        "Use `if`, `match`, `loop` or `while` instead",
+                    ),
+                };
+                self.scx.unsupported(feature, help);
+                self.scx.builder.unreachable().expect("unreachable");
+            }
         };
         self.super_terminator_kind(block, kind, location);
     }
@@ -2070,6 +3625,68 @@ impl<'b, 'a, 'tcx> rustc::mir::visit::Visitor<'tcx> for FunctionCx<'b, 'a, 'tcx>
 pub enum SpirvRvalue {}
 
 impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
+    /// Ends the current block with a branch to `target`.
+    pub fn branch(&mut self, target: mir::BasicBlock) {
+        match self.label_blocks.get(&target) {
+            Some(label) => {
+                self.scx.builder.branch(label.0).expect("branch");
+            }
+            None => {
+                struct_span_err!(
+                    self.scx.tcx.sess,
+                    self.scx.span,
+                    E1337,
+                    "Missing block `{:?}`",
+                    target
+                ).note("This is a bug in rlsl")
+                .emit();
+                self.scx.builder.unreachable().expect("unreachable");
+            }
+        }
+    }
+
+    /// Continues after a call. A call that never returns ends the block.
+    pub fn branch_after_call(
+        &mut self,
+        destination: &Option<(mir::Place<'tcx>, mir::BasicBlock)>,
+    ) {
+        match *destination {
+            Some((_, target)) => self.branch(target),
+            None => {
+                self.scx.builder.unreachable().expect("unreachable");
+            }
+        }
+    }
+
+    /// Copies every member of the return value into its output variable.
+    pub fn store_entry_outputs(&mut self) {
+        if self.entry_outputs.is_empty() {
+            return;
+        }
+        let ret_var = *self
+            .vars
+            .get(&mir::Local::new(0))
+            .expect("return variable");
+        for (index, global) in self.entry_outputs.clone() {
+            let spirv_ty = self.scx.to_ty(global.ty, global.storage_class);
+            let spirv_ptr_ty = self.scx.to_ty_as_ptr(global.ty, ret_var.storage_class);
+            let spirv_index = self.constant_u32(index).word;
+            let member = self
+                .scx
+                .builder
+                .access_chain(spirv_ptr_ty.word, None, ret_var.word, &[spirv_index])
+                .expect("access chain");
+            let load = self
+                .scx
+                .builder
+                .load(spirv_ty.word, None, member, None, &[])
+                .expect("load");
+            self.scx
+                .builder
+                .store(global.var, load, None, &[])
+                .expect("store");
+        }
+    }
     pub fn header(&mut self, block: mir::BasicBlock) {
         use rustc_data_structures::control_flow_graph::ControlFlowGraph;
         let cfg = self.mcx.control_flow.get(&block).expect("merge block");
@@ -2162,7 +3779,7 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
                             .expect("less than");
                         self.scx.bool_to_u32(lt)
                     }
-                    rest => unimplemented!("{:?}", rest),
+                    _ => self.unsupported_binary_op(op, ty, return_ty),
                 }
             }
             ty::TypeVariants::TyFloat(_) => match op {
@@ -2238,11 +3855,49 @@ impl<'b, 'a, 'tcx> FunctionCx<'b, 'a, 'tcx> {
                         .expect("not equal");
                     self.scx.bool_to_u32(ne)
                 }
-                rest => unimplemented!("{:?}", rest),
+                _ => self.unsupported_binary_op(op, ty, return_ty),
             },
-            _ => unimplemented!("ops"),
+            _ => self.unsupported_binary_op(op, ty, return_ty),
         }
     }
+
+    fn unsupported_binary_op(
+        &mut self,
+        op: mir::BinOp,
+        ty: ty::Ty<'tcx>,
+        return_ty: ty::Ty<'tcx>,
+    ) -> Value {
+        use rustc::mir::BinOp::*;
+        let symbol = match op {
+            Add => "+",
+            Sub => "-",
+            Mul => "*",
+            Div => "/",
+            Rem => "%",
+            BitXor => "^",
+            BitAnd => "&",
+            BitOr => "|",
+            Shl => "<<",
+            Shr => ">>",
+            Eq => "==",
+            Lt => "<",
+            Le => "<=",
+            Ne => "!=",
+            Ge => ">=",
+            Gt => ">",
+            Offset => "offset",
+        };
+        let help = match ty.sty {
+            TypeVariants::TyUint(_) => "The supported operators for `u32` are `+`, `*` and `<`",
+            TypeVariants::TyFloat(_) => {
+                "The supported operators for `f32` are `+`, `-`, `*`, `/`, `<`, `>` and `!=`"
+            }
+            _ => "Operators are only supported for `u32` and `f32`",
+        };
+        self.scx
+            .unsupported(&format!("The operator `{}` for `{}`", symbol, ty), help);
+        self.scx.undef(return_ty)
+    }
 }
 
 pub fn remove_unwind<'tcx>(mir: &mut mir::Mir<'tcx>) {
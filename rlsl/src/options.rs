@@ -0,0 +1,262 @@
+//! The options of rlsl, that are passed on the command line next to the
+//! options of rustc.
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// The outputs that can be selected with `--rlsl-emit=spirv,mir-dot`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EmitKind {
+    /// The SPIR-V module, `.spv`
+    Spirv,
+    /// The SPIR-V module as assembly, `.spvasm`. Every function is annotated
+    /// with its Rust path.
+    SpirvAsm,
+    /// The MIR of every collected function as graphviz, `.mir.dot`
+    MirDot,
+    /// The control flow graph of the MIR after it has been prepared for
+    /// SPIR-V, `.spirv-mir.dot`
+    SpirvMirDot,
+    /// The reflection of the entry points, `.reflect.json`
+    Reflection,
+}
+
+impl EmitKind {
+    pub fn from_str(s: &str) -> Option<EmitKind> {
+        match s {
+            "spirv" => Some(EmitKind::Spirv),
+            "spirv-asm" => Some(EmitKind::SpirvAsm),
+            "mir-dot" => Some(EmitKind::MirDot),
+            "spirv-mir-dot" => Some(EmitKind::SpirvMirDot),
+            "reflection" => Some(EmitKind::Reflection),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match *self {
+            EmitKind::Spirv => "spv",
+            EmitKind::SpirvAsm => "spvasm",
+            EmitKind::MirDot => "mir.dot",
+            EmitKind::SpirvMirDot => "spirv-mir.dot",
+            EmitKind::Reflection => "reflect.json",
+        }
+    }
+}
+
+/// The environment the module is compiled for, selected with `--target-env`.
+/// It decides the version of the module and how buffers are declared.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TargetEnv {
+    Spv1_0,
+    Spv1_1,
+    Spv1_2,
+    Spv1_3,
+    Spv1_4,
+    Spv1_5,
+    Vulkan1_0,
+    Vulkan1_1,
+    Vulkan1_2,
+    OpenGl4_5,
+}
+
+impl fmt::Display for TargetEnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            TargetEnv::Spv1_0 => "spv1.0",
+            TargetEnv::Spv1_1 => "spv1.1",
+            TargetEnv::Spv1_2 => "spv1.2",
+            TargetEnv::Spv1_3 => "spv1.3",
+            TargetEnv::Spv1_4 => "spv1.4",
+            TargetEnv::Spv1_5 => "spv1.5",
+            TargetEnv::Vulkan1_0 => "vulkan1.0",
+            TargetEnv::Vulkan1_1 => "vulkan1.1",
+            TargetEnv::Vulkan1_2 => "vulkan1.2",
+            TargetEnv::OpenGl4_5 => "opengl4.5",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl TargetEnv {
+    pub fn from_str(s: &str) -> Option<TargetEnv> {
+        match s {
+            "spv1.0" => Some(TargetEnv::Spv1_0),
+            "spv1.1" => Some(TargetEnv::Spv1_1),
+            "spv1.2" => Some(TargetEnv::Spv1_2),
+            "spv1.3" => Some(TargetEnv::Spv1_3),
+            "spv1.4" => Some(TargetEnv::Spv1_4),
+            "spv1.5" => Some(TargetEnv::Spv1_5),
+            "vulkan1.0" => Some(TargetEnv::Vulkan1_0),
+            "vulkan1.1" => Some(TargetEnv::Vulkan1_1),
+            "vulkan1.2" => Some(TargetEnv::Vulkan1_2),
+            "opengl4.5" => Some(TargetEnv::OpenGl4_5),
+            _ => None,
+        }
+    }
+
+    /// The version in the header of the module, `(major, minor)`
+    pub fn spirv_version(&self) -> (u8, u8) {
+        match *self {
+            TargetEnv::Spv1_0 | TargetEnv::Vulkan1_0 | TargetEnv::OpenGl4_5 => (1, 0),
+            TargetEnv::Spv1_1 => (1, 1),
+            TargetEnv::Spv1_2 => (1, 2),
+            TargetEnv::Spv1_3 | TargetEnv::Vulkan1_1 => (1, 3),
+            TargetEnv::Spv1_4 => (1, 4),
+            TargetEnv::Spv1_5 | TargetEnv::Vulkan1_2 => (1, 5),
+        }
+    }
+
+    pub fn is_vulkan(&self) -> bool {
+        match *self {
+            TargetEnv::Vulkan1_0 | TargetEnv::Vulkan1_1 | TargetEnv::Vulkan1_2 => true,
+            _ => false,
+        }
+    }
+
+    /// Buffers are declared in the `StorageBuffer` storage class, otherwise
+    /// with `Uniform` and a `BufferBlock`. The storage class is core since
+    /// 1.3, Vulkan 1.0 uses it with `SPV_KHR_storage_buffer_storage_class`.
+    pub fn has_storage_buffer(&self) -> bool {
+        *self == TargetEnv::Vulkan1_0 || self.spirv_version() >= (1, 3)
+    }
+
+    /// `SPV_KHR_storage_buffer_storage_class` has to be declared to use the
+    /// `StorageBuffer` storage class
+    pub fn needs_storage_buffer_extension(&self) -> bool {
+        self.has_storage_buffer() && self.spirv_version() < (1, 3)
+    }
+
+    /// Since 1.4 the interface of `OpEntryPoint` lists every global variable
+    /// that the entry point uses, not only the inputs and outputs.
+    pub fn interface_has_all_globals(&self) -> bool {
+        self.spirv_version() >= (1, 4)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Only runs the checks and the validation, no module is written. Set
+    /// with `--check` or by `cargo check`.
+    pub check: bool,
+    /// Set with `--rlsl-emit`, defaults to `spirv,reflection`
+    pub emit: Vec<EmitKind>,
+    /// The directory of the outputs. Either `--rlsl-out-dir`, the directory
+    /// of `-o`, or `shaders` in the target directory of cargo. Falls back to
+    /// `.shaders` in the current directory.
+    pub out_dir: PathBuf,
+    /// The file stem of `-o`, otherwise the name of the source file is used
+    pub out_name: Option<String>,
+    /// Set with `--target-env`, defaults to `vulkan1.0`
+    pub target_env: TargetEnv,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            check: false,
+            emit: vec![EmitKind::Spirv, EmitKind::Reflection],
+            out_dir: PathBuf::from(".shaders"),
+            out_name: None,
+            target_env: TargetEnv::Vulkan1_0,
+        }
+    }
+}
+
+impl Options {
+    /// Parses and removes the options of rlsl from `args`, the remaining
+    /// arguments are passed to rustc. Returns an error for an unknown emit
+    /// kind or target environment.
+    pub fn from_args(args: &mut Vec<String>) -> Result<Options, String> {
+        let mut options = Options::default();
+        options.check = args.iter().any(|arg| arg == "--check");
+        // `cargo check` only asks for the metadata
+        if let Some(kinds) = rustc_value(args, "--emit") {
+            let kinds: Vec<_> = kinds
+                .split(',')
+                .map(|kind| kind.split('=').next().unwrap_or(kind))
+                .collect();
+            options.check |= kinds.contains(&"metadata") && !kinds.contains(&"link");
+        }
+        if let Some(kinds) = rustc_value(args, "--rlsl-emit") {
+            options.emit = kinds
+                .split(',')
+                .map(|kind| {
+                    EmitKind::from_str(kind).ok_or_else(|| {
+                        format!(
+                            "Unknown emit kind `{}`, expected `spirv`, `spirv-asm`, `mir-dot`, `spirv-mir-dot` or `reflection`",
+                            kind
+                        )
+                    })
+                }).collect::<Result<_, _>>()?;
+        }
+        if let Some(target_env) = rustc_value(args, "--target-env") {
+            options.target_env = TargetEnv::from_str(&target_env).ok_or_else(|| {
+                format!(
+                    "Unknown target environment `{}`, expected one of `spv1.0` to `spv1.5`, `vulkan1.0`, `vulkan1.1`, `vulkan1.2` or `opengl4.5`",
+                    target_env
+                )
+            })?;
+        }
+        if let Some(out_file) = rustc_value(args, "-o") {
+            let out_file = Path::new(&out_file);
+            options.out_dir = out_file.parent().map_or(PathBuf::new(), Path::to_path_buf);
+            options.out_name = out_file
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned());
+        } else if let Some(out_dir) = rustc_value(args, "--out-dir") {
+            // cargo builds into `target/debug/deps`
+            let out_dir = PathBuf::from(out_dir);
+            options.out_dir = if out_dir.ends_with("deps") {
+                out_dir.with_file_name("shaders")
+            } else {
+                out_dir
+            };
+        }
+        if let Some(out_dir) = rustc_value(args, "--rlsl-out-dir") {
+            options.out_dir = PathBuf::from(out_dir);
+        }
+        remove_option(args, "--check", false);
+        remove_option(args, "--rlsl-emit", true);
+        remove_option(args, "--rlsl-out-dir", true);
+        remove_option(args, "--target-env", true);
+        Ok(options)
+    }
+
+    pub fn emits(&self, kind: EmitKind) -> bool {
+        self.emit.contains(&kind)
+    }
+
+    /// The path of an output, like `target/debug/shaders/circle.spv`
+    pub fn output_path(&self, source_name: &str, kind: EmitKind) -> PathBuf {
+        let name = self.out_name.as_ref().map_or(source_name, |name| name.as_str());
+        self.out_dir.join(format!("{}.{}", name, kind.extension()))
+    }
+}
+
+/// The value of an option, either `--name=value` or `--name value`. The last
+/// one wins, like in rustc.
+fn rustc_value(args: &[String], name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    args.iter()
+        .enumerate()
+        .filter_map(|(idx, arg)| {
+            if arg.starts_with(&prefix) {
+                Some(arg[prefix.len()..].to_string())
+            } else if arg == name {
+                args.get(idx + 1).cloned()
+            } else {
+                None
+            }
+        }).last()
+}
+
+fn remove_option(args: &mut Vec<String>, name: &str, has_value: bool) {
+    let prefix = format!("{}=", name);
+    while let Some(idx) = args.iter().position(|arg| arg == name || arg.starts_with(&prefix)) {
+        let with_value = has_value && args[idx] == name && idx + 1 < args.len();
+        args.remove(idx);
+        if with_value {
+            args.remove(idx);
+        }
+    }
+}
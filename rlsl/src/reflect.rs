@@ -0,0 +1,222 @@
+//! Builds the reflection that is written as `.reflect.json` next to every
+//! module.
+use context::CodegenCx;
+use rlsl_reflect as reflect;
+use rustc::ty::{self, TyCtxt, TypeVariants};
+use spirv;
+use {
+    Buffer, DescriptorArray, EntryPoint, Input, IntrinsicEntry, IntrinsicType, Layout, LayoutRules,
+    Uniform,
+};
+
+pub fn reflect<'a, 'tcx>(
+    entry_points: &[EntryPoint<'a, 'tcx>],
+    cx: &CodegenCx<'a, 'tcx>,
+) -> reflect::Reflection {
+    reflect::Reflection {
+        entry_points: entry_points.iter().map(reflect_entry).collect(),
+        device_extensions: cx
+            .device_extensions
+            .iter()
+            .map(|extension| extension.to_string())
+            .collect(),
+    }
+}
+
+fn reflect_entry<'a, 'tcx>(entry: &EntryPoint<'a, 'tcx>) -> reflect::EntryPoint {
+    let tcx = entry.mcx.tcx;
+    let stage = match entry.entry_type {
+        IntrinsicEntry::Vertex => reflect::Stage::Vertex,
+        IntrinsicEntry::Fragment => reflect::Stage::Fragment,
+        IntrinsicEntry::Compute => reflect::Stage::Compute,
+    };
+    let mut reflection = reflect::EntryPoint::new(entry.name.clone(), stage);
+    reflection.workgroup_size = entry
+        .execution_modes()
+        .into_iter()
+        .find(|&(mode, _)| mode == spirv::ExecutionMode::LocalSize)
+        .map(|(_, size)| [size[0], size[1], size[2]]);
+
+    let mir = entry.mcx.mir();
+    for local in entry.args() {
+        let decl = &mir.local_decls[local];
+        let name = decl.name.map(|name| name.to_string());
+        if let Some(input) = Input::new(entry, local) {
+            for (location, ty) in input.locations(tcx) {
+                reflection.inputs.push(reflect::InterfaceVariable {
+                    name: name.clone(),
+                    location: Some(location),
+                    builtin: None,
+                    ty: reflect_ty(tcx, ty, None),
+                });
+            }
+        } else if let Some(uniform) = Uniform::new(entry, local) {
+            reflection.uniforms.push(descriptor(
+                tcx,
+                name,
+                uniform.ty,
+                uniform.set,
+                uniform.binding,
+                uniform.rules,
+                uniform.count,
+            ));
+        } else if let Some(buffer) = Buffer::new(entry, local) {
+            reflection.buffers.push(descriptor(
+                tcx,
+                name,
+                buffer.ty,
+                buffer.set,
+                buffer.binding,
+                buffer.rules,
+                buffer.count,
+            ));
+        }
+    }
+
+    let outputs = output_names(entry).into_iter().zip(entry.output_tys());
+    for (idx, (name, ty)) in outputs.enumerate() {
+        let output = match entry.output(idx, ty) {
+            Some(output) => output,
+            None => continue,
+        };
+        if let Some(builtin) = output.builtin {
+            reflection.outputs.push(reflect::InterfaceVariable {
+                name,
+                location: None,
+                builtin: Some(format!("{:?}", builtin)),
+                ty: reflect_ty(tcx, ::data_ty(tcx, output.ty), None),
+            });
+            continue;
+        }
+        for (location, ty) in output.locations(tcx) {
+            reflection.outputs.push(reflect::InterfaceVariable {
+                name: name.clone(),
+                location: Some(location),
+                builtin: None,
+                ty: reflect_ty(tcx, ty, None),
+            });
+        }
+    }
+    reflection
+}
+
+/// The names of the fields of `#[spirv(FragmentOutputs)]`, in the same order
+/// as `EntryPoint::output_tys`.
+fn output_names<'a, 'tcx>(entry: &EntryPoint<'a, 'tcx>) -> Vec<Option<String>> {
+    match entry.mcx.mir().return_ty().sty {
+        TypeVariants::TyAdt(adt, _) if entry.has_multiple_outputs() => adt
+            .all_fields()
+            .map(|field| Some(field.ident.as_str().to_string()))
+            .collect(),
+        _ => entry.output_tys().iter().map(|_| None).collect(),
+    }
+}
+
+fn descriptor<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    name: Option<String>,
+    ty: ty::Ty<'tcx>,
+    set: u32,
+    binding: u32,
+    rules: LayoutRules,
+    count: reflect::DescriptorCount,
+) -> reflect::Descriptor {
+    // For arrays of descriptors the type of a single element is reflected
+    let data_ty = match DescriptorArray::from_ty(tcx, ty) {
+        Some(array) => array.elem,
+        None => ::data_ty(tcx, ty),
+    };
+    let layout = ::ty_layout(tcx, data_ty, rules);
+    reflect::Descriptor {
+        name,
+        set,
+        binding,
+        layout: Some(rules.name().to_string()),
+        count,
+        ty: reflect_ty(tcx, data_ty, layout.as_ref()),
+    }
+}
+
+/// Describes `ty`. If `layout` is set, the offsets, sizes and strides of the
+/// type inside of a block are included.
+pub fn reflect_ty<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    ty: ty::Ty<'tcx>,
+    layout: Option<&Layout>,
+) -> reflect::Type {
+    use rlsl_reflect::Type;
+    let stride = layout
+        .and_then(|layout| layout.array_stride())
+        .map(|stride| stride as u32);
+    let elem_layout = layout.and_then(|layout| layout.elem());
+    if let Some(intrinsic) = IntrinsicType::from_ty(tcx, ty) {
+        return match intrinsic {
+            IntrinsicType::TyVec(ty_vec) => Type::Vector {
+                component: Box::new(reflect_ty(tcx, ty_vec.ty, None)),
+                count: ty_vec.dim as u32,
+            },
+            IntrinsicType::TyMat(ty_mat) => Type::Matrix {
+                column: Box::new(reflect_ty(tcx, ty_mat.ty, None)),
+                columns: ty_mat.cols as u32,
+            },
+            IntrinsicType::RuntimeArray(rt_array) => Type::Array {
+                element: Box::new(reflect_ty(tcx, rt_array.ty, elem_layout)),
+                length: None,
+                stride,
+            },
+            // Descriptors are reflected with their `count`
+            IntrinsicType::DescriptorArray(array) => Type::Array {
+                element: Box::new(reflect_ty(tcx, array.elem, None)),
+                length: array.len.map(|len| len as u32),
+                stride: None,
+            },
+        };
+    }
+    match ty.sty {
+        TypeVariants::TyBool => Type::Bool,
+        TypeVariants::TyUint(uint_ty) => Type::Int {
+            width: uint_ty.bit_width().unwrap_or(64) as u32,
+            signed: false,
+        },
+        TypeVariants::TyInt(int_ty) => Type::Int {
+            width: int_ty.bit_width().unwrap_or(64) as u32,
+            signed: true,
+        },
+        TypeVariants::TyFloat(float_ty) => Type::Float {
+            width: float_ty.bit_width() as u32,
+        },
+        TypeVariants::TyArray(elem_ty, len) => Type::Array {
+            element: Box::new(reflect_ty(tcx, elem_ty, elem_layout)),
+            length: Some(len.unwrap_usize(tcx) as u32),
+            stride,
+        },
+        TypeVariants::TyAdt(adt, substs) if adt.is_struct() => {
+            let members = adt
+                .all_fields()
+                .filter(|field| !field.ty(tcx, substs).is_phantom_data())
+                .enumerate()
+                .map(|(idx, field)| {
+                    let member_layout = layout.and_then(|layout| layout.members().get(idx));
+                    reflect::Member {
+                        name: Some(field.ident.as_str().to_string()),
+                        offset: layout
+                            .and_then(|layout| layout.offsets().get(idx).cloned())
+                            .map(|offset| offset as u32),
+                        size: member_layout.map(|layout| layout.size as u32),
+                        matrix_stride: member_layout
+                            .and_then(|layout| layout.matrix_stride())
+                            .map(|stride| stride as u32),
+                        ty: reflect_ty(tcx, field.ty(tcx, substs), member_layout),
+                    }
+                }).collect();
+            Type::Struct {
+                name: Some(tcx.item_path_str(adt.did)),
+                size: layout.map(|layout| layout.size as u32),
+                members,
+            }
+        }
+        _ => Type::Opaque {
+            name: ty.to_string(),
+        },
+    }
+}
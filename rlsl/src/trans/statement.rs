@@ -21,7 +21,13 @@ pub fn trans_statement<'scope, 'a: 'scope, 'tcx: 'a>(
 
         let spirv_var = match place {
             &mir::Place::Local(local) => *visitor.vars.get(&local).expect("Local"),
-            _ => panic!("Should be local"),
+            _ => {
+                visitor.scx.unsupported(
+                    "Constructing an enum inside of a field",
+                    "Construct the enum in a local with `let` first",
+                );
+                return;
+            }
         };
 
         let discr_index = e.index;
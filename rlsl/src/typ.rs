@@ -162,6 +162,18 @@ impl<'tcx> Variable<'tcx> {
             Variable::access_chain(fx, &place)
         });
         indices.reverse();
+        // The data of the buffer is the block itself, see `unwrapped_block`
+        if ::unwrapped_block(fx.scx.tcx, variable.ty).is_some() && !indices.is_empty() {
+            indices.remove(0);
+            if indices.is_empty() {
+                let lvalue_ty = fx.mcx.monomorphize(&lvalue_ty);
+                return Variable {
+                    word: variable.word,
+                    ty: ::remove_ptr_ty(lvalue_ty),
+                    storage_class: variable.storage_class,
+                };
+            }
+        }
         if indices.is_empty() {
             variable
         } else {
@@ -170,7 +182,14 @@ impl<'tcx> Variable<'tcx> {
                 .to_ty(fx.scx.tcx);
             let lvalue_ty = fx.mcx.monomorphize(&lvalue_ty);
             let lvalue_ty = ::remove_ptr_ty(lvalue_ty);
-            let spirv_ty_ptr = fx.scx.to_ty_as_ptr(lvalue_ty, variable.storage_class);
+            // An access chain into a block points to the type with the layout
+            // of the block
+            let rules = fx.scx.layout_ptrs.get(&variable.word).cloned();
+            let spirv_ty_ptr = fx.scx.to_ty_as_ptr_with_layout(
+                lvalue_ty,
+                variable.storage_class,
+                rules,
+            );
             // let indices: Vec<_> = indices
             //     .iter()
             //     .map(|&i| fx.constant_u32(i as u32).word)
@@ -180,6 +199,9 @@ impl<'tcx> Variable<'tcx> {
                 .builder
                 .access_chain(spirv_ty_ptr.word, None, variable.word, &indices)
                 .expect("access_chain");
+            if let Some(rules) = rules {
+                fx.scx.layout_ptrs.insert(access, rules);
+            }
             Variable {
                 word: access,
                 ty: lvalue_ty,
@@ -188,17 +210,20 @@ impl<'tcx> Variable<'tcx> {
         }
     }
     pub fn load<'a>(&self, cx: &mut CodegenCx<'a, 'tcx>) -> Value {
-        let spirv_ty = cx.to_ty(self.ty, self.storage_class);
+        let rules = cx.layout_ptrs.get(&self.word).cloned();
+        let spirv_ty = cx.to_ty_with_layout(self.ty, self.storage_class, rules);
         let load = cx
             .builder
             .load(spirv_ty.word, None, self.word, None, &[])
             .expect("Load variable");
-        Value::new(load)
+        Value::new(cx.convert_layout(load, self.ty, self.storage_class, rules, None))
     }
 
     pub fn store<'a>(&self, cx: &mut CodegenCx<'a, 'tcx>, value: Value) {
+        let rules = cx.layout_ptrs.get(&self.word).cloned();
+        let value = cx.convert_layout(value.word, self.ty, self.storage_class, None, rules);
         cx.builder
-            .store(self.word, value.word, None, &[])
+            .store(self.word, value, None, &[])
             .expect("Store variable");
     }
 
@@ -0,0 +1,393 @@
+//! Finds code that can not be expressed in SPIR-V before codegen runs, so
+//! that every unsupported construct is reported at once.
+use context::MirContext;
+use rustc::hir::def_id::DefId;
+use rustc::mir;
+use rustc::mir::visit::{TyContext, Visitor};
+use rustc::ty::subst::Substs;
+use rustc::ty::{self, Instance, TyCtxt, TypeVariants};
+use std::collections::{HashMap, HashSet, VecDeque};
+use syntax::ast::FloatTy;
+use syntax::attr::{self, InlineAttr};
+use syntax_pos::Span;
+use Intrinsic;
+
+/// A monomorphized function
+pub type Node<'tcx> = (DefId, &'tcx Substs<'tcx>);
+
+/// The calls between the collected functions. The callees are resolved after
+/// monomorphization, so that a call to a trait method or a closure points to
+/// the function that is actually called.
+pub struct CallGraph<'tcx> {
+    pub callees: HashMap<Node<'tcx>, Vec<(Node<'tcx>, Span)>>,
+}
+
+impl<'tcx> CallGraph<'tcx> {
+    pub fn new<'m, 'a, I>(tcx: TyCtxt<'a, 'tcx, 'tcx>, functions: I) -> Self
+    where
+        I: IntoIterator<Item = (Node<'tcx>, &'m mir::Mir<'tcx>)>,
+        'tcx: 'm,
+    {
+        let callees = functions
+            .into_iter()
+            .map(|((def_id, substs), mir)| {
+                let calls = mir
+                    .basic_blocks()
+                    .iter()
+                    .filter_map(|data| {
+                        let terminator = data.terminator();
+                        match terminator.kind {
+                            mir::TerminatorKind::Call { ref func, .. } => {
+                                resolve_call(tcx, substs, mir, func)
+                                    .map(|callee| (callee, terminator.source_info.span))
+                            }
+                            _ => None,
+                        }
+                    }).collect();
+                ((def_id, substs), calls)
+            }).collect();
+        CallGraph { callees }
+    }
+
+    fn calls(&self, node: Node<'tcx>) -> &[(Node<'tcx>, Span)] {
+        self.callees.get(&node).map_or(&[], |calls| &calls[..])
+    }
+
+    /// The shortest call path from one of the `roots` to every reachable
+    /// function, including the function itself.
+    pub fn paths_from(&self, roots: &[Node<'tcx>]) -> HashMap<Node<'tcx>, Vec<Node<'tcx>>> {
+        let mut paths: HashMap<Node<'tcx>, Vec<Node<'tcx>>> = HashMap::new();
+        let mut queue: VecDeque<Node<'tcx>> = roots.iter().cloned().collect();
+        for &root in roots {
+            paths.insert(root, vec![root]);
+        }
+        while let Some(node) = queue.pop_front() {
+            for &(callee, _) in self.calls(node) {
+                if !paths.contains_key(&callee) {
+                    let mut path = paths[&node].clone();
+                    path.push(callee);
+                    paths.insert(callee, path);
+                    queue.push_back(callee);
+                }
+            }
+        }
+        paths
+    }
+}
+
+/// The function that is called by `func` inside of `mir`, after `substs` are
+/// applied.
+pub fn resolve_call<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    substs: &'tcx Substs<'tcx>,
+    mir: &mir::Mir<'tcx>,
+    func: &mir::Operand<'tcx>,
+) -> Option<Node<'tcx>> {
+    let fn_ty = tcx.subst_and_normalize_erasing_regions(
+        substs,
+        ty::ParamEnv::reveal_all(),
+        &func.ty(mir, tcx),
+    );
+    resolve_fn(tcx, fn_ty)
+}
+
+fn resolve_fn<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, fn_ty: ty::Ty<'tcx>) -> Option<Node<'tcx>> {
+    match fn_ty.sty {
+        TypeVariants::TyFnDef(def_id, substs) => {
+            let instance = Instance::resolve(tcx, ty::ParamEnv::reveal_all(), def_id, substs);
+            Some(instance.map_or((def_id, substs), |instance| {
+                (instance.def_id(), instance.substs)
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// SPIR-V does not allow recursion. Reports every cycle in the call graph
+/// that is reachable from one of the `entry_points`, returns true if there
+/// was one.
+pub fn has_recursion<'a, 'tcx>(
+    tcx: TyCtxt<'a, 'tcx, 'tcx>,
+    call_graph: &CallGraph<'tcx>,
+    entry_points: &[Node<'tcx>],
+) -> bool {
+    let mut finder = CycleFinder {
+        call_graph,
+        stack: Vec::new(),
+        finished: HashSet::new(),
+        cycles: Vec::new(),
+    };
+    for &entry_point in entry_points {
+        finder.visit(entry_point);
+    }
+    for cycle in &finder.cycles {
+        let (first, _, _) = cycle[0];
+        let mut err = struct_span_err!(
+            tcx.sess,
+            tcx.def_span(first.0),
+            E1337,
+            "`{}` is recursive, which is not supported by rlsl",
+            tcx.item_path_str(first.0)
+        );
+        for &(caller, callee, span) in cycle {
+            err.span_note(
+                span,
+                &format!(
+                    "`{}` calls `{}`",
+                    tcx.item_path_str(caller.0),
+                    tcx.item_path_str(callee.0)
+                ),
+            );
+        }
+        err.help("SPIR-V does not allow recursion, use a loop instead");
+        err.emit();
+    }
+    !finder.cycles.is_empty()
+}
+
+/// A depth first search that remembers the current call path
+struct CycleFinder<'g, 'tcx: 'g> {
+    call_graph: &'g CallGraph<'tcx>,
+    stack: Vec<Node<'tcx>>,
+    finished: HashSet<Node<'tcx>>,
+    /// Every call in a cycle, as caller, callee and the span of the call
+    cycles: Vec<Vec<(Node<'tcx>, Node<'tcx>, Span)>>,
+}
+
+impl<'g, 'tcx> CycleFinder<'g, 'tcx> {
+    fn visit(&mut self, node: Node<'tcx>) {
+        if self.finished.contains(&node) {
+            return;
+        }
+        self.stack.push(node);
+        for &(callee, span) in self.call_graph.calls(node) {
+            match self.stack.iter().position(|&n| n == callee) {
+                Some(start) => {
+                    let mut cycle: Vec<_> = self.stack[start..]
+                        .windows(2)
+                        .map(|pair| {
+                            let &(_, span) = self
+                                .call_graph
+                                .calls(pair[0])
+                                .iter()
+                                .find(|&&(callee, _)| callee == pair[1])
+                                .expect("call");
+                            (pair[0], pair[1], span)
+                        }).collect();
+                    cycle.push((node, callee, span));
+                    self.cycles.push(cycle);
+                }
+                None => self.visit(callee),
+            }
+        }
+        self.stack.pop();
+        self.finished.insert(node);
+    }
+}
+
+pub fn is_entry_point<'a, 'tcx>(tcx: TyCtxt<'a, 'tcx, 'tcx>, def_id: DefId) -> bool {
+    let attrs = tcx.get_attrs(def_id);
+    !::extract_attr(&attrs, "spirv", |s| match s {
+        "vertex" | "fragment" | "compute" => Some(()),
+        _ => None,
+    }).is_empty()
+}
+
+/// Reports everything that rlsl can not translate in the functions that are
+/// reachable from an entry point, returns true if there was an error or if a
+/// function did not type check. Intrinsics are skipped because their body is
+/// never translated.
+pub fn has_unsupported(mcxs: &[MirContext], intrinsic_fns: &HashMap<DefId, Intrinsic>) -> bool {
+    let tcx = match mcxs.first() {
+        Some(mcx) => mcx.tcx,
+        None => return false,
+    };
+    let errors = tcx.sess.err_count();
+    // Type errors were already reported by rustc, but codegen can not run
+    let has_ty_error = mcxs.iter().any(|mcx| {
+        let mut finder = TyErrorFinder { has_error: false };
+        finder.visit_mir(mcx.mir());
+        finder.has_error
+    });
+    let call_graph = CallGraph::new(tcx, mcxs.iter().map(|mcx| ((mcx.def_id, mcx.substs), mcx.mir)));
+    let entry_points: Vec<_> = mcxs
+        .iter()
+        .map(|mcx| (mcx.def_id, mcx.substs))
+        .filter(|&(def_id, _)| is_entry_point(tcx, def_id))
+        .collect();
+    let paths = call_graph.paths_from(&entry_points);
+    let mut reported = HashSet::new();
+    for mcx in mcxs {
+        let path = match paths.get(&(mcx.def_id, mcx.substs)) {
+            Some(path) if !intrinsic_fns.contains_key(&mcx.def_id) => path,
+            _ => continue,
+        };
+        let path = if path.len() > 1 {
+            let names: Vec<_> = path.iter().map(|&(def_id, _)| tcx.item_path_str(def_id)).collect();
+            Some(names.join(" -> "))
+        } else {
+            None
+        };
+        let mut validator = Validator {
+            mcx,
+            path,
+            reported: &mut reported,
+        };
+        validator.visit_mir(mcx.mir());
+        validator.check_returned_reference();
+    }
+    has_ty_error || tcx.sess.err_count() > errors
+}
+
+struct TyErrorFinder {
+    has_error: bool,
+}
+
+impl<'tcx> Visitor<'tcx> for TyErrorFinder {
+    fn visit_ty(&mut self, ty: &ty::Ty<'tcx>, _: TyContext) {
+        self.super_ty(ty);
+        if let TypeVariants::TyError = ty.sty {
+            self.has_error = true;
+        }
+    }
+}
+
+struct Validator<'r, 'b, 'a: 'b, 'tcx: 'a> {
+    mcx: &'b MirContext<'a, 'tcx>,
+    /// The call path from the entry point, eg `fragment -> shade -> fresnel`
+    path: Option<String>,
+    /// Generic functions are visited once per instance, but every error
+    /// should only be reported once.
+    reported: &'r mut HashSet<(Span, &'static str)>,
+}
+
+impl<'r, 'b, 'a, 'tcx> Validator<'r, 'b, 'a, 'tcx> {
+    fn report(&mut self, span: Span, feature: &'static str, help: &'static str) {
+        if !self.reported.insert((span, feature)) {
+            return;
+        }
+        let mut err = struct_span_err!(
+            self.mcx.tcx.sess,
+            span,
+            E1337,
+            "{} is not supported by rlsl",
+            feature
+        );
+        err.help(help);
+        if let Some(ref path) = self.path {
+            err.note(&format!("called from `{}`", path));
+        }
+        err.emit();
+    }
+
+    /// Functions that return a reference are inlined into their callers,
+    /// because a pointer can not be returned in SPIR-V.
+    /// Recursion is reported by `has_recursion`.
+    fn check_returned_reference(&mut self) {
+        let tcx = self.mcx.tcx;
+        let def_id = self.mcx.def_id;
+        if !::is_ptr(self.mcx.mir().return_ty()) {
+            return;
+        }
+        if attr::find_inline_attr(None, &tcx.get_attrs(def_id)) == InlineAttr::Never {
+            self.report(
+                tcx.def_span(def_id),
+                "A function that returns a reference and can not be inlined",
+                "Return the value instead, or remove `#[inline(never)]`",
+            );
+        }
+    }
+
+    fn check_ty(&mut self, span: Span, ty: ty::Ty<'tcx>, visited: &mut HashSet<ty::Ty<'tcx>>) {
+        if !visited.insert(ty) || ty.is_phantom_data() {
+            return;
+        }
+        if ty.is_box() {
+            return self.report(
+                span,
+                "Heap allocation",
+                "Values can only live on the stack in a shader",
+            );
+        }
+        match ty.sty {
+            TypeVariants::TyFloat(FloatTy::F64) => self.report(span, "`f64`", "Use `f32` instead"),
+            TypeVariants::TyDynamic(..) => {
+                self.report(span, "`dyn Trait`", "Use generics instead of trait objects")
+            }
+            TypeVariants::TySlice(_) | TypeVariants::TyStr => {
+                self.report(span, "A slice", "Use an array with a fixed length")
+            }
+            TypeVariants::TyRawPtr(_) => {
+                self.report(span, "A raw pointer", "Use a reference instead")
+            }
+            TypeVariants::TyRef(_, ty, _) | TypeVariants::TyArray(ty, _) => {
+                self.check_ty(span, ty, visited)
+            }
+            TypeVariants::TyTuple(tys) => {
+                for ty in tys {
+                    self.check_ty(span, ty, visited);
+                }
+            }
+            TypeVariants::TyAdt(adt, substs) => {
+                for field in adt.all_fields() {
+                    self.check_ty(span, field.ty(self.mcx.tcx, substs), visited);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+impl<'r, 'b, 'a, 'tcx> Visitor<'tcx> for Validator<'r, 'b, 'a, 'tcx> {
+    fn visit_ty(&mut self, ty: &ty::Ty<'tcx>, ty_context: TyContext) {
+        self.super_ty(ty);
+        let span = match ty_context {
+            TyContext::LocalDecl { source_info, .. }
+            | TyContext::ReturnTy(source_info)
+            | TyContext::YieldTy(source_info) => source_info.span,
+            TyContext::Location(location) => self.mcx.mir().source_info(location).span,
+        };
+        let ty = self.mcx.monomorphize(ty);
+        self.check_ty(span, ty, &mut HashSet::new());
+    }
+
+    fn visit_rvalue(&mut self, rvalue: &mir::Rvalue<'tcx>, location: mir::Location) {
+        self.super_rvalue(rvalue, location);
+        let span = self.mcx.mir().source_info(location).span;
+        match *rvalue {
+            mir::Rvalue::Cast(..) => self.report(span, "A cast", "Use values of the same type"),
+            mir::Rvalue::BinaryOp(mir::BinOp::Offset, ..) => self.report(
+                span,
+                "Pointer arithmetic",
+                "Index into an array instead",
+            ),
+            mir::Rvalue::NullaryOp(mir::NullOp::Box, _) => self.report(
+                span,
+                "Heap allocation",
+                "Values can only live on the stack in a shader",
+            ),
+            mir::Rvalue::Len(_) => self.report(span, "A slice", "Use an array with a fixed length"),
+            _ => (),
+        }
+    }
+
+    fn visit_terminator_kind(
+        &mut self,
+        block: mir::BasicBlock,
+        kind: &mir::TerminatorKind<'tcx>,
+        location: mir::Location,
+    ) {
+        self.super_terminator_kind(block, kind, location);
+        if let mir::TerminatorKind::Call { ref func, .. } = *kind {
+            let fn_ty = func.ty(self.mcx.mir(), self.mcx.tcx);
+            if resolve_fn(self.mcx.tcx, fn_ty).is_none() {
+                let span = self.mcx.mir().source_info(location).span;
+                self.report(
+                    span,
+                    "Calling a function pointer",
+                    "Call the function directly or use generics",
+                );
+            }
+        }
+    }
+}
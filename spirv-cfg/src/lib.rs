@@ -1,5 +1,7 @@
+extern crate rlsl_reflect;
 extern crate rspirv;
 extern crate spirv_headers as spirv;
+use rlsl_reflect::Reflection;
 use rspirv::binary::{Disassemble};
 use rspirv::mr::{BasicBlock, Function, Module, Operand};
 use std::collections::{BTreeMap, HashSet};
@@ -29,6 +31,9 @@ impl SpirvModule {
         let return_id = label.result_id?;
         self.names.get(&return_id).map(String::as_str)
     }
+    pub fn reflect(&self) -> Reflection {
+        Reflection::from_module(&self.module)
+    }
     pub fn load<P: AsRef<Path>>(p: &P) -> Self {
         fn inner(p: &Path) -> SpirvModule {
             use rspirv::binary::Parser;
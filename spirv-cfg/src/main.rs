@@ -13,10 +13,18 @@ fn main() {
                 .help("Path to the .spv file")
                 .required(true)
                 .takes_value(true),
+        ).arg(
+            Arg::with_name("reflect")
+                .long("reflect")
+                .help("Prints the reflection of the module as json"),
         ).get_matches();
     let file_path = matches.value_of("file").expect("No filename");
     let file_path = PathBuf::from(file_path);
     let module = SpirvModule::load(&file_path);
+    if matches.is_present("reflect") {
+        println!("{}", module.reflect().to_json());
+        return;
+    }
     export_spirv_cfg(&module);
     //println!("{:#?}", module.names);
 }